@@ -0,0 +1 @@
+alter table "user" add column if not exists avatar_url varchar(255);
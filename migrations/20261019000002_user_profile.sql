@@ -0,0 +1,4 @@
+alter table "user" add column if not exists bio varchar(512);
+alter table "user" add column if not exists locale varchar(35);
+alter table "user" add column if not exists timezone varchar(64);
+alter table "user" add column if not exists preferences jsonb;
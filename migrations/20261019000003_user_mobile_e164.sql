@@ -0,0 +1,7 @@
+alter table "user" add column if not exists mobile_country varchar(2);
+
+-- the mobiles stored before are all mainland-China numbers
+update "user"
+set mobile = '+86' || regexp_replace(mobile, '^0', ''),
+    mobile_country = 'CN'
+where mobile is not null and mobile not like '+%';
@@ -0,0 +1,9 @@
+create table if not exists user_pwd_history (
+    id bigserial primary key,
+    user_id bigint not null references "user" (id),
+    salt varchar(255) not null,
+    pwd varchar(255) not null,
+    created_at timestamptz not null
+);
+
+create index if not exists user_pwd_history_user_id_created_at_idx on user_pwd_history (user_id, created_at desc);
@@ -0,0 +1,21 @@
+create type email_outbox_status as enum ('Pending', 'Sent', 'Dead');
+
+-- the emails to deliver by the outbox worker, a failed delivery is retried with exponential backoff
+-- until it turns dead
+create table if not exists email_outbox (
+    id bigserial primary key,
+    recipient varchar(255) not null,
+    subject varchar(255) not null,
+    text_body text not null,
+    html_body text not null,
+    status email_outbox_status not null default 'Pending',
+    attempts int not null default 0,
+    next_attempt_at timestamptz not null,
+    last_error text,
+    created_at timestamptz not null,
+    updated_at timestamptz,
+    sent_at timestamptz
+);
+
+create index if not exists email_outbox_pending_idx on email_outbox (next_attempt_at) where status = 'Pending';
+create index if not exists email_outbox_status_idx on email_outbox (status, created_at desc);
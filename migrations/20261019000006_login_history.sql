@@ -0,0 +1,16 @@
+-- every login attempt, user_id is null when the email is not registered
+create table if not exists login_history (
+    id bigserial primary key,
+    user_id bigint references "user" (id),
+    email varchar(255) not null,
+    ip varchar(45),
+    -- /24 of ipv4 or /48 of ipv6
+    ip_range varchar(64),
+    user_agent varchar(512),
+    fingerprint varchar(64) not null,
+    success boolean not null,
+    reason varchar(255),
+    created_at timestamptz not null
+);
+
+create index if not exists login_history_user_id_created_at_idx on login_history (user_id, created_at desc);
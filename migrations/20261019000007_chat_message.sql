@@ -0,0 +1,13 @@
+-- the messages of the chat rooms, user_id is null when the user has been deleted
+create table if not exists chat_message (
+    id bigserial primary key,
+    room varchar(255) not null,
+    user_id bigint references "user" (id) on delete set null,
+    -- the session id and the name of the sender when it was sent
+    from_id varchar(255) not null,
+    from_name varchar(255) not null,
+    content text not null,
+    created_at timestamptz not null
+);
+
+create index if not exists chat_message_room_id_idx on chat_message (room, id desc);
@@ -0,0 +1,15 @@
+-- the direct messages between two users, read_at is null until the recipient has read it
+create table if not exists direct_message (
+    id bigserial primary key,
+    from_user_id bigint not null references "user" (id),
+    -- the name of the sender when it was sent
+    from_name varchar(255) not null,
+    to_user_id bigint not null references "user" (id),
+    content text not null,
+    created_at timestamptz not null,
+    read_at timestamptz
+);
+
+create index if not exists direct_message_from_to_idx on direct_message (from_user_id, to_user_id, id desc);
+create index if not exists direct_message_to_from_idx on direct_message (to_user_id, from_user_id, id desc);
+create index if not exists direct_message_unread_idx on direct_message (to_user_id, from_user_id) where read_at is null;
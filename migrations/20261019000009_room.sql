@@ -0,0 +1,52 @@
+create type room_visibility as enum ('Public', 'InviteOnly', 'Private');
+
+-- the order is the rank, a member moderates the members of a lower role
+create type room_role as enum ('Member', 'Moderator', 'Owner');
+
+-- the chat rooms, the id is the name the sessions join, owner_id is null for the rooms of the system
+create table if not exists room (
+    id varchar(255) primary key,
+    owner_id bigint references "user" (id) on delete set null,
+    title varchar(255) not null,
+    topic text,
+    visibility room_visibility not null default 'Public',
+    created_at timestamptz not null,
+    updated_at timestamptz
+);
+
+-- muted_until is null when the member is not muted
+create table if not exists room_member (
+    room varchar(255) not null references room (id) on delete cascade,
+    user_id bigint not null references "user" (id) on delete cascade,
+    "role" room_role not null default 'Member',
+    muted_until timestamptz,
+    created_at timestamptz not null,
+    primary key (room, user_id)
+);
+
+create index if not exists room_member_user_id_idx on room_member (user_id);
+
+create table if not exists room_ban (
+    room varchar(255) not null references room (id) on delete cascade,
+    user_id bigint not null references "user" (id) on delete cascade,
+    banned_by bigint references "user" (id) on delete set null,
+    created_at timestamptz not null,
+    primary key (room, user_id)
+);
+
+-- an invitation is taken when the user joins
+create table if not exists room_invitation (
+    room varchar(255) not null references room (id) on delete cascade,
+    user_id bigint not null references "user" (id) on delete cascade,
+    invited_by bigint references "user" (id) on delete set null,
+    created_at timestamptz not null,
+    primary key (room, user_id)
+);
+
+-- the default room, and the rooms which have a history, stay open to everyone
+insert into room (id, title, visibility, created_at) values ('main', 'main', 'Public', now())
+on conflict do nothing;
+
+insert into room (id, title, visibility, created_at)
+select room, room, 'Public', min(created_at) from chat_message group by room
+on conflict do nothing;
@@ -0,0 +1,8 @@
+-- how far the user has read the room, the unread messages are the ones after last_read_id
+create table if not exists room_read (
+    room varchar(255) not null references room (id) on delete cascade,
+    user_id bigint not null references "user" (id) on delete cascade,
+    last_read_id bigint not null,
+    updated_at timestamptz not null,
+    primary key (room, user_id)
+);
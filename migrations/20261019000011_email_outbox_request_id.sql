@@ -0,0 +1,2 @@
+-- the request which has queued the email, sent along in its X-Request-Id header
+alter table email_outbox add column if not exists request_id varchar(128);
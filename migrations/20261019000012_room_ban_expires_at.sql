@@ -0,0 +1,2 @@
+-- a kick keeps the user out of the room until expires_at, a ban is null until it is lifted
+alter table room_ban add column if not exists expires_at timestamptz;
@@ -0,0 +1,121 @@
+use crate::model::chat as chat_model;
+use crate::service::chat as chat_service;
+use crate::service::room as room_service;
+use crate::session;
+use actix_web::web::{Json, Path, Query};
+use actix_web::{get, put, HttpRequest, Responder, Result};
+use util_response::{data, msg, prelude::*};
+
+#[utoipa::path(
+    path = "/api/chat/rooms/{room}/messages",
+    params(
+        ("room", description = "id of the room"),
+        chat_model::HistoryQuery
+    ),
+    responses(
+        (status = 200, description = "successfully", body = ChatHistoryResponse),
+        (status = 400, description = "bad request", body = MsgResponseWithErrCode),
+        (status = 401, description = "unthorized", body = MsgResponseWithErrCode),
+        (status = 500, description = "internal server error", body = MsgResponseWithErrCode)
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+#[get("/rooms/{room}/messages")]
+pub async fn history(
+    req: HttpRequest,
+    room: Path<String>,
+    query: Query<chat_model::HistoryQuery>,
+) -> Result<impl Responder> {
+    let user = session::get_current_user(&req).await?;
+    room_service::authorize_read(&room, user.id).await?;
+    let res = chat_service::history(&room, query.before, query.limit).await?;
+    Ok(Json(data!(res)))
+}
+
+#[utoipa::path(
+    path = "/api/chat/direct",
+    responses(
+        (status = 200, description = "successfully", body = ConversationListResponse),
+        (status = 401, description = "unthorized", body = MsgResponseWithErrCode),
+        (status = 500, description = "internal server error", body = MsgResponseWithErrCode)
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+#[get("/direct")]
+pub async fn conversations(req: HttpRequest) -> Result<impl Responder> {
+    let user = session::get_current_user(&req).await?;
+    let res = chat_service::conversations(user.id).await?;
+    Ok(Json(data!(res)))
+}
+
+#[utoipa::path(
+    path = "/api/chat/direct/{user_id}/messages",
+    params(
+        ("user_id", description = "id of the other user"),
+        chat_model::HistoryQuery
+    ),
+    responses(
+        (status = 200, description = "successfully", body = DirectHistoryResponse),
+        (status = 400, description = "bad request", body = MsgResponseWithErrCode),
+        (status = 401, description = "unthorized", body = MsgResponseWithErrCode),
+        (status = 500, description = "internal server error", body = MsgResponseWithErrCode)
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+#[get("/direct/{user_id}/messages")]
+pub async fn direct_history(
+    req: HttpRequest,
+    user_id: Path<i64>,
+    query: Query<chat_model::HistoryQuery>,
+) -> Result<impl Responder> {
+    let user = session::get_current_user(&req).await?;
+    let res =
+        chat_service::direct_history(user.id, user_id.into_inner(), query.before, query.limit)
+            .await?;
+    Ok(Json(data!(res)))
+}
+
+#[utoipa::path(
+    path = "/api/chat/direct/{user_id}/read",
+    params(
+        ("user_id", description = "id of the other user")
+    ),
+    responses(
+        (status = 200, description = "successfully", body = MsgResponse),
+        (status = 401, description = "unthorized", body = MsgResponseWithErrCode),
+        (status = 500, description = "internal server error", body = MsgResponseWithErrCode)
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+#[put("/direct/{user_id}/read")]
+pub async fn read_direct(req: HttpRequest, user_id: Path<i64>) -> Result<impl Responder> {
+    let user = session::get_current_user(&req).await?;
+    chat_service::mark_read(user.id, user_id.into_inner()).await?;
+    Ok(Json(msg!("ok")))
+}
+
+#[utoipa::path(
+    path = "/api/chat/unread",
+    responses(
+        (status = 200, description = "successfully", body = RoomUnreadListResponse),
+        (status = 401, description = "unthorized", body = MsgResponseWithErrCode),
+        (status = 500, description = "internal server error", body = MsgResponseWithErrCode)
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+#[get("/unread")]
+pub async fn unread(req: HttpRequest) -> Result<impl Responder> {
+    let user = session::get_current_user(&req).await?;
+    let res = chat_service::unread_rooms(user.id).await?;
+    Ok(Json(data!(res)))
+}
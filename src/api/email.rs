@@ -0,0 +1,51 @@
+use crate::service::email as email_service;
+use crate::session;
+use actix_web::web::{Json, Path, Query};
+use actix_web::{get, put, HttpRequest, Responder, Result};
+use util_response::{data, msg, prelude::*};
+
+#[utoipa::path(
+    path = "/api/admin/email/failed",
+    params(
+        Pagination
+    ),
+    responses(
+        (status = 200, description = "successfully", body = OutboxMailListResponse),
+        (status = 400, description = "bad request", body = MsgResponseWithErrCode),
+        (status = 401, description = "unthorized", body = MsgResponseWithErrCode),
+        (status = 403, description = "forbidden", body = MsgResponseWithErrCode),
+        (status = 500, description = "internal server error", body = MsgResponseWithErrCode)
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+#[get("/email/failed")]
+pub async fn failed(req: HttpRequest, page: Query<Pagination>) -> Result<impl Responder> {
+    session::get_current_admin(&req).await?;
+    let (data, total) = email_service::failed(&page).await?;
+    Ok(Json(data!(data, total)))
+}
+
+#[utoipa::path(
+    path = "/api/admin/email/retry/{id}",
+    params(
+        ("id", description = "id of the failed email")
+    ),
+    responses(
+        (status = 200, description = "successfully", body = MsgResponse),
+        (status = 400, description = "bad request", body = MsgResponseWithErrCode),
+        (status = 401, description = "unthorized", body = MsgResponseWithErrCode),
+        (status = 403, description = "forbidden", body = MsgResponseWithErrCode),
+        (status = 500, description = "internal server error", body = MsgResponseWithErrCode)
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+#[put("/email/retry/{id}")]
+pub async fn retry(req: HttpRequest, id: Path<i64>) -> Result<impl Responder> {
+    session::get_current_admin(&req).await?;
+    email_service::retry(id.into_inner()).await?;
+    Ok(Json(msg!("ok")))
+}
@@ -1,11 +1,15 @@
+pub mod chat;
+pub mod email;
+pub mod room;
 pub mod user;
 
 #[macro_export]
 macro_rules! serve_api {
     ($app: expr) => {
-        use crate::api::user;
+        use crate::api::{chat, email, room, user};
         $app = $app.service(
             scope("/api")
+                .wrap(middleware::cors::cors(|x| &x.api))
                 .service(ping)
                 .service(user::login)
                 .service(user::register)
@@ -13,14 +17,45 @@ macro_rules! serve_api {
                 .service(user::validate_exist_email)
                 .service(user::validate_not_exist_email)
                 .service(user::change_pwd)
+                .service(user::send_reset_pwd_link)
+                .service(user::reset_pwd)
                 .service(
                     scope("/user")
                         .wrap(middleware::auth::Auth)
                         .service(user::search)
                         .service(user::update)
                         .service(user::get_current_user)
+                        .service(user::get_logins)
                         .service(user::delete)
                         .service(user::get),
+                )
+                .service(
+                    scope("/chat")
+                        .wrap(middleware::auth::Auth)
+                        .service(chat::history)
+                        .service(room::create)
+                        .service(room::query)
+                        .service(room::get)
+                        .service(room::update)
+                        .service(room::members)
+                        .service(room::set_role)
+                        .service(room::invite)
+                        .service(room::kick)
+                        .service(room::ban)
+                        .service(room::unban)
+                        .service(room::mute)
+                        .service(room::invitations)
+                        .service(room::accept)
+                        .service(chat::conversations)
+                        .service(chat::direct_history)
+                        .service(chat::read_direct)
+                        .service(chat::unread),
+                )
+                .service(
+                    scope("/admin")
+                        .wrap(middleware::auth::Auth)
+                        .service(email::failed)
+                        .service(email::retry),
                 ),
         );
     };
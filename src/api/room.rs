@@ -0,0 +1,360 @@
+use crate::model::room as room_model;
+use crate::service::room as room_service;
+use crate::session;
+#[cfg(feature = "ws")]
+use crate::ws::{
+    protocol::{RemovedReason, ServerMessage},
+    server::ChatServerHandle,
+};
+use actix_web::web::{Json, Path, Query};
+use actix_web::{delete, get, post, put, route, HttpRequest, Responder, Result};
+use util_response::{data, msg, prelude::*};
+
+/// push to the sessions of the user on any node, when this node runs the ws server
+#[cfg(feature = "ws")]
+async fn notify(req: &HttpRequest, user_id: i64, msg: ServerMessage) {
+    if let Some(chat_server) = req.app_data::<actix_web::web::Data<ChatServerHandle>>() {
+        chat_server.send_direct(user_id, None, msg.to_hub()).await;
+    }
+}
+
+#[utoipa::path(
+    request_body = CreateRoomReq,
+    path = "/api/chat/rooms",
+    responses(
+        (status = 200, description = "successfully", body = RoomResponse),
+        (status = 400, description = "bad request", body = MsgResponseWithErrCode),
+        (status = 401, description = "unthorized", body = MsgResponseWithErrCode),
+        (status = 500, description = "internal server error", body = MsgResponseWithErrCode)
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+#[post("/rooms")]
+pub async fn create(
+    http_req: HttpRequest,
+    req: Json<room_model::CreateRoomReq>,
+) -> Result<impl Responder> {
+    let user = session::get_current_user(&http_req).await?;
+    let res = room_service::create(user.id, &req).await?;
+    Ok(Json(data!(res)))
+}
+
+#[utoipa::path(
+    path = "/api/chat/rooms",
+    params(
+        Pagination
+    ),
+    responses(
+        (status = 200, description = "successfully", body = RoomListResponse),
+        (status = 400, description = "bad request", body = MsgResponseWithErrCode),
+        (status = 401, description = "unthorized", body = MsgResponseWithErrCode),
+        (status = 500, description = "internal server error", body = MsgResponseWithErrCode)
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+#[get("/rooms")]
+pub async fn query(req: HttpRequest, page: Query<Pagination>) -> Result<impl Responder> {
+    let user = session::get_current_user(&req).await?;
+    let (data, total) = room_service::query(user.id, &page).await?;
+    Ok(Json(data!(data, total)))
+}
+
+#[utoipa::path(
+    path = "/api/chat/rooms/{room}",
+    params(
+        ("room", description = "id of the room")
+    ),
+    responses(
+        (status = 200, description = "successfully", body = RoomResponse),
+        (status = 400, description = "bad request", body = MsgResponseWithErrCode),
+        (status = 401, description = "unthorized", body = MsgResponseWithErrCode),
+        (status = 500, description = "internal server error", body = MsgResponseWithErrCode)
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+#[get("/rooms/{room}")]
+pub async fn get(req: HttpRequest, room: Path<String>) -> Result<impl Responder> {
+    let user = session::get_current_user(&req).await?;
+    let res = room_service::get(&room, user.id).await?;
+    Ok(Json(data!(res)))
+}
+
+#[utoipa::path(
+    patch,
+    request_body = UpdateRoomReq,
+    path = "/api/chat/rooms/{room}",
+    params(
+        ("room", description = "id of the room")
+    ),
+    responses(
+        (status = 200, description = "successfully", body = RoomResponse),
+        (status = 400, description = "bad request", body = MsgResponseWithErrCode),
+        (status = 401, description = "unthorized", body = MsgResponseWithErrCode),
+        (status = 500, description = "internal server error", body = MsgResponseWithErrCode)
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+#[route("/rooms/{room}", method = "PATCH", method = "PUT")]
+pub async fn update(
+    http_req: HttpRequest,
+    room: Path<String>,
+    req: Json<room_model::UpdateRoomReq>,
+) -> Result<impl Responder> {
+    let user = session::get_current_user(&http_req).await?;
+    let res = room_service::update(&room, user.id, &req).await?;
+    Ok(Json(data!(res)))
+}
+
+#[utoipa::path(
+    path = "/api/chat/rooms/{room}/members",
+    params(
+        ("room", description = "id of the room")
+    ),
+    responses(
+        (status = 200, description = "successfully", body = RoomMemberListResponse),
+        (status = 400, description = "bad request", body = MsgResponseWithErrCode),
+        (status = 401, description = "unthorized", body = MsgResponseWithErrCode),
+        (status = 500, description = "internal server error", body = MsgResponseWithErrCode)
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+#[get("/rooms/{room}/members")]
+pub async fn members(req: HttpRequest, room: Path<String>) -> Result<impl Responder> {
+    let user = session::get_current_user(&req).await?;
+    let res = room_service::members(&room, user.id).await?;
+    Ok(Json(data!(res)))
+}
+
+#[utoipa::path(
+    request_body = SetRoomRoleReq,
+    path = "/api/chat/rooms/{room}/members/{user_id}/role",
+    params(
+        ("room", description = "id of the room"),
+        ("user_id", description = "id of the member")
+    ),
+    responses(
+        (status = 200, description = "successfully", body = MsgResponse),
+        (status = 400, description = "bad request", body = MsgResponseWithErrCode),
+        (status = 401, description = "unthorized", body = MsgResponseWithErrCode),
+        (status = 500, description = "internal server error", body = MsgResponseWithErrCode)
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+#[put("/rooms/{room}/members/{user_id}/role")]
+pub async fn set_role(
+    http_req: HttpRequest,
+    path: Path<(String, i64)>,
+    req: Json<room_model::SetRoomRoleReq>,
+) -> Result<impl Responder> {
+    let user = session::get_current_user(&http_req).await?;
+    let (room, user_id) = path.into_inner();
+    room_service::set_role(&room, user.id, user_id, req.role).await?;
+    Ok(Json(msg!("ok")))
+}
+
+#[utoipa::path(
+    path = "/api/chat/rooms/{room}/invitations/{user_id}",
+    params(
+        ("room", description = "id of the room"),
+        ("user_id", description = "id of the invited user")
+    ),
+    responses(
+        (status = 200, description = "successfully", body = MsgResponse),
+        (status = 400, description = "bad request", body = MsgResponseWithErrCode),
+        (status = 401, description = "unthorized", body = MsgResponseWithErrCode),
+        (status = 500, description = "internal server error", body = MsgResponseWithErrCode)
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+#[post("/rooms/{room}/invitations/{user_id}")]
+pub async fn invite(req: HttpRequest, path: Path<(String, i64)>) -> Result<impl Responder> {
+    let user = session::get_current_user(&req).await?;
+    let (room, user_id) = path.into_inner();
+    room_service::invite(&room, user.id, user_id).await?;
+    Ok(Json(msg!("ok")))
+}
+
+#[utoipa::path(
+    path = "/api/chat/rooms/{room}/members/{user_id}/kick",
+    params(
+        ("room", description = "id of the room"),
+        ("user_id", description = "id of the member")
+    ),
+    responses(
+        (status = 200, description = "successfully", body = MsgResponse),
+        (status = 400, description = "bad request", body = MsgResponseWithErrCode),
+        (status = 401, description = "unthorized", body = MsgResponseWithErrCode),
+        (status = 500, description = "internal server error", body = MsgResponseWithErrCode)
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+#[post("/rooms/{room}/members/{user_id}/kick")]
+pub async fn kick(req: HttpRequest, path: Path<(String, i64)>) -> Result<impl Responder> {
+    let user = session::get_current_user(&req).await?;
+    let (room, user_id) = path.into_inner();
+    room_service::kick(&room, user.id, user_id).await?;
+    #[cfg(feature = "ws")]
+    notify(
+        &req,
+        user_id,
+        ServerMessage::Removed {
+            room,
+            reason: RemovedReason::Kicked,
+        },
+    )
+    .await;
+    Ok(Json(msg!("ok")))
+}
+
+#[utoipa::path(
+    path = "/api/chat/rooms/{room}/bans/{user_id}",
+    params(
+        ("room", description = "id of the room"),
+        ("user_id", description = "id of the user")
+    ),
+    responses(
+        (status = 200, description = "successfully", body = MsgResponse),
+        (status = 400, description = "bad request", body = MsgResponseWithErrCode),
+        (status = 401, description = "unthorized", body = MsgResponseWithErrCode),
+        (status = 500, description = "internal server error", body = MsgResponseWithErrCode)
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+#[post("/rooms/{room}/bans/{user_id}")]
+pub async fn ban(req: HttpRequest, path: Path<(String, i64)>) -> Result<impl Responder> {
+    let user = session::get_current_user(&req).await?;
+    let (room, user_id) = path.into_inner();
+    room_service::ban(&room, user.id, user_id).await?;
+    #[cfg(feature = "ws")]
+    notify(
+        &req,
+        user_id,
+        ServerMessage::Removed {
+            room,
+            reason: RemovedReason::Banned,
+        },
+    )
+    .await;
+    Ok(Json(msg!("ok")))
+}
+
+#[utoipa::path(
+    path = "/api/chat/rooms/{room}/bans/{user_id}",
+    params(
+        ("room", description = "id of the room"),
+        ("user_id", description = "id of the user")
+    ),
+    responses(
+        (status = 200, description = "successfully", body = MsgResponse),
+        (status = 400, description = "bad request", body = MsgResponseWithErrCode),
+        (status = 401, description = "unthorized", body = MsgResponseWithErrCode),
+        (status = 500, description = "internal server error", body = MsgResponseWithErrCode)
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+#[delete("/rooms/{room}/bans/{user_id}")]
+pub async fn unban(req: HttpRequest, path: Path<(String, i64)>) -> Result<impl Responder> {
+    let user = session::get_current_user(&req).await?;
+    let (room, user_id) = path.into_inner();
+    room_service::unban(&room, user.id, user_id).await?;
+    Ok(Json(msg!("ok")))
+}
+
+#[utoipa::path(
+    request_body = MuteReq,
+    path = "/api/chat/rooms/{room}/members/{user_id}/mute",
+    params(
+        ("room", description = "id of the room"),
+        ("user_id", description = "id of the member")
+    ),
+    responses(
+        (status = 200, description = "successfully", body = MuteResponse),
+        (status = 400, description = "bad request", body = MsgResponseWithErrCode),
+        (status = 401, description = "unthorized", body = MsgResponseWithErrCode),
+        (status = 500, description = "internal server error", body = MsgResponseWithErrCode)
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+#[put("/rooms/{room}/members/{user_id}/mute")]
+pub async fn mute(
+    http_req: HttpRequest,
+    path: Path<(String, i64)>,
+    req: Json<room_model::MuteReq>,
+) -> Result<impl Responder> {
+    let user = session::get_current_user(&http_req).await?;
+    let (room, user_id) = path.into_inner();
+    let until = room_service::mute(&room, user.id, user_id, req.seconds).await?;
+    #[cfg(feature = "ws")]
+    notify(
+        &http_req,
+        user_id,
+        ServerMessage::Muted {
+            room,
+            until: until.clone(),
+        },
+    )
+    .await;
+    Ok(Json(data!(until)))
+}
+
+#[utoipa::path(
+    path = "/api/chat/invitations",
+    responses(
+        (status = 200, description = "successfully", body = RoomInvitationListResponse),
+        (status = 401, description = "unthorized", body = MsgResponseWithErrCode),
+        (status = 500, description = "internal server error", body = MsgResponseWithErrCode)
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+#[get("/invitations")]
+pub async fn invitations(req: HttpRequest) -> Result<impl Responder> {
+    let user = session::get_current_user(&req).await?;
+    let res = room_service::invitations(user.id).await?;
+    Ok(Json(data!(res)))
+}
+
+#[utoipa::path(
+    path = "/api/chat/invitations/{room}/accept",
+    params(
+        ("room", description = "id of the room")
+    ),
+    responses(
+        (status = 200, description = "successfully", body = MsgResponse),
+        (status = 400, description = "bad request", body = MsgResponseWithErrCode),
+        (status = 401, description = "unthorized", body = MsgResponseWithErrCode),
+        (status = 500, description = "internal server error", body = MsgResponseWithErrCode)
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+#[post("/invitations/{room}/accept")]
+pub async fn accept(req: HttpRequest, room: Path<String>) -> Result<impl Responder> {
+    let user = session::get_current_user(&req).await?;
+    room_service::accept(&room, user.id).await?;
+    Ok(Json(msg!("ok")))
+}
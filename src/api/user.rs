@@ -1,7 +1,8 @@
 use crate::model::user as user_model;
+use crate::service::login_history as login_history_service;
 use crate::service::user as user_service;
 use actix_web::web::{Json, Path, Query};
-use actix_web::{delete, get, post, put, HttpRequest, Responder, Result};
+use actix_web::{delete, get, post, put, route, HttpRequest, Responder, Result};
 use serde::Deserialize;
 use util_response::{data, msg, prelude::*};
 
@@ -18,8 +19,12 @@ use crate::session;
     )
 )]
 #[post("/login")]
-pub async fn login(req: Json<user_model::LoginReq>) -> Result<impl Responder> {
-    let res = user_service::login(&req.email, &req.pwd).await?;
+pub async fn login(
+    http_req: HttpRequest,
+    req: Json<user_model::LoginReq>,
+) -> Result<impl Responder> {
+    let client = session::get_login_client(&http_req);
+    let res = user_service::login(&req.email, &req.pwd, &client).await?;
     Ok(Json(data!(res)))
 }
 
@@ -39,6 +44,38 @@ pub async fn change_pwd(req: Json<user_model::ChangePasswordReq>) -> Result<impl
     Ok(Json(msg!("ok")))
 }
 
+#[utoipa::path(
+    request_body = SendResetPasswordLinkReq,
+    path = "/api/send_reset_pwd_link",
+    responses(
+        (status = 200, description = "successfully", body = MsgResponse),
+        (status = 400, description = "bad request", body = MsgResponseWithErrCode),
+        (status = 500, description = "internal server error", body = MsgResponseWithErrCode)
+    )
+)]
+#[post("/send_reset_pwd_link")]
+pub async fn send_reset_pwd_link(
+    req: Json<user_model::SendResetPasswordLinkReq>,
+) -> Result<impl Responder> {
+    user_service::send_reset_pwd_link(&req.email).await?;
+    Ok(Json(msg!("ok")))
+}
+
+#[utoipa::path(
+    request_body = ResetPasswordReq,
+    path = "/api/reset_pwd",
+    responses(
+        (status = 200, description = "successfully", body = MsgResponse),
+        (status = 400, description = "bad request", body = MsgResponseWithErrCode),
+        (status = 500, description = "internal server error", body = MsgResponseWithErrCode)
+    )
+)]
+#[put("/reset_pwd")]
+pub async fn reset_pwd(req: Json<user_model::ResetPasswordReq>) -> Result<impl Responder> {
+    let _ = user_service::reset_pwd(&req.token, &req.pwd).await?;
+    Ok(Json(msg!("ok")))
+}
+
 #[utoipa::path(
     request_body = SendEmailCodeReq,
     path = "/api/send_email_code",
@@ -50,7 +87,7 @@ pub async fn change_pwd(req: Json<user_model::ChangePasswordReq>) -> Result<impl
 )]
 #[post("/send_email_code")]
 pub async fn send_email_code(req: Json<user_model::SendEmailCodeReq>) -> Result<impl Responder> {
-    let res = user_service::send_email_code(&req.email, &req.from).await?;
+    let res = user_service::send_email_code(&req.email, &req.from, req.locale.as_deref()).await?;
     Ok(Json(data!(res)))
 }
 
@@ -71,6 +108,7 @@ pub async fn register(req: Json<user_model::RegisterReq>) -> Result<impl Respond
         &req.pwd,
         req.name.as_deref(),
         req.mobile.as_deref(),
+        req.mobile_country.as_deref(),
     )
     .await?;
     Ok(Json(msg!("ok")))
@@ -175,6 +213,29 @@ pub async fn get_current_user(req: HttpRequest) -> Result<impl Responder> {
 }
 
 #[utoipa::path(
+    path = "/api/user/me/logins",
+    params(
+        Pagination
+    ),
+    responses(
+        (status = 200, description = "successfully", body = LoginRecordListResponse),
+        (status = 400, description = "bad request", body = MsgResponseWithErrCode),
+        (status = 401, description = "unthorized", body = MsgResponseWithErrCode),
+        (status = 500, description = "internal server error", body = MsgResponseWithErrCode)
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+#[get("/me/logins")]
+pub async fn get_logins(req: HttpRequest, page: Query<Pagination>) -> Result<impl Responder> {
+    let user = session::get_current_user(&req).await?;
+    let (data, total) = login_history_service::query(user.id, &page).await?;
+    Ok(Json(data!(data, total)))
+}
+
+#[utoipa::path(
+    patch,
     request_body = UserUpdateReq,
     path = "/api/user/update",
     responses(
@@ -187,9 +248,13 @@ pub async fn get_current_user(req: HttpRequest) -> Result<impl Responder> {
         ("token" = [])
     )
 )]
-#[put("/update")]
-pub async fn update(req: Json<user_model::UserUpdateReq>) -> Result<impl Responder> {
-    let res = user_service::update(req.id, req.mobile.as_deref(), req.name.as_deref()).await?;
+#[route("/update", method = "PATCH", method = "PUT")]
+pub async fn update(
+    http_req: HttpRequest,
+    req: Json<user_model::UserUpdateReq>,
+) -> Result<impl Responder> {
+    let user = session::get_current_user(&http_req).await?;
+    let res = user_service::update(user.id, req.into_inner()).await?;
     Ok(Json(data!(res)))
 }
 
@@ -1,12 +1,23 @@
 pub mod user;
 
+use crate::metrics;
 use serde::Serialize;
 use std::fmt::Display;
+use std::time::Instant;
+use tracing::{instrument, Instrument};
 use util_error::BasicResult;
 use util_meilisearch::Settings;
 
 pub const USER_LIST_INDEX: &str = "user_list";
 
+/// how long a task took from being sent to being completed
+fn observe(index: &str, operation: &str, start: Instant) {
+    metrics::MEILISEARCH_TASK_DURATION
+        .with_label_values(&[index, operation])
+        .observe(start.elapsed().as_secs_f64());
+}
+
+#[instrument(skip_all, fields(db.system = "meilisearch", db.collection.name = index))]
 pub async fn reload<D>(index: &str, documents: &[D], primary_key: Option<&str>) -> BasicResult<()>
 where
     D: Serialize,
@@ -16,12 +27,15 @@ where
         .delete_all_documents()
         .await?;
 
+    let start = Instant::now();
     util_meilisearch::client()
         .index(index)
         .add_documents(documents, primary_key)
         .await?
         .wait_for_completion(util_meilisearch::client(), None, None)
+        .instrument(tracing::info_span!("wait_for_completion"))
         .await?;
+    observe(index, "add_documents", start);
 
     util_meilisearch::client()
         .index(index)
@@ -31,29 +45,37 @@ where
     Ok(())
 }
 
+#[instrument(skip_all, fields(db.system = "meilisearch", db.collection.name = index))]
 pub async fn update<D>(index: &str, documents: &[D], primary_key: Option<&str>) -> BasicResult<()>
 where
     D: Serialize,
 {
+    let start = Instant::now();
     util_meilisearch::client()
         .index(index)
         .add_or_update(documents, primary_key)
         .await?
         .wait_for_completion(util_meilisearch::client(), None, None)
+        .instrument(tracing::info_span!("wait_for_completion"))
         .await?;
+    observe(index, "add_or_update", start);
     Ok(())
 }
 
+#[instrument(skip_all, fields(db.system = "meilisearch", db.collection.name = index))]
 pub async fn delete<T>(index: &str, ids: &[T]) -> BasicResult<()>
 where
     T: Display + Serialize + std::fmt::Debug,
 {
+    let start = Instant::now();
     util_meilisearch::client()
         .index(index)
         .delete_documents(ids)
         .await?
         .wait_for_completion(util_meilisearch::client(), None, None)
+        .instrument(tracing::info_span!("wait_for_completion"))
         .await?;
+    observe(index, "delete_documents", start);
 
     Ok(())
 }
@@ -1,8 +1,10 @@
 use crate::model::user as user_model;
 use meilisearch_sdk::search::Selectors;
+use tracing::instrument;
 use util_error::BasicResult;
 use util_response::Pagination;
 
+#[instrument(skip_all, fields(db.system = "meilisearch"))]
 pub async fn search(
     key_word: &str,
     page: &Pagination,
@@ -0,0 +1,107 @@
+use crate::model::chat as chat_model;
+use chrono::{DateTime, Utc};
+use tracing::instrument;
+use util_datetime::FormatDateTime;
+use util_postgres::{conn, SqlResult};
+
+#[derive(Debug, Clone)]
+pub struct ChatMessage {
+    pub id: i64,
+    pub room: String,
+    pub user_id: Option<i64>,
+    pub from_id: String,
+    pub from_name: String,
+    pub content: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<ChatMessage> for chat_model::ChatMessage {
+    fn from(x: ChatMessage) -> Self {
+        chat_model::ChatMessage {
+            id: x.id,
+            room: x.room,
+            user_id: x.user_id,
+            from_id: x.from_id,
+            from_name: x.from_name,
+            content: x.content,
+            created_at: x.created_at.to_default(),
+        }
+    }
+}
+
+pub struct NewChatMessage<'a> {
+    pub room: &'a str,
+    pub user_id: i64,
+    pub from_id: &'a str,
+    pub from_name: &'a str,
+    pub content: &'a str,
+}
+
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn insert(x: &NewChatMessage<'_>) -> SqlResult<ChatMessage> {
+    let created_at = chrono::Local::now();
+    sqlx::query_as!(
+        ChatMessage,
+        r#"
+insert into chat_message (room,user_id,from_id,from_name,content,created_at) values ($1,$2,$3,$4,$5,$6)
+RETURNING id,room,user_id,from_id,from_name,content,created_at
+            "#,
+        x.room,
+        x.user_id,
+        x.from_id,
+        x.from_name,
+        x.content,
+        created_at,
+    )
+    .fetch_one(conn().await)
+    .await
+}
+
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn get(id: i64) -> SqlResult<Option<ChatMessage>> {
+    sqlx::query_as!(
+        ChatMessage,
+        r#"
+select
+    id,
+    room,
+    user_id,
+    from_id,
+    from_name,
+    content,
+    created_at
+from chat_message
+where id = $1
+"#,
+        id,
+    )
+    .fetch_optional(conn().await)
+    .await
+}
+
+/// at most `limit` messages of the room older than the message `before`, the latest first
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn query(room: &str, before: Option<i64>, limit: i64) -> SqlResult<Vec<ChatMessage>> {
+    sqlx::query_as!(
+        ChatMessage,
+        r#"
+select
+    id,
+    room,
+    user_id,
+    from_id,
+    from_name,
+    content,
+    created_at
+from chat_message
+where room = $1 and ($2::bigint is null or id < $2)
+order by id desc
+limit $3
+"#,
+        room,
+        before,
+        limit
+    )
+    .fetch_all(conn().await)
+    .await
+}
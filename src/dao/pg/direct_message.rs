@@ -0,0 +1,192 @@
+use crate::model::chat as chat_model;
+use chrono::{DateTime, Utc};
+use tracing::instrument;
+use util_datetime::FormatDateTime;
+use util_postgres::{conn, SqlResult};
+
+#[derive(Debug, Clone)]
+pub struct DirectMessage {
+    pub id: i64,
+    pub from_user_id: i64,
+    pub from_name: String,
+    pub to_user_id: i64,
+    pub content: String,
+    pub created_at: DateTime<Utc>,
+    pub read_at: Option<DateTime<Utc>>,
+}
+
+impl From<DirectMessage> for chat_model::DirectMessage {
+    fn from(x: DirectMessage) -> Self {
+        chat_model::DirectMessage {
+            id: x.id,
+            from_user_id: x.from_user_id,
+            from_name: x.from_name,
+            to_user_id: x.to_user_id,
+            content: x.content,
+            created_at: x.created_at.to_default(),
+            read_at: x.read_at.map(|x| x.to_default()),
+        }
+    }
+}
+
+/// the other user of the conversations of a user, with their last message
+#[derive(Debug, Clone)]
+pub struct Conversation {
+    pub user_id: i64,
+    pub email: String,
+    pub name: Option<String>,
+    pub unread: i64,
+    pub id: i64,
+    pub from_user_id: i64,
+    pub from_name: String,
+    pub to_user_id: i64,
+    pub content: String,
+    pub created_at: DateTime<Utc>,
+    pub read_at: Option<DateTime<Utc>>,
+}
+
+impl From<Conversation> for chat_model::Conversation {
+    fn from(x: Conversation) -> Self {
+        chat_model::Conversation {
+            user_id: x.user_id,
+            name: x.name.unwrap_or(x.email),
+            unread: x.unread,
+            last_message: chat_model::DirectMessage {
+                id: x.id,
+                from_user_id: x.from_user_id,
+                from_name: x.from_name,
+                to_user_id: x.to_user_id,
+                content: x.content,
+                created_at: x.created_at.to_default(),
+                read_at: x.read_at.map(|x| x.to_default()),
+            },
+        }
+    }
+}
+
+pub struct NewDirectMessage<'a> {
+    pub from_user_id: i64,
+    pub from_name: &'a str,
+    pub to_user_id: i64,
+    pub content: &'a str,
+}
+
+/// none when the recipient does not exist or has been deleted
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn insert(x: &NewDirectMessage<'_>) -> SqlResult<Option<DirectMessage>> {
+    let created_at = chrono::Local::now();
+    sqlx::query_as!(
+        DirectMessage,
+        r#"
+insert into direct_message (from_user_id,from_name,to_user_id,content,created_at)
+select $1,$2,$3,$4,$5
+where exists (select 1 from "user" where id = $3 and deleted_at is null)
+RETURNING id,from_user_id,from_name,to_user_id,content,created_at,read_at
+            "#,
+        x.from_user_id,
+        x.from_name,
+        x.to_user_id,
+        x.content,
+        created_at,
+    )
+    .fetch_optional(conn().await)
+    .await
+}
+
+/// at most `limit` messages between the two users older than the message `before`, the latest
+/// first
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn query(
+    user_id: i64,
+    other_user_id: i64,
+    before: Option<i64>,
+    limit: i64,
+) -> SqlResult<Vec<DirectMessage>> {
+    sqlx::query_as!(
+        DirectMessage,
+        r#"
+select
+    id,
+    from_user_id,
+    from_name,
+    to_user_id,
+    content,
+    created_at,
+    read_at
+from direct_message
+where ((from_user_id = $1 and to_user_id = $2) or (from_user_id = $2 and to_user_id = $1))
+    and ($3::bigint is null or id < $3)
+order by id desc
+limit $4
+"#,
+        user_id,
+        other_user_id,
+        before,
+        limit
+    )
+    .fetch_all(conn().await)
+    .await
+}
+
+/// the conversations of the user, the one with the latest message first
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn conversations(user_id: i64) -> SqlResult<Vec<Conversation>> {
+    sqlx::query_as!(
+        Conversation,
+        r#"
+select
+    c.peer as "user_id!",
+    u.email,
+    u."name",
+    (
+        select count(1) from direct_message x
+        where x.to_user_id = $1 and x.from_user_id = c.peer and x.read_at is null
+    ) as "unread!",
+    c.id as "id!",
+    c.from_user_id as "from_user_id!",
+    c.from_name as "from_name!",
+    c.to_user_id as "to_user_id!",
+    c.content as "content!",
+    c.created_at as "created_at!",
+    c.read_at
+from (
+    select distinct on (peer) *
+    from (
+        select
+            case when from_user_id = $1 then to_user_id else from_user_id end as peer,
+            id,
+            from_user_id,
+            from_name,
+            to_user_id,
+            content,
+            created_at,
+            read_at
+        from direct_message
+        where from_user_id = $1 or to_user_id = $1
+    ) m
+    order by peer, id desc
+) c
+join "user" u on u.id = c.peer
+order by c.id desc
+"#,
+        user_id,
+    )
+    .fetch_all(conn().await)
+    .await
+}
+
+/// mark the messages from the other user as read, returns how many there were
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn mark_read(user_id: i64, other_user_id: i64) -> SqlResult<u64> {
+    let res = sqlx::query!(
+        r#"
+update direct_message set read_at = now()
+where to_user_id = $1 and from_user_id = $2 and read_at is null
+"#,
+        user_id,
+        other_user_id,
+    )
+    .execute(conn().await)
+    .await?;
+    Ok(res.rows_affected())
+}
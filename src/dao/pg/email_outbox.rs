@@ -0,0 +1,262 @@
+use crate::model::email::{self as email_model, OutboxStatus};
+use chrono::{DateTime, Utc};
+use tracing::instrument;
+use util_datetime::FormatDateTime;
+use util_postgres::{conn, SqlResult};
+use util_response::Pagination;
+
+#[derive(Debug, Clone)]
+pub struct OutboxMail {
+    pub id: i64,
+    pub recipient: String,
+    pub subject: String,
+    pub text_body: String,
+    pub html_body: String,
+    pub status: OutboxStatus,
+    pub attempts: i32,
+    pub next_attempt_at: DateTime<Utc>,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: Option<DateTime<Utc>>,
+    pub sent_at: Option<DateTime<Utc>>,
+    pub request_id: Option<String>,
+}
+
+impl From<OutboxMail> for email_model::OutboxMail {
+    fn from(x: OutboxMail) -> Self {
+        email_model::OutboxMail {
+            id: x.id,
+            recipient: x.recipient,
+            subject: x.subject,
+            status: x.status,
+            attempts: x.attempts,
+            next_attempt_at: x.next_attempt_at.to_default(),
+            last_error: x.last_error,
+            created_at: x.created_at.to_default(),
+            updated_at: x.updated_at.map(|x| x.to_default()),
+            sent_at: x.sent_at.map(|x| x.to_default()),
+            request_id: x.request_id,
+        }
+    }
+}
+
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn insert(
+    recipient: &str,
+    subject: &str,
+    text_body: &str,
+    html_body: &str,
+    request_id: Option<&str>,
+) -> SqlResult<i64> {
+    let created_at = chrono::Local::now();
+    let res = sqlx::query!(
+        r#"
+insert into email_outbox (recipient,subject,text_body,html_body,next_attempt_at,created_at,request_id) values ($1,$2,$3,$4,$5,$5,$6)
+RETURNING id
+            "#,
+        recipient,
+        subject,
+        text_body,
+        html_body,
+        created_at,
+        request_id,
+    )
+    .fetch_one(conn().await)
+    .await?;
+
+    Ok(res.id)
+}
+
+/// insert the emails at once, the arguments are in the same order
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn insert_many(
+    recipients: &[String],
+    subjects: &[String],
+    text_bodies: &[String],
+    html_bodies: &[String],
+    request_id: Option<&str>,
+) -> SqlResult<u64> {
+    let created_at = chrono::Local::now();
+    let res = sqlx::query!(
+        r#"
+insert into email_outbox (recipient,subject,text_body,html_body,next_attempt_at,created_at,request_id)
+select x.recipient, x.subject, x.text_body, x.html_body, $5, $5, $6
+from unnest($1::text[], $2::text[], $3::text[], $4::text[]) as x(recipient,subject,text_body,html_body)
+            "#,
+        recipients,
+        subjects,
+        text_bodies,
+        html_bodies,
+        created_at,
+        request_id,
+    )
+    .execute(conn().await)
+    .await?;
+
+    Ok(res.rows_affected())
+}
+
+/// take at most `limit` due emails and push their next attempt to `lease_until`
+///
+/// the lease keeps the other workers off the emails while they are being delivered, and lets them
+/// be retried if this worker dies before marking them
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn claim(limit: i64, lease_until: DateTime<Utc>) -> SqlResult<Vec<OutboxMail>> {
+    sqlx::query_as!(
+        OutboxMail,
+        r#"
+update email_outbox set next_attempt_at = $1
+where id in (
+    select id from email_outbox
+    where status = 'Pending' and next_attempt_at <= now()
+    order by next_attempt_at
+    limit $2
+    for update skip locked
+)
+RETURNING
+    id,
+    recipient,
+    subject,
+    text_body,
+    html_body,
+    status as "status!: OutboxStatus",
+    attempts,
+    next_attempt_at,
+    last_error,
+    created_at,
+    updated_at,
+    sent_at,
+    request_id
+            "#,
+        lease_until,
+        limit,
+    )
+    .fetch_all(conn().await)
+    .await
+}
+
+/// the leases below are the `next_attempt_at` the email was claimed with, nothing is updated
+/// when it has been claimed again by another worker since
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn mark_sent(id: i64, lease: DateTime<Utc>) -> SqlResult<u64> {
+    let now = chrono::Local::now();
+    let res = sqlx::query!(
+        r#"
+update email_outbox set
+    status = $1,
+    attempts = attempts + 1,
+    last_error = null,
+    updated_at = $2,
+    sent_at = $2
+where id = $3 and status = 'Pending' and next_attempt_at = $4
+"#,
+        OutboxStatus::Sent as OutboxStatus,
+        now,
+        id,
+        lease,
+    )
+    .execute(conn().await)
+    .await?;
+
+    Ok(res.rows_affected())
+}
+
+/// record a failed attempt, the email is retried at `next_attempt_at` unless `status` is dead
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn mark_failed(
+    id: i64,
+    lease: DateTime<Utc>,
+    status: OutboxStatus,
+    next_attempt_at: DateTime<Utc>,
+    error: &str,
+) -> SqlResult<u64> {
+    let now = chrono::Local::now();
+    let res = sqlx::query!(
+        r#"
+update email_outbox set
+    status = $1,
+    attempts = attempts + 1,
+    next_attempt_at = $2,
+    last_error = $3,
+    updated_at = $4
+where id = $5 and status = 'Pending' and next_attempt_at = $6
+"#,
+        status as OutboxStatus,
+        next_attempt_at,
+        error,
+        now,
+        id,
+        lease,
+    )
+    .execute(conn().await)
+    .await?;
+
+    Ok(res.rows_affected())
+}
+
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn count_by_status(status: OutboxStatus) -> SqlResult<usize> {
+    let res = sqlx::query!(
+        r#"select count(1) from email_outbox where status = $1"#,
+        status as OutboxStatus,
+    )
+    .fetch_one(conn().await)
+    .await?;
+    Ok(res.count.unwrap() as usize)
+}
+
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn query_by_status(status: OutboxStatus, p: &Pagination) -> SqlResult<Vec<OutboxMail>> {
+    sqlx::query_as!(
+        OutboxMail,
+        r#"
+select
+    id,
+    recipient,
+    subject,
+    text_body,
+    html_body,
+    status as "status!: OutboxStatus",
+    attempts,
+    next_attempt_at,
+    last_error,
+    created_at,
+    updated_at,
+    sent_at,
+    request_id
+from email_outbox
+where status = $1
+order by created_at desc
+limit $2 offset $3
+"#,
+        status as OutboxStatus,
+        p.take(),
+        p.skip()
+    )
+    .fetch_all(conn().await)
+    .await
+}
+
+/// put a dead email back to the queue with a fresh count of attempts
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn requeue(id: i64) -> SqlResult<u64> {
+    let now = chrono::Local::now();
+    let res = sqlx::query!(
+        r#"
+update email_outbox set
+    status = $1,
+    attempts = 0,
+    next_attempt_at = $2,
+    updated_at = $2
+where id = $3 and status = $4
+"#,
+        OutboxStatus::Pending as OutboxStatus,
+        now,
+        id,
+        OutboxStatus::Dead as OutboxStatus,
+    )
+    .execute(conn().await)
+    .await?;
+
+    Ok(res.rows_affected())
+}
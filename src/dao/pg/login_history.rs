@@ -0,0 +1,134 @@
+use crate::model::user as user_model;
+use chrono::{DateTime, Utc};
+use tracing::instrument;
+use util_datetime::FormatDateTime;
+use util_postgres::{conn, SqlResult};
+use util_response::Pagination;
+
+#[derive(Debug, Clone)]
+pub struct LoginHistory {
+    pub id: i64,
+    pub user_id: Option<i64>,
+    pub email: String,
+    pub ip: Option<String>,
+    pub ip_range: Option<String>,
+    pub user_agent: Option<String>,
+    pub fingerprint: String,
+    pub success: bool,
+    pub reason: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<LoginHistory> for user_model::LoginRecord {
+    fn from(x: LoginHistory) -> Self {
+        user_model::LoginRecord {
+            id: x.id,
+            ip: x.ip,
+            user_agent: x.user_agent,
+            success: x.success,
+            reason: x.reason,
+            created_at: x.created_at.to_default(),
+        }
+    }
+}
+
+pub struct NewLoginHistory<'a> {
+    pub user_id: Option<i64>,
+    pub email: &'a str,
+    pub ip: Option<&'a str>,
+    pub ip_range: Option<&'a str>,
+    pub user_agent: Option<&'a str>,
+    pub fingerprint: &'a str,
+    pub success: bool,
+    pub reason: Option<&'a str>,
+}
+
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn insert(x: &NewLoginHistory<'_>) -> SqlResult<u64> {
+    let created_at = chrono::Local::now();
+    let res = sqlx::query!(
+        r#"
+insert into login_history (user_id,email,ip,ip_range,user_agent,fingerprint,success,reason,created_at) values ($1,$2,$3,$4,$5,$6,$7,$8,$9)
+            "#,
+        x.user_id,
+        x.email,
+        x.ip,
+        x.ip_range,
+        x.user_agent,
+        x.fingerprint,
+        x.success,
+        x.reason,
+        created_at,
+    )
+    .execute(conn().await)
+    .await?;
+
+    Ok(res.rows_affected())
+}
+
+/// how many successful logins of the user there are, in total, from the fingerprint and from the ip range
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn count_seen(
+    user_id: i64,
+    fingerprint: &str,
+    ip_range: Option<&str>,
+) -> SqlResult<(i64, i64, i64)> {
+    let res = sqlx::query!(
+        r#"
+select
+    count(1) as "total!",
+    count(1) filter (where fingerprint = $2) as "fingerprint!",
+    count(1) filter (where ip_range = $3) as "ip_range!"
+from login_history
+where user_id = $1 and success
+"#,
+        user_id,
+        fingerprint,
+        ip_range,
+    )
+    .fetch_one(conn().await)
+    .await?;
+
+    Ok((res.total, res.fingerprint, res.ip_range))
+}
+
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn count(user_id: i64) -> SqlResult<usize> {
+    let res = sqlx::query!(
+        r#"select count(1) from login_history where user_id = $1"#,
+        user_id,
+    )
+    .fetch_one(conn().await)
+    .await?;
+    Ok(res.count.unwrap() as usize)
+}
+
+/// the logins of the user, the latest first
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn query(user_id: i64, p: &Pagination) -> SqlResult<Vec<LoginHistory>> {
+    sqlx::query_as!(
+        LoginHistory,
+        r#"
+select
+    id,
+    user_id,
+    email,
+    ip,
+    ip_range,
+    user_agent,
+    fingerprint,
+    success,
+    reason,
+    created_at
+from login_history
+where user_id = $1
+order by created_at desc
+limit $2 offset $3
+"#,
+        user_id,
+        p.take(),
+        p.skip()
+    )
+    .fetch_all(conn().await)
+    .await
+}
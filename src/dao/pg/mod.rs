@@ -1,2 +1,8 @@
+pub mod chat_message;
+pub mod direct_message;
+pub mod email_outbox;
+pub mod login_history;
 pub mod role;
+pub mod room;
+pub mod room_read;
 pub mod user;
@@ -1,4 +1,5 @@
 use chrono::{DateTime, Utc};
+use tracing::instrument;
 use util_postgres::{conn, SqlResult};
 use util_response::Pagination;
 
@@ -12,6 +13,7 @@ pub struct Role {
     pub deleted_at: Option<DateTime<Utc>>,
 }
 
+#[instrument(skip_all, fields(db.system = "postgresql"))]
 pub async fn query(p: &Pagination) -> SqlResult<Vec<Role>> {
     sqlx::query_as!(
         Role,
@@ -35,6 +37,7 @@ limit $1 offset $2
     .await
 }
 
+#[instrument(skip_all, fields(db.system = "postgresql"))]
 pub async fn get(id: i64) -> SqlResult<Role> {
     sqlx::query_as!(
         Role,
@@ -55,6 +58,7 @@ where id = $1
     .await
 }
 
+#[instrument(skip_all, fields(db.system = "postgresql"))]
 pub async fn insert(name: &str, description: Option<&str>) -> SqlResult<Role> {
     let created_at = chrono::Local::now();
     let res = sqlx::query_as!(
@@ -79,6 +83,7 @@ deleted_at
     Ok(res)
 }
 
+#[instrument(skip_all, fields(db.system = "postgresql"))]
 pub async fn update(id: i64, name: &str, description: Option<&str>) -> SqlResult<Role> {
     let updated_at = chrono::Local::now();
     sqlx::query_as!(
@@ -101,6 +106,7 @@ deleted_at
     .await
 }
 
+#[instrument(skip_all, fields(db.system = "postgresql"))]
 pub async fn delete(ids: &[i64]) -> SqlResult<u64> {
     let deleted_at = chrono::Local::now();
     let res = sqlx::query!(
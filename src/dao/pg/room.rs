@@ -0,0 +1,482 @@
+use crate::model::room::{self as room_model, RoomRole, RoomVisibility};
+use chrono::{DateTime, Utc};
+use tracing::instrument;
+use util_datetime::FormatDateTime;
+use util_postgres::{conn, SqlResult};
+use util_response::Pagination;
+
+#[derive(Debug, Clone)]
+pub struct Room {
+    pub id: String,
+    pub owner_id: Option<i64>,
+    pub title: String,
+    pub topic: Option<String>,
+    pub visibility: RoomVisibility,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl From<Room> for room_model::Room {
+    fn from(x: Room) -> Self {
+        room_model::Room {
+            id: x.id,
+            owner_id: x.owner_id,
+            title: x.title,
+            topic: x.topic,
+            visibility: x.visibility,
+            created_at: x.created_at.to_default(),
+            updated_at: x.updated_at.map(|x| x.to_default()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Member {
+    pub user_id: i64,
+    pub email: String,
+    pub name: Option<String>,
+    pub role: RoomRole,
+    pub muted_until: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<Member> for room_model::RoomMember {
+    fn from(x: Member) -> Self {
+        room_model::RoomMember {
+            user_id: x.user_id,
+            name: x.name.unwrap_or(x.email),
+            role: x.role,
+            muted_until: x.muted_until.map(|x| x.to_default()),
+            created_at: x.created_at.to_default(),
+        }
+    }
+}
+
+/// a kick has `expires_at`, a ban has none
+#[derive(Debug, Clone)]
+pub struct Ban {
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Invitation {
+    pub room: String,
+    pub title: String,
+    pub visibility: RoomVisibility,
+    pub invited_by: Option<i64>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<Invitation> for room_model::RoomInvitation {
+    fn from(x: Invitation) -> Self {
+        room_model::RoomInvitation {
+            room: x.room,
+            title: x.title,
+            visibility: x.visibility,
+            invited_by: x.invited_by,
+            created_at: x.created_at.to_default(),
+        }
+    }
+}
+
+pub struct NewRoom<'a> {
+    pub id: &'a str,
+    pub owner_id: i64,
+    pub title: &'a str,
+    pub topic: Option<&'a str>,
+    pub visibility: RoomVisibility,
+}
+
+/// the room with its owner as a member, none when the id is taken
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn insert(x: &NewRoom<'_>) -> SqlResult<Option<Room>> {
+    let created_at = chrono::Local::now();
+    sqlx::query_as!(
+        Room,
+        r#"
+with r as (
+    insert into room (id,owner_id,title,topic,visibility,created_at) values ($1,$2,$3,$4,$5,$6)
+    on conflict do nothing
+    RETURNING id,owner_id,title,topic,visibility,created_at,updated_at
+), m as (
+    insert into room_member (room,user_id,"role",created_at)
+    select id, owner_id, 'Owner', created_at from r
+)
+select
+    id as "id!",
+    owner_id,
+    title as "title!",
+    topic,
+    visibility as "visibility!: RoomVisibility",
+    created_at as "created_at!",
+    updated_at
+from r
+            "#,
+        x.id,
+        x.owner_id,
+        x.title,
+        x.topic,
+        x.visibility as RoomVisibility,
+        created_at,
+    )
+    .fetch_optional(conn().await)
+    .await
+}
+
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn get(id: &str) -> SqlResult<Option<Room>> {
+    sqlx::query_as!(
+        Room,
+        r#"
+select
+    id,
+    owner_id,
+    title,
+    topic,
+    visibility as "visibility!: RoomVisibility",
+    created_at,
+    updated_at
+from room
+where id = $1
+"#,
+        id,
+    )
+    .fetch_optional(conn().await)
+    .await
+}
+
+/// the rooms the user sees: the listed ones and the ones of which it is a member
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn query(user_id: i64, p: &Pagination) -> SqlResult<Vec<Room>> {
+    sqlx::query_as!(
+        Room,
+        r#"
+select
+    id,
+    owner_id,
+    title,
+    topic,
+    visibility as "visibility!: RoomVisibility",
+    created_at,
+    updated_at
+from room r
+where r.visibility <> 'Private'
+    or exists (select 1 from room_member m where m.room = r.id and m.user_id = $1)
+order by created_at desc
+limit $2 offset $3
+"#,
+        user_id,
+        p.take(),
+        p.skip()
+    )
+    .fetch_all(conn().await)
+    .await
+}
+
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn count(user_id: i64) -> SqlResult<usize> {
+    let res = sqlx::query!(
+        r#"
+select count(1) from room r
+where r.visibility <> 'Private'
+    or exists (select 1 from room_member m where m.room = r.id and m.user_id = $1)
+"#,
+        user_id,
+    )
+    .fetch_one(conn().await)
+    .await?;
+    Ok(res.count.unwrap() as usize)
+}
+
+/// the fields which are none are kept, `Some(None)` clears the topic
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn update(
+    id: &str,
+    title: Option<&str>,
+    topic: Option<Option<&str>>,
+    visibility: Option<RoomVisibility>,
+) -> SqlResult<Room> {
+    let updated_at = chrono::Local::now();
+    sqlx::query_as!(
+        Room,
+        r#"
+update room set
+    title = coalesce($2, title),
+    topic = case when $3 then $4 else topic end,
+    visibility = coalesce($5, visibility),
+    updated_at = $6
+where id = $1
+RETURNING
+    id,
+    owner_id,
+    title,
+    topic,
+    visibility as "visibility!: RoomVisibility",
+    created_at,
+    updated_at
+"#,
+        id,
+        title,
+        topic.is_some(),
+        topic.flatten(),
+        visibility as Option<RoomVisibility>,
+        updated_at,
+    )
+    .fetch_one(conn().await)
+    .await
+}
+
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn get_member(room: &str, user_id: i64) -> SqlResult<Option<Member>> {
+    sqlx::query_as!(
+        Member,
+        r#"
+select
+    m.user_id,
+    u.email,
+    u."name",
+    m."role" as "role!: RoomRole",
+    m.muted_until,
+    m.created_at
+from room_member m
+join "user" u on u.id = m.user_id
+where m.room = $1 and m.user_id = $2
+"#,
+        room,
+        user_id,
+    )
+    .fetch_optional(conn().await)
+    .await
+}
+
+/// the members of the room, the highest role first
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn members(room: &str) -> SqlResult<Vec<Member>> {
+    sqlx::query_as!(
+        Member,
+        r#"
+select
+    m.user_id,
+    u.email,
+    u."name",
+    m."role" as "role!: RoomRole",
+    m.muted_until,
+    m.created_at
+from room_member m
+join "user" u on u.id = m.user_id
+where m.room = $1 and u.deleted_at is null
+order by m."role" desc, m.created_at
+"#,
+        room,
+    )
+    .fetch_all(conn().await)
+    .await
+}
+
+/// nothing changes when the user is a member already, or is not a user, e.g. a guest of `test_ws`
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn add_member(room: &str, user_id: i64) -> SqlResult<u64> {
+    let created_at = chrono::Local::now();
+    let res = sqlx::query!(
+        r#"
+insert into room_member (room,user_id,"role",created_at)
+select $1,$2,'Member',$3
+where exists (select 1 from "user" where id = $2)
+on conflict do nothing
+"#,
+        room,
+        user_id,
+        created_at,
+    )
+    .execute(conn().await)
+    .await?;
+    Ok(res.rows_affected())
+}
+
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn set_role(room: &str, user_id: i64, role: RoomRole) -> SqlResult<u64> {
+    let res = sqlx::query!(
+        r#"update room_member set "role" = $3 where room = $1 and user_id = $2"#,
+        room,
+        user_id,
+        role as RoomRole,
+    )
+    .execute(conn().await)
+    .await?;
+    Ok(res.rows_affected())
+}
+
+/// none unmutes the member
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn set_muted_until(
+    room: &str,
+    user_id: i64,
+    muted_until: Option<DateTime<Utc>>,
+) -> SqlResult<u64> {
+    let res = sqlx::query!(
+        r#"update room_member set muted_until = $3 where room = $1 and user_id = $2"#,
+        room,
+        user_id,
+        muted_until,
+    )
+    .execute(conn().await)
+    .await?;
+    Ok(res.rows_affected())
+}
+
+/// the ban or the kick which keeps the user out of the room now
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn get_ban(room: &str, user_id: i64) -> SqlResult<Option<Ban>> {
+    sqlx::query_as!(
+        Ban,
+        r#"
+select expires_at from room_ban
+where room = $1 and user_id = $2 and (expires_at is null or expires_at > now())
+"#,
+        room,
+        user_id,
+    )
+    .fetch_optional(conn().await)
+    .await
+}
+
+/// remove the user from the room with its read pointer and keep it out until `expires_at`, a ban
+/// is kept as it is
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn kick(
+    room: &str,
+    user_id: i64,
+    kicked_by: i64,
+    expires_at: DateTime<Utc>,
+) -> SqlResult<()> {
+    let created_at = chrono::Local::now();
+    sqlx::query!(
+        r#"
+with m as (
+    delete from room_member where room = $1 and user_id = $2
+), r as (
+    delete from room_read where room = $1 and user_id = $2
+)
+insert into room_ban (room,user_id,banned_by,created_at,expires_at) values ($1,$2,$3,$4,$5)
+on conflict (room,user_id) do update
+set banned_by = excluded.banned_by, created_at = excluded.created_at, expires_at = excluded.expires_at
+where room_ban.expires_at is not null
+"#,
+        room,
+        user_id,
+        kicked_by,
+        created_at,
+        expires_at,
+    )
+    .execute(conn().await)
+    .await?;
+    Ok(())
+}
+
+/// remove the user from the room and keep it out, with its invitation and its read pointer, a kick
+/// becomes a ban
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn ban(room: &str, user_id: i64, banned_by: i64) -> SqlResult<()> {
+    let created_at = chrono::Local::now();
+    sqlx::query!(
+        r#"
+with m as (
+    delete from room_member where room = $1 and user_id = $2
+), i as (
+    delete from room_invitation where room = $1 and user_id = $2
+), r as (
+    delete from room_read where room = $1 and user_id = $2
+)
+insert into room_ban (room,user_id,banned_by,created_at) values ($1,$2,$3,$4)
+on conflict (room,user_id) do update
+set banned_by = excluded.banned_by, created_at = excluded.created_at, expires_at = null
+where room_ban.expires_at is not null
+"#,
+        room,
+        user_id,
+        banned_by,
+        created_at,
+    )
+    .execute(conn().await)
+    .await?;
+    Ok(())
+}
+
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn unban(room: &str, user_id: i64) -> SqlResult<u64> {
+    let res = sqlx::query!(
+        r#"delete from room_ban where room = $1 and user_id = $2"#,
+        room,
+        user_id,
+    )
+    .execute(conn().await)
+    .await?;
+    Ok(res.rows_affected())
+}
+
+/// nothing changes when the user has been invited already
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn invite(room: &str, user_id: i64, invited_by: i64) -> SqlResult<u64> {
+    let created_at = chrono::Local::now();
+    let res = sqlx::query!(
+        r#"
+insert into room_invitation (room,user_id,invited_by,created_at)
+select $1,$2,$3,$4
+where exists (select 1 from "user" where id = $2 and deleted_at is null)
+on conflict do nothing
+"#,
+        room,
+        user_id,
+        invited_by,
+        created_at,
+    )
+    .execute(conn().await)
+    .await?;
+    Ok(res.rows_affected())
+}
+
+/// turn the invitation into a membership, returns false when there is none
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn accept_invitation(room: &str, user_id: i64) -> SqlResult<bool> {
+    let created_at = chrono::Local::now();
+    let res = sqlx::query!(
+        r#"
+with i as (
+    delete from room_invitation where room = $1 and user_id = $2
+    RETURNING room, user_id
+)
+insert into room_member (room,user_id,"role",created_at)
+select room, user_id, 'Member', $3 from i
+on conflict do nothing
+"#,
+        room,
+        user_id,
+        created_at,
+    )
+    .execute(conn().await)
+    .await?;
+    Ok(res.rows_affected() > 0)
+}
+
+/// the invitations of the user, the latest first
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn invitations(user_id: i64) -> SqlResult<Vec<Invitation>> {
+    sqlx::query_as!(
+        Invitation,
+        r#"
+select
+    i.room,
+    r.title,
+    r.visibility as "visibility!: RoomVisibility",
+    i.invited_by,
+    i.created_at
+from room_invitation i
+join room r on r.id = i.room
+where i.user_id = $1
+order by i.created_at desc
+"#,
+        user_id,
+    )
+    .fetch_all(conn().await)
+    .await
+}
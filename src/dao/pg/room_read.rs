@@ -0,0 +1,79 @@
+use crate::model::chat as chat_model;
+use tracing::instrument;
+use util_postgres::{conn, SqlResult};
+
+#[derive(Debug, Clone)]
+pub struct RoomUnread {
+    pub room: String,
+    pub last_read_id: Option<i64>,
+    pub unread: i64,
+}
+
+impl From<RoomUnread> for chat_model::RoomUnread {
+    fn from(x: RoomUnread) -> Self {
+        chat_model::RoomUnread {
+            room: x.room,
+            last_read_id: x.last_read_id,
+            unread: x.unread,
+        }
+    }
+}
+
+/// move the read pointer of the user forward to the message, it never goes back
+///
+/// returns the pointer, none when the message is not in the room
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn mark(room: &str, user_id: i64, message_id: i64) -> SqlResult<Option<i64>> {
+    let updated_at = chrono::Local::now();
+    let res = sqlx::query!(
+        r#"
+insert into room_read (room,user_id,last_read_id,updated_at)
+select $1,$2,$3,$4
+where exists (select 1 from chat_message where id = $3 and room = $1)
+on conflict (room,user_id) do update set
+    last_read_id = greatest(room_read.last_read_id, excluded.last_read_id),
+    updated_at = excluded.updated_at
+RETURNING last_read_id
+"#,
+        room,
+        user_id,
+        message_id,
+        updated_at,
+    )
+    .fetch_optional(conn().await)
+    .await?;
+    Ok(res.map(|x| x.last_read_id))
+}
+
+/// the rooms of which the user is a member, or the public ones it has read, with the messages of
+/// the others after its read pointer
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn unread(user_id: i64) -> SqlResult<Vec<RoomUnread>> {
+    sqlx::query_as!(
+        RoomUnread,
+        r#"
+select
+    x.room as "room!",
+    r.last_read_id as "last_read_id?",
+    (
+        select count(1) from chat_message c
+        where c.room = x.room
+            and c.id > coalesce(r.last_read_id, 0)
+            and c.user_id is distinct from $1
+    ) as "unread!"
+from (
+    select room from room_member where user_id = $1
+    union
+    -- a public room is read without being a member, e.g. main
+    select rr.room from room_read rr
+    join room on room.id = rr.room
+    where rr.user_id = $1 and room.visibility = 'Public'
+) x
+left join room_read r on r.room = x.room and r.user_id = $1
+order by x.room
+"#,
+        user_id,
+    )
+    .fetch_all(conn().await)
+    .await
+}
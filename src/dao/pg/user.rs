@@ -1,5 +1,6 @@
 use crate::model::user::{self as user_model, UserStatus, UserType};
 use chrono::{DateTime, Utc};
+use tracing::instrument;
 use util_datetime::FormatDateTime;
 use util_error::BasicResult;
 use util_response::Pagination;
@@ -16,7 +17,15 @@ pub struct User {
     pub name: Option<String>,
     pub salt: String,
     pub pwd: Option<String>,
+    /// E.164
     pub mobile: Option<String>,
+    /// ISO 3166-1 alpha-2
+    pub mobile_country: Option<String>,
+    pub avatar_url: Option<String>,
+    pub bio: Option<String>,
+    pub locale: Option<String>,
+    pub timezone: Option<String>,
+    pub preferences: Option<serde_json::Value>,
     pub laston: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
     pub updated_at: Option<DateTime<Utc>>,
@@ -32,6 +41,11 @@ impl From<User> for user_model::User {
             status: x.status,
             name: x.name,
             mobile: x.mobile,
+            mobile_country: x.mobile_country,
+            avatar_url: x.avatar_url,
+            bio: x.bio,
+            locale: x.locale,
+            timezone: x.timezone,
             laston: x.laston.map(|x| x.to_default()),
             created_at: x.created_at.to_default(),
             updated_at: x.updated_at.map(|x| x.to_default()),
@@ -39,6 +53,7 @@ impl From<User> for user_model::User {
     }
 }
 
+#[instrument(skip_all, fields(db.system = "postgresql"))]
 pub async fn count() -> SqlResult<usize> {
     let res = sqlx::query!(
         r#"
@@ -54,6 +69,7 @@ where u.deleted_at is null
     Ok(res.count.unwrap() as usize)
 }
 
+#[instrument(skip_all, fields(db.system = "postgresql"))]
 pub async fn query(p: &Pagination) -> SqlResult<Vec<User>> {
     sqlx::query_as!(
         User,
@@ -67,6 +83,12 @@ select
      salt,
      pwd,
      mobile,
+     mobile_country,
+     avatar_url,
+     bio,
+     locale,
+     timezone,
+     preferences,
      laston,
      created_at,
      updated_at,
@@ -83,6 +105,7 @@ limit $1 offset $2
     .await
 }
 
+#[instrument(skip_all, fields(db.system = "postgresql"))]
 pub async fn get_all() -> SqlResult<Vec<User>> {
     sqlx::query_as!(
         User,
@@ -96,6 +119,12 @@ select
      salt,
      pwd,
      mobile,
+     mobile_country,
+     avatar_url,
+     bio,
+     locale,
+     timezone,
+     preferences,
      laston,
      created_at,
      updated_at,
@@ -109,6 +138,7 @@ order by u.created_at desc
     .await
 }
 
+#[instrument(skip_all, fields(db.system = "postgresql"))]
 pub async fn get(id: i64) -> SqlResult<User> {
     sqlx::query_as!(
         User,
@@ -122,6 +152,12 @@ select
     salt,
     pwd,
     mobile,
+    mobile_country,
+    avatar_url,
+    bio,
+    locale,
+    timezone,
+    preferences,
     laston,
     created_at,
     updated_at,
@@ -135,6 +171,40 @@ where id = $1
     .await
 }
 
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn get_by_ids(ids: &[i64]) -> SqlResult<Vec<User>> {
+    sqlx::query_as!(
+        User,
+        r#"
+select
+    id,
+    "type" as "type!: UserType",
+    email,
+    status as "status!: UserStatus",
+    "name",
+    salt,
+    pwd,
+    mobile,
+    mobile_country,
+    avatar_url,
+    bio,
+    locale,
+    timezone,
+    preferences,
+    laston,
+    created_at,
+    updated_at,
+    deleted_at
+from "user" 
+where id = ANY($1)
+            "#,
+        ids,
+    )
+    .fetch_all(conn().await)
+    .await
+}
+
+#[instrument(skip_all, fields(db.system = "postgresql"))]
 pub async fn get_by_email(email: &str) -> BasicResult<User> {
     let res = sqlx::query_as!(
         User,
@@ -148,6 +218,12 @@ select
     salt,
     pwd,
     mobile,
+    mobile_country,
+    avatar_url,
+    bio,
+    locale,
+    timezone,
+    preferences,
     laston,
     created_at,
     updated_at,
@@ -167,18 +243,54 @@ where email = $1
     Ok(res)
 }
 
+/// unlike [`get_by_email`], a missing user is not an error
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn find_by_email(email: &str) -> SqlResult<Option<User>> {
+    sqlx::query_as!(
+        User,
+        r#"
+select
+    id,
+    "type" as "type!: UserType",
+    email,
+    status as "status!: UserStatus",
+    "name",
+    salt,
+    pwd,
+    mobile,
+    mobile_country,
+    avatar_url,
+    bio,
+    locale,
+    timezone,
+    preferences,
+    laston,
+    created_at,
+    updated_at,
+    deleted_at
+from "user" 
+where email = $1
+            "#,
+        email,
+    )
+    .fetch_optional(conn().await)
+    .await
+}
+
+#[instrument(skip_all, fields(db.system = "postgresql"))]
 pub async fn insert(
     email: &str,
     salt: &str,
     pwd: &str,
     name: Option<&str>,
     mobile: Option<&str>,
+    mobile_country: Option<&str>,
 ) -> SqlResult<User> {
     let created_at = chrono::Local::now();
     let res = sqlx::query_as!(
         User,
         r#"
-insert into "user" (type,email,pwd,salt,name,mobile,created_at) values ($1,$2,$3,$4,$5,$6,$7) 
+insert into "user" (type,email,pwd,salt,name,mobile,mobile_country,created_at) values ($1,$2,$3,$4,$5,$6,$7,$8) 
 RETURNING 
 
 id,
@@ -189,6 +301,12 @@ status as "status!: UserStatus",
 salt,
 pwd,
 mobile,
+mobile_country,
+avatar_url,
+bio,
+locale,
+timezone,
+preferences,
 laston,
 created_at,
 updated_at,
@@ -200,6 +318,7 @@ deleted_at
         salt,
         name,
         mobile,
+        mobile_country,
         created_at,
     )
     .fetch_one(conn().await)
@@ -208,6 +327,7 @@ deleted_at
     Ok(res)
 }
 
+#[instrument(skip_all, fields(db.system = "postgresql"))]
 pub async fn delete(ids: &[i64]) -> SqlResult<u64> {
     let deleted_at = chrono::Local::now();
     let res = sqlx::query!(
@@ -221,11 +341,24 @@ pub async fn delete(ids: &[i64]) -> SqlResult<u64> {
     Ok(res.rows_affected())
 }
 
-pub async fn update(id: i64, name: Option<&str>, mobile: Option<&str>) -> SqlResult<User> {
+/// update the profile of a user
+///
+/// a field which is omitted in `req` stays unchanged, an explicit null clears it
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn update(id: i64, req: &user_model::UserUpdateReq) -> SqlResult<User> {
     let updated_at = chrono::Local::now();
     sqlx::query_as!(
         User,
-        r#"update "user" set name = $1, mobile = $2, updated_at=$3 where id = $4 RETURNING
+        r#"update "user" set
+    name = case when $1 then $2 else name end,
+    mobile = case when $3 then $4 else mobile end,
+    mobile_country = case when $3 then $5 else mobile_country end,
+    bio = case when $6 then $7 else bio end,
+    locale = case when $8 then $9 else locale end,
+    timezone = case when $10 then $11 else timezone end,
+    preferences = case when $12 then $13 else preferences end,
+    updated_at = $14
+where id = $15 RETURNING
 id,
 "type" as "type!: UserType",
 email,
@@ -234,13 +367,30 @@ status as "status!: UserStatus",
 salt,
 pwd,
 mobile,
+mobile_country,
+avatar_url,
+bio,
+locale,
+timezone,
+preferences,
 laston,
 created_at,
 updated_at,
 deleted_at
             "#,
-        name,
-        mobile,
+        req.name.is_some(),
+        req.name.clone().flatten(),
+        req.mobile.is_some(),
+        req.mobile.clone().flatten(),
+        req.mobile_country.clone(),
+        req.bio.is_some(),
+        req.bio.clone().flatten(),
+        req.locale.is_some(),
+        req.locale.clone().flatten(),
+        req.timezone.is_some(),
+        req.timezone.clone().flatten(),
+        req.preferences.is_some(),
+        req.preferences.clone().flatten(),
         updated_at,
         id,
     )
@@ -248,6 +398,7 @@ deleted_at
     .await
 }
 
+#[instrument(skip_all, fields(db.system = "postgresql"))]
 pub async fn update_pwd(id: i64, salt: &str, pwd: &str) -> SqlResult<u64> {
     let updated_at = chrono::Local::now();
     let res = sqlx::query!(
@@ -263,6 +414,7 @@ pub async fn update_pwd(id: i64, salt: &str, pwd: &str) -> SqlResult<u64> {
     Ok(res.rows_affected())
 }
 
+#[instrument(skip_all, fields(db.system = "postgresql"))]
 pub async fn update_laston(id: i64, laston: &DateTime<Utc>) -> SqlResult<u64> {
     let res = sqlx::query!(r#"update "user" set laston = $1 where id = $2"#, laston, id)
         .execute(conn().await)
@@ -270,3 +422,104 @@ pub async fn update_laston(id: i64, laston: &DateTime<Utc>) -> SqlResult<u64> {
 
     Ok(res.rows_affected())
 }
+
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn update_avatar(id: i64, avatar_url: &str) -> SqlResult<User> {
+    let updated_at = chrono::Local::now();
+    sqlx::query_as!(
+        User,
+        r#"update "user" set avatar_url = $1, updated_at=$2 where id = $3 RETURNING
+id,
+"type" as "type!: UserType",
+email,
+status as "status!: UserStatus",
+"name",
+salt,
+pwd,
+mobile,
+mobile_country,
+avatar_url,
+bio,
+locale,
+timezone,
+preferences,
+laston,
+created_at,
+updated_at,
+deleted_at
+            "#,
+        avatar_url,
+        updated_at,
+        id,
+    )
+    .fetch_one(conn().await)
+    .await
+}
+
+#[derive(Debug, Clone)]
+pub struct PwdHistory {
+    pub salt: String,
+    pub pwd: String,
+}
+
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn insert_pwd_history(user_id: i64, salt: &str, pwd: &str) -> SqlResult<u64> {
+    let created_at = chrono::Local::now();
+    let res = sqlx::query!(
+        r#"insert into user_pwd_history (user_id,salt,pwd,created_at) values ($1,$2,$3,$4)"#,
+        user_id,
+        salt,
+        pwd,
+        created_at,
+    )
+    .execute(conn().await)
+    .await?;
+
+    Ok(res.rows_affected())
+}
+
+/// keep the current password of a user who has no history yet, as the one at `created_at`
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn seed_pwd_history(
+    user_id: i64,
+    salt: &str,
+    pwd: &str,
+    created_at: &DateTime<Utc>,
+) -> SqlResult<u64> {
+    let res = sqlx::query!(
+        r#"
+insert into user_pwd_history (user_id,salt,pwd,created_at)
+select $1,$2,$3,$4
+where not exists (select 1 from user_pwd_history where user_id = $1)
+"#,
+        user_id,
+        salt,
+        pwd,
+        created_at,
+    )
+    .execute(conn().await)
+    .await?;
+
+    Ok(res.rows_affected())
+}
+
+/// the last `limit` passwords of a user, the latest first
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn get_pwd_history(user_id: i64, limit: i64) -> SqlResult<Vec<PwdHistory>> {
+    sqlx::query_as!(
+        PwdHistory,
+        r#"
+select
+    salt,
+    pwd
+from user_pwd_history
+where user_id = $1
+order by created_at desc
+limit $2
+"#,
+        user_id,
+        limit,
+    )
+    .fetch_all(conn().await)
+    .await
+}
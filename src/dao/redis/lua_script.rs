@@ -1,22 +1,22 @@
-use async_once::AsyncOnce;
-use lazy_static::lazy_static;
 use redis::{aio::ConnectionLike, FromRedisValue};
-use util_error::BasicResult;
+use tokio::sync::OnceCell;
+use util_error::{business_error, BasicResult};
 
-lazy_static! {
-    pub static ref ROOMS_CHANGE: AsyncOnce<String> =
-        AsyncOnce::new(async { load_rooms_change().await.unwrap() });
-    pub static ref ROOMS_RETRIEVE: AsyncOnce<String> =
-        AsyncOnce::new(async { load_rooms_retrieve().await.unwrap() });
-}
+const ROOMS_CHANGE_FILES: [&str; 2] = ["json.lua", "rooms_change.lua"];
+const ROOMS_RETRIEVE_FILES: [&str; 2] = ["json.lua", "rooms_retrieve.lua"];
+const NODES_REAP_FILES: [&str; 1] = ["nodes_reap.lua"];
+
+static ROOMS_CHANGE: OnceCell<String> = OnceCell::const_new();
+static ROOMS_RETRIEVE: OnceCell<String> = OnceCell::const_new();
+static NODES_REAP: OnceCell<String> = OnceCell::const_new();
 
-async fn load_rooms_change() -> BasicResult<String> {
+/// load the script into redis, returns its sha
+async fn load(files: &[&str]) -> BasicResult<String> {
     let mut conn = util_redis::conn().await?;
-    let files = ["json.lua", "rooms_change.lua"];
-    let cmd_str: String = files
+    let cmd_str = files
         .iter()
-        .map(|&name| std::fs::read_to_string(format!("static/lua_scripts/{}", name)).unwrap())
-        .collect();
+        .map(|&name| std::fs::read_to_string(format!("static/lua_scripts/{}", name)))
+        .collect::<Result<String, _>>()?;
 
     let mut cmd = redis::cmd("script");
 
@@ -28,20 +28,87 @@ async fn load_rooms_change() -> BasicResult<String> {
     Ok(res)
 }
 
-async fn load_rooms_retrieve() -> BasicResult<String> {
-    let mut conn = util_redis::conn().await?;
-    let files = ["json.lua", "rooms_retrieve.lua"];
-    let cmd_str: String = files
-        .iter()
-        .map(|&name| std::fs::read_to_string(format!("static/lua_scripts/{}", name)).unwrap())
-        .collect();
+/// the sha of the rooms change script, loaded on first use and again after a failure
+pub async fn rooms_change() -> BasicResult<&'static str> {
+    let sha = ROOMS_CHANGE
+        .get_or_try_init(|| load(&ROOMS_CHANGE_FILES))
+        .await?;
+    Ok(sha)
+}
+
+/// the sha of the rooms retrieve script, loaded on first use and again after a failure
+pub async fn rooms_retrieve() -> BasicResult<&'static str> {
+    let sha = ROOMS_RETRIEVE
+        .get_or_try_init(|| load(&ROOMS_RETRIEVE_FILES))
+        .await?;
+    Ok(sha)
+}
+
+/// the sha of the script removing the sessions of dead nodes, loaded on first use and again after a
+/// failure
+pub async fn nodes_reap() -> BasicResult<&'static str> {
+    let sha = NODES_REAP
+        .get_or_try_init(|| load(&NODES_REAP_FILES))
+        .await?;
+    Ok(sha)
+}
+
+/// the scripts in use which redis no longer has, it loses them when it restarts without
+/// persistence
+///
+/// only asked, a script not used yet is loaded on its first use
+async fn missing() -> BasicResult<Vec<(&'static str, &'static [&'static str])>> {
+    let scripts: Vec<(&str, &[&str])> = [
+        (ROOMS_CHANGE.get(), &ROOMS_CHANGE_FILES[..]),
+        (ROOMS_RETRIEVE.get(), &ROOMS_RETRIEVE_FILES[..]),
+        (NODES_REAP.get(), &NODES_REAP_FILES[..]),
+    ]
+    .into_iter()
+    .filter_map(|(sha, files)| sha.map(|x| (x.as_str(), files)))
+    .collect();
+    if scripts.is_empty() {
+        return Ok(vec![]);
+    }
 
     let mut cmd = redis::cmd("script");
+    cmd.arg("exists");
+    for (sha, _) in scripts.iter() {
+        cmd.arg(*sha);
+    }
+    let value = util_redis::conn().await?.req_packed_command(&cmd).await?;
+    let exists = Vec::<bool>::from_redis_value(&value)?;
 
-    cmd.arg("load").arg(cmd_str);
+    Ok(scripts
+        .into_iter()
+        .zip(exists)
+        .filter(|(_, exists)| !exists)
+        .map(|(script, _)| script)
+        .collect())
+}
 
-    let value = conn.req_packed_command(&cmd).await?;
+/// redis still has the scripts in use, nothing is loaded so a probe can call it
+pub async fn check() -> BasicResult<()> {
+    let missing = missing().await?;
+    if !missing.is_empty() {
+        let shas = missing.iter().map(|(sha, _)| *sha).collect::<Vec<_>>();
+        return business_error!(format!(
+            "lua scripts {} are missing in redis",
+            shas.join(", ")
+        ))
+        .into();
+    }
+    Ok(())
+}
 
-    let res = String::from_redis_value(&value)?;
-    Ok(res)
+/// load again the scripts which redis has lost
+///
+/// a script is the same as it was, so it is loaded again under the sha which is already known
+pub async fn ensure_loaded() -> BasicResult<()> {
+    for (sha, files) in missing().await? {
+        log::warn!("lua script {} is missing in redis, load it again", sha);
+        if load(files).await? != sha {
+            return business_error!(format!("lua script {} has changed", sha)).into();
+        }
+    }
+    Ok(())
 }
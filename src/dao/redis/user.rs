@@ -1,4 +1,6 @@
 use crate::model::user as user_model;
+use redis::{aio::ConnectionLike, FromRedisValue};
+use tracing::instrument;
 use util_error::BasicResult;
 use util_redis as redis_util;
 
@@ -10,6 +12,11 @@ fn user_agent_key(email: &str) -> String {
     format!("{email}_session")
 }
 
+fn reset_pwd_key(email: &str) -> String {
+    format!("{email}_reset_pwd")
+}
+
+#[instrument(skip_all, fields(db.system = "redis"))]
 pub async fn get_email_code(
     email: &str,
     from: &user_model::SendEmailCodeFrom,
@@ -19,6 +26,7 @@ pub async fn get_email_code(
     Ok(res)
 }
 
+#[instrument(skip_all, fields(db.system = "redis"))]
 pub async fn set_email_code(
     email: &str,
     from: &user_model::SendEmailCodeFrom,
@@ -29,6 +37,7 @@ pub async fn set_email_code(
     Ok(())
 }
 
+#[instrument(skip_all, fields(db.system = "redis"))]
 pub async fn exist_email_code(
     email: &str,
     from: &user_model::SendEmailCodeFrom,
@@ -37,11 +46,13 @@ pub async fn exist_email_code(
     Ok(res)
 }
 
+#[instrument(skip_all, fields(db.system = "redis"))]
 pub async fn exist_current_user(email: &str) -> BasicResult<bool> {
     let res = redis_util::exists(user_agent_key(&email)).await?;
     Ok(res)
 }
 
+#[instrument(skip_all, fields(db.system = "redis"))]
 pub async fn set_current_user(
     current_user: user_model::CurrentUser,
     expired_seconds: u64,
@@ -54,8 +65,33 @@ pub async fn set_current_user(
     .await?;
     Ok(())
 }
+#[instrument(skip_all, fields(db.system = "redis"))]
 pub async fn get_current_user(email: &str) -> BasicResult<user_model::CurrentUser> {
     let current_user =
         redis_util::get::<_, user_model::CurrentUser>(user_agent_key(&email)).await?;
     Ok(current_user)
 }
+
+#[instrument(skip_all, fields(db.system = "redis"))]
+pub async fn set_reset_pwd_token(email: &str, id: &str, expired_seconds: u64) -> BasicResult<()> {
+    redis_util::set_ex(reset_pwd_key(email), id.to_string(), expired_seconds).await?;
+    Ok(())
+}
+
+#[instrument(skip_all, fields(db.system = "redis"))]
+pub async fn get_reset_pwd_token(email: &str) -> BasicResult<Option<String>> {
+    let res = redis_util::get::<_, Option<String>>(reset_pwd_key(email)).await?;
+    Ok(res)
+}
+
+/// get and delete the reset password token in one go, so that it can be used only once
+#[instrument(skip_all, fields(db.system = "redis"))]
+pub async fn take_reset_pwd_token(email: &str) -> BasicResult<Option<String>> {
+    let mut cmd = redis::cmd("getdel");
+    cmd.arg(reset_pwd_key(email));
+
+    let value = redis_util::conn().await?.req_packed_command(&cmd).await?;
+
+    let res = Option::<String>::from_redis_value(&value)?;
+    Ok(res)
+}
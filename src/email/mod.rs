@@ -0,0 +1,42 @@
+pub mod outbox;
+pub mod template;
+pub mod transport;
+
+use crate::config;
+use template::Template;
+use util_error::BasicResult;
+
+/// start the outbox worker delivering through the transport in config
+pub fn init() -> BasicResult<()> {
+    let transport = transport::from_config(&config::cfg().email)?;
+    outbox::spawn(transport);
+    Ok(())
+}
+
+/// render the template in the locale of the receiver and put it into the outbox
+///
+/// the email is delivered in background, a slow or failing relay does not fail the caller
+pub async fn send(
+    to: &str,
+    template: Template,
+    locale: Option<&str>,
+    vars: &[(&str, &str)],
+) -> BasicResult<()> {
+    let rendered = template::render(template, locale, vars)?;
+    outbox::enqueue(to, &rendered).await?;
+    Ok(())
+}
+
+/// render the template for every receiver, which is `(to, locale, vars)`, and put them into the
+/// outbox at once
+pub async fn send_many(
+    template: Template,
+    receivers: &[(&str, Option<&str>, Vec<(&str, &str)>)],
+) -> BasicResult<()> {
+    let mut mails = Vec::with_capacity(receivers.len());
+    for (to, locale, vars) in receivers {
+        mails.push((*to, template::render(template, *locale, vars)?));
+    }
+    outbox::enqueue_many(&mails).await?;
+    Ok(())
+}
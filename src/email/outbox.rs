@@ -0,0 +1,181 @@
+use super::{template::Rendered, transport::Transport};
+use crate::{
+    config, dao::pg::email_outbox as pg_email_outbox_dao, metrics, middleware::request_id,
+    model::email::OutboxStatus,
+};
+use chrono::{DateTime, Duration, Utc};
+use std::sync::Arc;
+use util_error::BasicResult;
+
+/// seconds to wait before the `attempts`th retry, doubled each time and capped at `backoff_max`
+fn backoff(cfg: &config::Outbox, attempts: i32) -> u64 {
+    let exp = attempts.saturating_sub(1).clamp(0, 32) as u32;
+    cfg.backoff_base
+        .saturating_mul(2u64.saturating_pow(exp))
+        .min(cfg.backoff_max)
+}
+
+/// what becomes of an email which has failed `attempts` times
+fn after_failure(
+    cfg: &config::Outbox,
+    attempts: i32,
+    now: DateTime<Utc>,
+) -> (OutboxStatus, DateTime<Utc>) {
+    let status = match attempts >= cfg.max_attempts {
+        true => OutboxStatus::Dead,
+        _ => OutboxStatus::Pending,
+    };
+    (
+        status,
+        now + Duration::seconds(backoff(cfg, attempts) as i64),
+    )
+}
+
+/// store the email to be delivered by the worker, with the id of the request being handled
+pub async fn enqueue(to: &str, mail: &Rendered) -> BasicResult<i64> {
+    let request_id = request_id::current();
+    let id = pg_email_outbox_dao::insert(
+        to,
+        &mail.subject,
+        &mail.text,
+        &mail.html,
+        request_id.as_deref(),
+    )
+    .await?;
+    Ok(id)
+}
+
+/// store the emails to be delivered by the worker in one go
+pub async fn enqueue_many(mails: &[(&str, Rendered)]) -> BasicResult<u64> {
+    let recipients: Vec<String> = mails.iter().map(|(to, _)| to.to_string()).collect();
+    let subjects: Vec<String> = mails.iter().map(|(_, x)| x.subject.clone()).collect();
+    let text_bodies: Vec<String> = mails.iter().map(|(_, x)| x.text.clone()).collect();
+    let html_bodies: Vec<String> = mails.iter().map(|(_, x)| x.html.clone()).collect();
+    let request_id = request_id::current();
+    let res = pg_email_outbox_dao::insert_many(
+        &recipients,
+        &subjects,
+        &text_bodies,
+        &html_bodies,
+        request_id.as_deref(),
+    )
+    .await?;
+    Ok(res)
+}
+
+/// an email marked by nothing has been taken over by another worker after the lease expired
+fn lost_lease(id: i64, rows_affected: u64) {
+    if rows_affected == 0 {
+        log::warn!(
+            "email {} has been claimed by another worker, its result is dropped",
+            id
+        );
+    }
+}
+
+/// deliver the due emails once, returns how many have been taken
+async fn deliver(transport: &dyn Transport) -> BasicResult<usize> {
+    let config = config::cfg();
+    let cfg = &config.email.outbox;
+    let lease_until = Utc::now() + Duration::seconds(cfg.lease as i64);
+    let mails = pg_email_outbox_dao::claim(cfg.batch_size, lease_until).await?;
+
+    for mail in mails.iter() {
+        // the rest may have been claimed again by another worker
+        if mail.next_attempt_at <= Utc::now() {
+            log::warn!(
+                "the lease of email {} has expired before its delivery",
+                mail.id
+            );
+            break;
+        }
+        let rendered = Rendered {
+            subject: mail.subject.clone(),
+            text: mail.text_body.clone(),
+            html: mail.html_body.clone(),
+        };
+        match transport
+            .send(&mail.recipient, &rendered, mail.request_id.as_deref())
+            .await
+        {
+            Ok(_) => {
+                metrics::EMAILS.with_label_values(&["sent"]).inc();
+                let res = pg_email_outbox_dao::mark_sent(mail.id, mail.next_attempt_at).await?;
+                lost_lease(mail.id, res);
+            }
+            Err(err) => {
+                let attempts = mail.attempts + 1;
+                let (status, next_attempt_at) = after_failure(cfg, attempts, Utc::now());
+                metrics::EMAILS.with_label_values(&["failed"]).inc();
+                if status == OutboxStatus::Dead {
+                    metrics::EMAILS.with_label_values(&["dead"]).inc();
+                }
+                log::warn!(
+                    "deliver email {} to {} failed {} times: {:?}",
+                    mail.id,
+                    mail.recipient,
+                    attempts,
+                    err
+                );
+                let res = pg_email_outbox_dao::mark_failed(
+                    mail.id,
+                    mail.next_attempt_at,
+                    status,
+                    next_attempt_at,
+                    &format!("{:?}", err),
+                )
+                .await?;
+                lost_lease(mail.id, res);
+            }
+        }
+    }
+    Ok(mails.len())
+}
+
+/// run the worker in background, several nodes can run it against the same outbox
+pub fn spawn(transport: Arc<dyn Transport>) {
+    tokio::spawn(async move {
+        loop {
+            match deliver(transport.as_ref()).await {
+                // there may be more, go on without waiting
+                Ok(n) if n as i64 >= config::cfg().email.outbox.batch_size => continue,
+                Ok(_) => {}
+                Err(err) => log::error!("email outbox err: {:?}", err),
+            }
+            let poll_interval = config::cfg().email.outbox.poll_interval;
+            tokio::time::sleep(std::time::Duration::from_secs(poll_interval)).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_after_failure() {
+        let cfg = config::Outbox {
+            poll_interval: 5,
+            batch_size: 20,
+            lease: 60,
+            max_attempts: 5,
+            backoff_base: 30,
+            backoff_max: 300,
+        };
+        let now = Utc::now();
+        let delays: Vec<_> = (1..=5)
+            .map(|x| after_failure(&cfg, x, now))
+            .map(|(status, at)| (status, (at - now).num_seconds()))
+            .collect();
+        assert_eq!(
+            vec![
+                (OutboxStatus::Pending, 30),
+                (OutboxStatus::Pending, 60),
+                (OutboxStatus::Pending, 120),
+                (OutboxStatus::Pending, 240),
+                (OutboxStatus::Dead, 300),
+            ],
+            delays
+        );
+    }
+}
@@ -0,0 +1,186 @@
+use std::path::{Path, PathBuf};
+use util_error::{business_error, BasicResult};
+
+/// every template is made of `{name}.subject`, `{name}.txt` and `{name}.html` under a locale dir
+const DIR: &str = "static/email_templates";
+
+/// the locale to fall back to when there is no template for the locale of the user
+pub const DEFAULT_LOCALE: &str = "en";
+
+#[derive(Debug, Clone, Copy)]
+pub enum Template {
+    /// vars: code, minutes
+    Register,
+    /// vars: name, code, minutes
+    ChangePwd,
+    /// vars: name, link, minutes
+    ResetPwd,
+    /// vars: name, inviter, room, link
+    Invitation,
+    /// vars: name, time, ip, user_agent
+    LoginAlert,
+    /// vars: name, email
+    AccountDeletion,
+}
+
+impl Template {
+    pub const ALL: [Template; 6] = [
+        Template::Register,
+        Template::ChangePwd,
+        Template::ResetPwd,
+        Template::Invitation,
+        Template::LoginAlert,
+        Template::AccountDeletion,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Template::Register => "register",
+            Template::ChangePwd => "change_pwd",
+            Template::ResetPwd => "reset_pwd",
+            Template::Invitation => "invitation",
+            Template::LoginAlert => "login_alert",
+            Template::AccountDeletion => "account_deletion",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Rendered {
+    pub subject: String,
+    pub text: String,
+    pub html: String,
+}
+
+/// the locales to try in order, e.g. zh-CN, zh, en
+fn candidates(locale: Option<&str>) -> Vec<&str> {
+    let mut res = Vec::new();
+    // a locale comes from the user, it must not escape from the template dir
+    if let Some(locale) = locale.filter(|x| {
+        !x.is_empty() && x.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    }) {
+        res.push(locale);
+        if let Some((language, _)) = locale.split_once('-') {
+            res.push(language);
+        }
+    }
+    res.push(DEFAULT_LOCALE);
+    res
+}
+
+fn locate(template: Template, locale: Option<&str>) -> BasicResult<PathBuf> {
+    candidates(locale)
+        .into_iter()
+        .map(|x| Path::new(DIR).join(x))
+        .find(|x| x.join(format!("{}.txt", template.name())).exists())
+        .ok_or_else(|| business_error!(format!("email template {} not found", template.name())))
+}
+
+fn escape_html(value: &str) -> String {
+    let mut res = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => res.push_str("&amp;"),
+            '<' => res.push_str("&lt;"),
+            '>' => res.push_str("&gt;"),
+            '"' => res.push_str("&quot;"),
+            '\'' => res.push_str("&#x27;"),
+            _ => res.push(c),
+        }
+    }
+    res
+}
+
+/// replace every `{{ var }}` in the content, a missing var is an error
+fn fill(content: &str, vars: &[(&str, &str)], html: bool) -> BasicResult<String> {
+    let mut res = String::with_capacity(content.len());
+    let mut rest = content;
+    while let Some(start) = rest.find("{{") {
+        let end = rest[start..]
+            .find("}}")
+            .map(|x| x + start)
+            .ok_or_else(|| business_error!("unclosed var in email template".to_string()))?;
+        let name = rest[start + 2..end].trim();
+        let value = vars
+            .iter()
+            .find(|(k, _)| *k == name)
+            .map(|(_, v)| *v)
+            .ok_or_else(|| business_error!(format!("var {} of email template is missing", name)))?;
+
+        res.push_str(&rest[..start]);
+        match html {
+            true => res.push_str(&escape_html(value)),
+            _ => res.push_str(value),
+        }
+        rest = &rest[end + 2..];
+    }
+    res.push_str(rest);
+    Ok(res)
+}
+
+/// render a template in the locale of the user, falling back to [`DEFAULT_LOCALE`]
+pub fn render(
+    template: Template,
+    locale: Option<&str>,
+    vars: &[(&str, &str)],
+) -> BasicResult<Rendered> {
+    let dir = locate(template, locale)?;
+    let read = |ext: &str| std::fs::read_to_string(dir.join(format!("{}.{}", template.name(), ext)));
+
+    Ok(Rendered {
+        subject: fill(read("subject")?.trim(), vars, false)?,
+        text: fill(&read("txt")?, vars, false)?,
+        html: fill(&read("html")?, vars, true)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SNAPSHOT_DIR: &str = "src/email/snapshots";
+
+    const VARS: [(&str, &str); 10] = [
+        ("name", "Tom & <Jerry>"),
+        ("email", "tom@evolve.com"),
+        ("code", "123456"),
+        ("minutes", "10"),
+        ("link", "http://127.0.0.1:8881/reset_pwd?token=a.b.c&x=1"),
+        ("inviter", "Jerry"),
+        ("room", "main"),
+        ("time", "2026-10-19 08:00:00"),
+        ("ip", "127.0.0.1"),
+        ("user_agent", "Mozilla/5.0"),
+    ];
+
+    /// set UPDATE_SNAPSHOTS=1 to regenerate the snapshots after changing a template
+    #[test]
+    fn test_render_snapshots() {
+        let update = std::env::var("UPDATE_SNAPSHOTS").is_ok();
+        for locale in ["en", "zh-CN"] {
+            for template in Template::ALL {
+                let rendered = render(template, Some(locale), &VARS).unwrap();
+                let actual = format!(
+                    "subject: {}\n--- text\n{}--- html\n{}",
+                    rendered.subject, rendered.text, rendered.html
+                );
+                let path = Path::new(SNAPSHOT_DIR).join(format!("{}.{}.snap", locale, template.name()));
+                if update {
+                    std::fs::write(&path, &actual).unwrap();
+                    continue;
+                }
+                let expected = std::fs::read_to_string(&path).unwrap();
+                assert_eq!(expected, actual, "snapshot {:?} mismatched", path);
+            }
+        }
+    }
+
+    #[test]
+    fn test_render_fallback() {
+        let en = render(Template::Register, None, &VARS).unwrap();
+        assert_eq!(en.subject, render(Template::Register, Some("fr-FR"), &VARS).unwrap().subject);
+        assert_eq!(en.subject, render(Template::Register, Some("../zh-CN"), &VARS).unwrap().subject);
+        assert_ne!(en.subject, render(Template::Register, Some("zh-CN"), &VARS).unwrap().subject);
+        assert!(render(Template::Register, None, &[]).is_err());
+    }
+}
@@ -0,0 +1,259 @@
+use super::template::Rendered;
+use crate::config;
+use async_trait::async_trait;
+use fancy_regex::Regex;
+use lettre::{
+    message::{
+        header::{Header, HeaderName, HeaderValue},
+        Mailbox, MultiPart,
+    },
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use util_error::{business_error, validate_error, BasicResult};
+
+/// where the outbox worker hands the emails over
+///
+/// `request_id` is the request which has queued the email, if any
+#[async_trait]
+pub trait Transport: Send + Sync {
+    async fn send(&self, to: &str, mail: &Rendered, request_id: Option<&str>) -> BasicResult<()>;
+}
+
+/// the `X-Request-Id` header of an email
+#[derive(Clone)]
+struct XRequestId(String);
+
+impl Header for XRequestId {
+    fn name() -> HeaderName {
+        HeaderName::new_from_ascii_str("X-Request-Id")
+    }
+
+    fn parse(s: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Self(s.to_string()))
+    }
+
+    fn display(&self) -> HeaderValue {
+        HeaderValue::new(Self::name(), self.0.clone())
+    }
+}
+
+/// the transport selected in config
+pub fn from_config(cfg: &config::Email) -> BasicResult<Arc<dyn Transport>> {
+    let from = cfg
+        .from
+        .parse::<Mailbox>()
+        .map_err(|err| business_error!(format!("invalid sender: {}", err)))?;
+
+    let res: Arc<dyn Transport> = match &cfg.transport {
+        config::EmailTransport::Smtp {
+            username,
+            password,
+            relay,
+            port,
+        } => Arc::new(SmtpTransport::new(from, username, password, relay, *port)?),
+        config::EmailTransport::File { dir } => Arc::new(FileTransport::new(from, dir)),
+        config::EmailTransport::Console => Arc::new(ConsoleTransport),
+    };
+    Ok(res)
+}
+
+/// build a message with both the text and the html part
+pub fn message(
+    from: &Mailbox,
+    to: &str,
+    mail: &Rendered,
+    request_id: Option<&str>,
+) -> BasicResult<Message> {
+    let to = to
+        .parse::<Mailbox>()
+        .map_err(|err| validate_error!(format!("invalid email: {}", err)))?;
+
+    let mut builder = Message::builder()
+        .from(from.clone())
+        .to(to)
+        .subject(mail.subject.clone());
+    if let Some(x) = request_id {
+        builder = builder.header(XRequestId(x.to_string()));
+    }
+    builder
+        .multipart(MultiPart::alternative_plain_html(
+            mail.text.clone(),
+            mail.html.clone(),
+        ))
+        .map_err(|err| business_error!(format!("build email err: {}", err)))
+}
+
+pub struct SmtpTransport {
+    from: Mailbox,
+    mailer: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpTransport {
+    pub fn new(
+        from: Mailbox,
+        username: &str,
+        password: &str,
+        relay: &str,
+        port: u16,
+    ) -> BasicResult<Self> {
+        let mailer = AsyncSmtpTransport::<Tokio1Executor>::relay(relay)
+            .map_err(|err| business_error!(format!("init email err: {}", err)))?
+            .port(port)
+            .credentials(Credentials::new(username.to_string(), password.to_string()))
+            .build();
+        Ok(Self { from, mailer })
+    }
+}
+
+#[async_trait]
+impl Transport for SmtpTransport {
+    async fn send(&self, to: &str, mail: &Rendered, request_id: Option<&str>) -> BasicResult<()> {
+        let message = message(&self.from, to, mail, request_id)?;
+        self.mailer
+            .send(message)
+            .await
+            .map_err(|err| business_error!(format!("send email err: {}", err)))?;
+        Ok(())
+    }
+}
+
+/// write every email into a dir as an .eml file, which can be opened by any mail client
+pub struct FileTransport {
+    from: Mailbox,
+    dir: PathBuf,
+}
+
+impl FileTransport {
+    pub fn new(from: Mailbox, dir: impl Into<PathBuf>) -> Self {
+        Self {
+            from,
+            dir: dir.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl Transport for FileTransport {
+    async fn send(&self, to: &str, mail: &Rendered, request_id: Option<&str>) -> BasicResult<()> {
+        let message = message(&self.from, to, mail, request_id)?;
+        std::fs::create_dir_all(&self.dir)?;
+        // sortable by the time they are sent
+        let filename = format!(
+            "{}_{}.eml",
+            chrono::Utc::now().format("%Y%m%d%H%M%S%3f"),
+            uuid::Uuid::new_v4()
+        );
+        std::fs::write(self.dir.join(filename), message.formatted())?;
+        Ok(())
+    }
+}
+
+/// print every email to the log instead of sending it, for development only
+///
+/// the codes and the links are masked, they would be usable by anyone reading the log
+pub struct ConsoleTransport;
+
+/// mask the numbers of 4 digits or more, and the links but their origins
+fn mask(text: &str) -> BasicResult<String> {
+    let reg = Regex::new(r#"(https?://[^/?#\s]+)\S*|[0-9]{4,}"#)?;
+    let res = reg.replace_all(text, |caps: &fancy_regex::Captures| match caps.get(1) {
+        Some(origin) => format!("{}/******", origin.as_str()),
+        None => "******".to_string(),
+    });
+    Ok(res.into_owned())
+}
+
+#[async_trait]
+impl Transport for ConsoleTransport {
+    async fn send(&self, to: &str, mail: &Rendered, _: Option<&str>) -> BasicResult<()> {
+        log::info!(
+            "email to: {}\nsubject: {}\n{}",
+            to,
+            mail.subject,
+            mask(&mail.text)?
+        );
+        Ok(())
+    }
+}
+
+/// keep the emails in memory instead of sending them, for tests
+#[derive(Default)]
+pub struct MemoryTransport {
+    sent: Mutex<Vec<(String, Rendered)>>,
+}
+
+impl MemoryTransport {
+    /// the receivers and the emails sent so far
+    pub fn sent(&self) -> Vec<(String, Rendered)> {
+        self.sent.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl Transport for MemoryTransport {
+    async fn send(&self, to: &str, mail: &Rendered, _: Option<&str>) -> BasicResult<()> {
+        self.sent
+            .lock()
+            .unwrap()
+            .push((to.to_string(), mail.clone()));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::email::template::{self, Template};
+
+    #[tokio::test]
+    async fn test_memory_transport() {
+        let transport = MemoryTransport::default();
+        let vars = [("code", "123456"), ("minutes", "10")];
+        let mail = template::render(Template::Register, None, &vars).unwrap();
+        transport.send("tom@evolve.com", &mail, None).await.unwrap();
+
+        let sent = transport.sent();
+        assert_eq!(1, sent.len());
+        assert_eq!("tom@evolve.com", sent[0].0);
+        assert!(sent[0].1.text.contains("123456"));
+    }
+
+    #[test]
+    fn test_mask() {
+        let vars = [("name", "Tom"), ("code", "123456"), ("minutes", "10")];
+        let mail = template::render(Template::ChangePwd, None, &vars).unwrap();
+        let text = mask(&mail.text).unwrap();
+        assert!(!text.contains("123456"));
+        assert!(text.contains("10 minutes"));
+
+        let link = "http://127.0.0.1:8881/reset_pwd?token=a.b.c&x=1";
+        let vars = [("name", "Tom"), ("link", link), ("minutes", "10")];
+        let mail = template::render(Template::ResetPwd, None, &vars).unwrap();
+        let text = mask(&mail.text).unwrap();
+        assert!(!text.contains("token"));
+        assert!(text.contains("http://127.0.0.1:8881/******"));
+    }
+
+    #[tokio::test]
+    async fn test_file_transport() {
+        let dir = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        let from = "evolve <noreply@evolve.local>".parse().unwrap();
+        let transport = FileTransport::new(from, &dir);
+        let vars = [("code", "123456"), ("minutes", "10")];
+        let mail = template::render(Template::Register, None, &vars).unwrap();
+        transport
+            .send("tom@evolve.com", &mail, Some("3f2a"))
+            .await
+            .unwrap();
+
+        let files: Vec<_> = std::fs::read_dir(&dir).unwrap().collect();
+        assert_eq!(1, files.len());
+        let content = std::fs::read_to_string(files[0].as_ref().unwrap().path()).unwrap();
+        assert!(content.contains("To: tom@evolve.com"));
+        assert!(content.contains("X-Request-Id: 3f2a"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,177 @@
+use crate::config;
+use crate::dao::redis::lua_script;
+#[cfg(feature = "ws")]
+use crate::ws::server::ChatServerHandle;
+use actix_web::{get, HttpRequest, HttpResponse};
+use redis::aio::ConnectionLike;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::time::{Duration, Instant};
+use util_error::{business_error, BasicResult};
+
+#[macro_export]
+macro_rules! serve_health {
+    ($app: expr) => {
+        $app = $app.service(health::live).service(health::ready);
+    };
+    ($app: expr, $cmd_tx:expr) => {
+        $app = $app
+            .app_data(actix_web::web::Data::new($cmd_tx.clone()))
+            .service(health::live)
+            .service(health::ready);
+    };
+}
+
+#[derive(Serialize, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+enum Status {
+    Up,
+    Down,
+}
+
+#[derive(Serialize)]
+struct Component {
+    status: Status,
+    latency_ms: u128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Readiness {
+    status: Status,
+    components: BTreeMap<&'static str, Component>,
+}
+
+impl Readiness {
+    fn new(components: BTreeMap<&'static str, Component>) -> Self {
+        let status = match components.values().all(|x| x.status == Status::Up) {
+            true => Status::Up,
+            false => Status::Down,
+        };
+        Self { status, components }
+    }
+}
+
+/// run the check, it is down when it fails or does not answer in time
+async fn check<F>(timeout: Duration, fut: F) -> Component
+where
+    F: Future<Output = BasicResult<()>>,
+{
+    let start = Instant::now();
+    let res = tokio::time::timeout(timeout, fut).await;
+    let latency_ms = start.elapsed().as_millis();
+    let error = match res {
+        Ok(Ok(())) => None,
+        Ok(Err(err)) => Some(err.to_string()),
+        Err(_) => Some(format!("no answer in {}ms", timeout.as_millis())),
+    };
+    Component {
+        status: match error {
+            None => Status::Up,
+            Some(_) => Status::Down,
+        },
+        latency_ms,
+        error,
+    }
+}
+
+async fn postgres() -> BasicResult<()> {
+    sqlx::query("select 1")
+        .execute(util_postgres::conn().await)
+        .await?;
+    Ok(())
+}
+
+async fn redis() -> BasicResult<()> {
+    util_redis::conn()
+        .await?
+        .req_packed_command(&redis::cmd("PING"))
+        .await?;
+    Ok(())
+}
+
+async fn meilisearch() -> BasicResult<()> {
+    util_meilisearch::client().health().await?;
+    Ok(())
+}
+
+#[cfg(feature = "ws")]
+async fn ws(req: &HttpRequest) -> BasicResult<()> {
+    let Some(chat_server) = req.app_data::<actix_web::web::Data<ChatServerHandle>>() else {
+        return business_error!("ws server is not registered").into();
+    };
+    if !chat_server.ping().await {
+        return business_error!("ws server loop is not running").into();
+    }
+    Ok(())
+}
+
+/// the ws server loop of this node, none when it is built without ws
+#[cfg(feature = "ws")]
+async fn ws_component(timeout: Duration, req: &HttpRequest) -> Option<Component> {
+    Some(check(timeout, ws(req)).await)
+}
+
+#[cfg(not(feature = "ws"))]
+async fn ws_component(_: Duration, _: &HttpRequest) -> Option<Component> {
+    None
+}
+
+/// the process is up, nothing else is checked so a restart is only asked when it hangs
+#[get("/health/live")]
+pub async fn live() -> HttpResponse {
+    HttpResponse::Ok().json(BTreeMap::from([("status", Status::Up)]))
+}
+
+/// every component this node needs to serve requests, 503 when one of them is down
+#[get("/health/ready")]
+pub async fn ready(req: HttpRequest) -> HttpResponse {
+    let timeout = Duration::from_millis(config::cfg().health.timeout_ms);
+    let (postgres, redis, meilisearch, lua_scripts, ws) = tokio::join!(
+        check(timeout, postgres()),
+        check(timeout, redis()),
+        check(timeout, meilisearch()),
+        check(timeout, lua_script::check()),
+        ws_component(timeout, &req),
+    );
+    let mut components = BTreeMap::from([
+        ("postgres", postgres),
+        ("redis", redis),
+        ("meilisearch", meilisearch),
+        ("lua_scripts", lua_scripts),
+    ]);
+    if let Some(ws) = ws {
+        components.insert("ws", ws);
+    }
+
+    let readiness = Readiness::new(components);
+    match readiness.status {
+        Status::Up => HttpResponse::Ok().json(readiness),
+        Status::Down => HttpResponse::ServiceUnavailable().json(readiness),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_web::test]
+    async fn test_check() {
+        let timeout = Duration::from_millis(50);
+        assert_eq!(check(timeout, async { Ok(()) }).await.status, Status::Up);
+
+        let failed = check(timeout, async { Err(business_error!("refused")) }).await;
+        assert_eq!(failed.status, Status::Down);
+        assert!(failed.error.is_some());
+
+        let slow = check(timeout, async {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            Ok(())
+        })
+        .await;
+        assert_eq!(slow.status, Status::Down);
+        assert_eq!(slow.error.unwrap(), "no answer in 50ms");
+    }
+}
@@ -1,19 +1,32 @@
 use crate::config;
+use crate::email;
+use crate::metrics;
+use crate::telemetry;
 use crate::service::user as user_service;
 use dotenv::dotenv;
-use util_error::ErrorKind;
+use util_error::{business_error, ErrorKind};
 
 pub async fn init() -> Result<(), ErrorKind> {
     // env
     dotenv().ok();
 
+    // get config, report every problem before anything starts
+    let cfg = config::init().map_err(|err| {
+        eprintln!("{}", err);
+        business_error!("invalid config")
+    })?;
+
     // init log
     log4rs::init_file("log4rs.yml", Default::default())?;
 
-    // get config
-    let cfg = config::cfg();
+    // init tracing
+    telemetry::init(&cfg)?;
+
+    // init metrics
+    metrics::init();
 
-    // init pg
+    // init pg, util_postgres takes the url from env
+    std::env::set_var("DATABASE_URL", &cfg.postgres.url);
     util_postgres::init();
 
     // init redis
@@ -28,14 +41,11 @@ pub async fn init() -> Result<(), ErrorKind> {
     // init meilisearch
     util_meilisearch::init(&cfg.meilisearch.address, &cfg.meilisearch.api_key).await;
 
+    // reload config on SIGHUP or when the file changes
+    config::watch();
+
     // init email
-    util_email::init(
-        &cfg.email.username,
-        &cfg.email.password,
-        &cfg.email.relay,
-        cfg.email.port,
-    )
-    .await;
+    email::init()?;
 
     // load user search data
     user_service::load_search().await?;
@@ -0,0 +1,61 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// the fields added to every log line, shown by `{X(name)}` in the patterns of log4rs.yml and
+/// under `mdc` by the json encoder
+pub type Fields = Vec<(&'static str, String)>;
+
+/// put the fields into the log context until the guard is dropped
+pub fn enter(fields: &Fields) -> log_mdc::ExtendGuard {
+    log_mdc::extend_scoped(fields.iter().map(|(k, v)| (*k, v.as_str())))
+}
+
+/// the value of a field in the current log context
+pub fn get(name: &str) -> Option<String> {
+    log_mdc::get(name, |x| x.map(str::to_string))
+}
+
+/// a future which logs with its fields
+///
+/// the log context is thread local and a worker thread polls many futures in turn, so the fields
+/// are put in on every poll and taken out after it
+pub struct Scoped<F> {
+    fields: Fields,
+    fut: Pin<Box<F>>,
+}
+
+impl<F: Future> Future for Scoped<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let _guard = enter(&this.fields);
+        this.fut.as_mut().poll(cx)
+    }
+}
+
+/// run the future with the fields in its log context, e.g. everything a request does, the
+/// postgres, redis and meilisearch calls included, logs with its request id
+pub fn scope<F: Future>(fields: Fields, fut: F) -> Scoped<F> {
+    Scoped {
+        fields,
+        fut: Box::pin(fut),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_scope() {
+        let fields = vec![("request_id", "abc".to_string())];
+        let inner = scope(fields, async {
+            tokio::task::yield_now().await;
+            get("request_id")
+        });
+        assert_eq!(Some("abc".to_string()), inner.await);
+        assert_eq!(None, get("request_id"));
+    }
+}
@@ -3,13 +3,20 @@
 mod api;
 mod config;
 mod dao;
+mod email;
+mod health;
 mod init;
+mod log_context;
+mod metrics;
 mod middleware;
 mod model;
 mod openapi;
 mod service;
 mod session;
+mod shutdown;
 mod static_file;
+mod telemetry;
+mod tls;
 mod upload_file;
 mod ws;
 
@@ -29,11 +36,19 @@ async fn main() -> Result<(), ErrorKind> {
     let cmd_tx = init_ws!();
     #[cfg(feature = "ws")]
     let cmd_tx_for_req = cmd_tx.clone();
-    HttpServer::new(move || {
+    let server = HttpServer::new(move || {
         let mut app = App::new()
-            .wrap(middleware::logger::logger())
-            .wrap(middleware::cors::cors());
+            .wrap(middleware::security_headers::SecurityHeaders)
+            .wrap(middleware::trace::Trace)
+            .wrap(middleware::metrics::Metrics)
+            .wrap(middleware::request_id::RequestId)
+            .wrap(middleware::logger::logger());
 
+        app = app.configure(metrics::configure);
+        #[cfg(feature = "ws")]
+        serve_health!(app, cmd_tx_for_req);
+        #[cfg(not(feature = "ws"))]
+        serve_health!(app);
         serve_api!(app);
         serve_openapi!(app);
         #[cfg(feature = "ws")]
@@ -43,15 +58,53 @@ async fn main() -> Result<(), ErrorKind> {
         #[cfg(feature = "upload_file")]
         serve_upload_file!(app);
         app
-    })
-    .bind((config::cfg().host.as_str(), config::cfg().port))?
-    .run()
-    .await
-    .unwrap();
+    });
+
+    let cfg = config::cfg();
+    // the signals are handled by `shutdown`, which stops every server in order
+    let server = server
+        .disable_signals()
+        .shutdown_timeout(cfg.shutdown.timeout);
+    let mut servers = Vec::new();
+    match cfg.tls.enabled {
+        true => {
+            let acceptor = tls::acceptor(&cfg.tls)?;
+            servers.push(
+                server
+                    .bind_openssl((cfg.host.as_str(), cfg.tls.port), acceptor)?
+                    .run(),
+            );
+            if cfg.tls.redirect {
+                servers.push(tls::redirect_server(&cfg)?);
+            }
+        }
+        _ => servers.push(server.bind((cfg.host.as_str(), cfg.port))?.run()),
+    }
+    if cfg.metrics.enabled && cfg.metrics.port != 0 {
+        servers.push(metrics::server(&cfg)?);
+    }
+    let handles: Vec<_> = servers.iter().map(|x| x.handle()).collect();
+    let running = futures::future::try_join_all(servers);
+    tokio::pin!(running);
+    tokio::select! {
+        res = &mut running => {
+            res?;
+        }
+        _ = shutdown::wait() => {
+            let close_sessions = async {
+                #[cfg(feature = "ws")]
+                cmd_tx.shutdown().await;
+            };
+            // the servers are polled until they are drained
+            let (res, _) = tokio::join!(&mut running, shutdown::graceful(&handles, close_sessions));
+            res?;
+        }
+    }
 
     #[cfg(feature = "ws")]
     cmd_tx.close().await;
 
+    telemetry::shutdown();
     log::info!("server stoped");
     Ok(())
 }
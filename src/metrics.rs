@@ -0,0 +1,198 @@
+use crate::config;
+use actix_web::{dev::Server, get, http::header, web, App, HttpRequest, HttpResponse, HttpServer};
+use lazy_static::lazy_static;
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge,
+    IntGaugeVec, Opts, Registry, TextEncoder,
+};
+use redis::aio::ConnectionLike;
+use std::time::{Duration, Instant};
+
+lazy_static! {
+    pub static ref REGISTRY: Registry = Registry::new();
+    pub static ref HTTP_REQUESTS: IntCounterVec = register(IntCounterVec::new(
+        Opts::new("http_requests_total", "requests by route and status"),
+        &["method", "route", "status"]
+    ));
+    pub static ref HTTP_REQUEST_DURATION: HistogramVec = register(HistogramVec::new(
+        HistogramOpts::new(
+            "http_request_duration_seconds",
+            "latency by route and status"
+        ),
+        &["method", "route", "status"]
+    ));
+    pub static ref WS_SESSIONS: IntGauge = register(IntGauge::new(
+        "ws_sessions",
+        "websocket sessions connected to this node"
+    ));
+    pub static ref WS_ROOMS: IntGauge = register(IntGauge::new(
+        "ws_rooms",
+        "rooms with a session on this node"
+    ));
+    pub static ref HUB_MESSAGES_PUBLISHED: IntCounter = register(IntCounter::new(
+        "hub_messages_published_total",
+        "messages published to the redis hub"
+    ));
+    pub static ref HUB_MESSAGES_RECEIVED: IntCounter = register(IntCounter::new(
+        "hub_messages_received_total",
+        "messages received from the redis hub"
+    ));
+    pub static ref PG_POOL_CONNECTIONS: IntGaugeVec = register(IntGaugeVec::new(
+        Opts::new("pg_pool_connections", "postgres pool connections by state"),
+        &["state"]
+    ));
+    pub static ref REDIS_UP: IntGauge = register(IntGauge::new(
+        "redis_up",
+        "1 if redis answered the last ping"
+    ));
+    pub static ref REDIS_PING_DURATION: Histogram = register(Histogram::with_opts(
+        HistogramOpts::new("redis_ping_duration_seconds", "latency of the redis pings")
+    ));
+    pub static ref MEILISEARCH_TASK_DURATION: HistogramVec = register(HistogramVec::new(
+        HistogramOpts::new(
+            "meilisearch_task_duration_seconds",
+            "time until a meilisearch task is completed"
+        ),
+        &["index", "operation"]
+    ));
+    pub static ref EMAILS: IntCounterVec = register(IntCounterVec::new(
+        Opts::new("emails_total", "email deliveries by result"),
+        &["result"]
+    ));
+}
+
+/// add the metric to the registry, the names are fixed so it can only fail on a typo
+fn register<T>(metric: prometheus::Result<T>) -> T
+where
+    T: prometheus::core::Collector + Clone + 'static,
+{
+    let metric = metric.unwrap();
+    REGISTRY.register(Box::new(metric.clone())).unwrap();
+    metric
+}
+
+/// register every metric, so a scrape shows them before they are first touched
+pub fn init() {
+    lazy_static::initialize(&HTTP_REQUESTS);
+    lazy_static::initialize(&HTTP_REQUEST_DURATION);
+    lazy_static::initialize(&WS_SESSIONS);
+    lazy_static::initialize(&WS_ROOMS);
+    lazy_static::initialize(&HUB_MESSAGES_PUBLISHED);
+    lazy_static::initialize(&HUB_MESSAGES_RECEIVED);
+    lazy_static::initialize(&PG_POOL_CONNECTIONS);
+    lazy_static::initialize(&REDIS_UP);
+    lazy_static::initialize(&REDIS_PING_DURATION);
+    lazy_static::initialize(&MEILISEARCH_TASK_DURATION);
+    lazy_static::initialize(&EMAILS);
+}
+
+/// the gauges which are read from postgres and redis on every scrape
+async fn refresh() {
+    let pool = util_postgres::conn().await;
+    let idle = pool.num_idle() as i64;
+    PG_POOL_CONNECTIONS.with_label_values(&["idle"]).set(idle);
+    PG_POOL_CONNECTIONS
+        .with_label_values(&["active"])
+        .set(pool.size() as i64 - idle);
+
+    // a redis which does not answer must not hold the scrape
+    let timeout = Duration::from_millis(config::cfg().health.timeout_ms);
+    let start = Instant::now();
+    let ping = tokio::time::timeout(timeout, async {
+        match util_redis::conn().await {
+            Ok(mut conn) => conn.req_packed_command(&redis::cmd("PING")).await.is_ok(),
+            Err(_) => false,
+        }
+    })
+    .await
+    .unwrap_or(false);
+    REDIS_PING_DURATION.observe(start.elapsed().as_secs_f64());
+    REDIS_UP.set(ping as i64);
+}
+
+/// only a scraper with the token in config gets the metrics, when a token is set
+fn authorized(req: &HttpRequest, token: &str) -> bool {
+    if token.is_empty() {
+        return true;
+    }
+    req.headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|x| x.to_str().ok())
+        .and_then(|x| x.strip_prefix("Bearer "))
+        .is_some_and(|x| constant_time_eq(x.as_bytes(), token.as_bytes()))
+}
+
+/// compare every byte, so the time taken does not tell how much of the token matched
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[get("/metrics")]
+pub async fn metrics(req: HttpRequest) -> HttpResponse {
+    if !authorized(&req, &config::cfg().metrics.token) {
+        return HttpResponse::Unauthorized().finish();
+    }
+    refresh().await;
+
+    let mut buf = Vec::new();
+    let encoder = TextEncoder::new();
+    if let Err(err) = encoder.encode(&REGISTRY.gather(), &mut buf) {
+        log::error!("encode metrics err: {}", err);
+        return HttpResponse::InternalServerError().finish();
+    }
+    HttpResponse::Ok()
+        .content_type(encoder.format_type())
+        .body(buf)
+}
+
+/// the listener on the admin port which serves `/metrics` only
+pub fn server(cfg: &config::Config) -> std::io::Result<Server> {
+    let server = HttpServer::new(|| App::new().service(metrics))
+        .workers(1)
+        .disable_signals()
+        .shutdown_timeout(cfg.shutdown.timeout)
+        .bind((cfg.metrics.host.as_str(), cfg.metrics.port))?
+        .run();
+    Ok(server)
+}
+
+/// serve `/metrics` on the main port, when it has no port of its own
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    let config = config::cfg();
+    if config.metrics.enabled && config.metrics.port == 0 {
+        cfg.service(metrics);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[test]
+    fn test_authorized() {
+        let req = TestRequest::default()
+            .insert_header((header::AUTHORIZATION, "Bearer s3cret"))
+            .to_http_request();
+        assert!(authorized(&req, "s3cret"));
+        assert!(!authorized(&req, "other"));
+        assert!(authorized(&TestRequest::default().to_http_request(), ""));
+        assert!(!authorized(
+            &TestRequest::default().to_http_request(),
+            "s3cret"
+        ));
+        assert!(!authorized(&req, "s3cre"));
+        assert!(!authorized(&req, "s3cret!"));
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"s3cret", b"s3cret"));
+        assert!(!constant_time_eq(b"s3cret", b"s3creT"));
+        assert!(!constant_time_eq(b"s3cret", b"s3cre"));
+        assert!(constant_time_eq(b"", b""));
+    }
+}
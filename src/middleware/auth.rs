@@ -3,6 +3,7 @@ use std::future::{ready, Ready};
 use crate::service::user as user_service;
 
 use actix_web::{
+    body::EitherBody,
     dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
     http::header::{HeaderName, HeaderValue},
     Error,
@@ -25,7 +26,7 @@ where
     S::Future: 'static,
     B: 'static,
 {
-    type Response = ServiceResponse<B>;
+    type Response = ServiceResponse<EitherBody<B>>;
     type Error = Error;
     type InitError = ();
     type Transform = AuthMiddleware<S>;
@@ -40,13 +41,25 @@ pub struct AuthMiddleware<S> {
     service: S,
 }
 
+/// answer with the error as a response, so the outer middlewares still add their headers to it
+fn reject<B>(
+    req: ServiceRequest,
+    err: impl Into<Error>,
+) -> LocalBoxFuture<'static, Result<ServiceResponse<EitherBody<B>>, Error>>
+where
+    B: 'static,
+{
+    let res = req.error_response(err).map_into_right_body();
+    Box::pin(async move { Ok(res) })
+}
+
 impl<S, B> Service<ServiceRequest> for AuthMiddleware<S>
 where
     S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
     S::Future: 'static,
     B: 'static,
 {
-    type Response = ServiceResponse<B>;
+    type Response = ServiceResponse<EitherBody<B>>;
     type Error = Error;
     type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
 
@@ -69,15 +82,15 @@ where
 
                             Box::pin(async move {
                                 let res = fut.await?;
-                                Ok(res)
+                                Ok(res.map_into_left_body())
                             })
                         }
-                        Err(err) => Box::pin(async move { Err(err.into()) }),
+                        Err(err) => reject(req, err),
                     }
                 }
-                Err(err) => Box::pin(async move { Err(unauthorized!(err).into()) }),
+                Err(err) => reject(req, unauthorized!(err)),
             },
-            None => Box::pin(async move { Err(unauthorized!("unauthorized").into()) }),
+            None => reject(req, unauthorized!("unauthorized")),
         }
     }
 }
@@ -1,11 +1,91 @@
+use crate::config;
 use actix_cors::Cors;
+use actix_web::http::{header::HeaderName, Method};
 
-pub fn cors() -> Cors {
-    Cors::default()
-        .allow_any_header()
-        .allow_any_method()
-        .allow_any_origin()
-        .expose_any_header()
-        // .supports_credentials()
-        .max_age(3600)
+/// whether the origin is allowed by a pattern, `*` allows any and `https://*.evolve.com` allows
+/// every subdomain of evolve.com but not evolve.com itself
+fn origin_matches(pattern: &str, origin: &str) -> bool {
+    if pattern == "*" {
+        return true;
+    }
+    let (pattern, origin) = (pattern.to_ascii_lowercase(), origin.to_ascii_lowercase());
+    match pattern.split_once("*.") {
+        Some((scheme, domain)) => origin
+            .strip_prefix(scheme)
+            .and_then(|x| x.strip_suffix(domain))
+            .and_then(|x| x.strip_suffix('.'))
+            .is_some_and(|x| {
+                !x.is_empty()
+                    && x.chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+            }),
+        None => pattern == origin,
+    }
+}
+
+/// the cors of a scope, `policy` picks its policy from config
+///
+/// the allowed origins are taken from the config on every request, so they follow a reload
+pub fn cors(policy: fn(&config::Cors) -> &config::CorsPolicy) -> Cors {
+    let config = config::cfg();
+    let cfg = policy(&config.cors);
+
+    let mut res = Cors::default()
+        .allowed_origin_fn(move |origin, _| {
+            let (config, origin) = (config::cfg(), origin.to_str().unwrap_or_default());
+            policy(&config.cors)
+                .allowed_origins
+                .iter()
+                .any(|x| origin_matches(x, origin))
+        })
+        .allowed_methods(
+            cfg.allowed_methods
+                .iter()
+                .filter_map(|x| x.parse::<Method>().ok()),
+        )
+        .allowed_headers(
+            cfg.allowed_headers
+                .iter()
+                .filter_map(|x| x.parse::<HeaderName>().ok()),
+        )
+        .max_age(cfg.max_age);
+
+    if !cfg.exposed_headers.is_empty() {
+        res = res.expose_headers(
+            cfg.exposed_headers
+                .iter()
+                .filter_map(|x| x.parse::<HeaderName>().ok()),
+        );
+    }
+    if cfg.supports_credentials {
+        res = res.supports_credentials();
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_origin_matches() {
+        assert!(origin_matches("*", "https://evil.com"));
+        assert!(origin_matches(
+            "http://127.0.0.1:8881",
+            "http://127.0.0.1:8881"
+        ));
+        assert!(!origin_matches(
+            "http://127.0.0.1:8881",
+            "http://127.0.0.1:8882"
+        ));
+
+        let pattern = "https://*.evolve.com";
+        assert!(origin_matches(pattern, "https://app.evolve.com"));
+        assert!(origin_matches(pattern, "https://a.b.Evolve.com"));
+        assert!(!origin_matches(pattern, "https://evolve.com"));
+        assert!(!origin_matches(pattern, "http://app.evolve.com"));
+        assert!(!origin_matches(pattern, "https://app.evolve.com.evil.com"));
+        assert!(!origin_matches(pattern, "https://evilevolve.com"));
+        assert!(!origin_matches(pattern, "https://evil.com/.evolve.com"));
+    }
 }
@@ -1,5 +1,6 @@
 use actix_web::middleware;
 
+/// the default format led by the request id, which is set on the response by `request_id`
 pub fn logger() -> middleware::Logger {
-    middleware::Logger::default()
+    middleware::Logger::new(r#"%{x-request-id}o %a "%r" %s %b "%{Referer}i" "%{User-Agent}i" %T"#)
 }
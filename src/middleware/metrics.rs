@@ -0,0 +1,71 @@
+use std::future::{ready, Ready};
+use std::time::Instant;
+
+use crate::metrics;
+
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    Error,
+};
+use futures_util::future::LocalBoxFuture;
+
+/// count every request and observe its latency by method, route and status
+pub struct Metrics;
+
+impl<S, B> Transform<S, ServiceRequest> for Metrics
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = MetricsMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(MetricsMiddleware { service }))
+    }
+}
+
+pub struct MetricsMiddleware<S> {
+    service: S,
+}
+
+impl<S, B> Service<ServiceRequest> for MetricsMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        // the pattern, not the path, keeps the number of series bounded
+        let route = req
+            .match_pattern()
+            .unwrap_or_else(|| "unmatched".to_string());
+        let method = req.method().to_string();
+        let start = Instant::now();
+        let fut = self.service.call(req);
+
+        Box::pin(async move {
+            let res = fut.await;
+            let status = match &res {
+                Ok(res) => res.status(),
+                Err(err) => err.as_response_error().status_code(),
+            };
+            let labels = [method.as_str(), route.as_str(), status.as_str()];
+            metrics::HTTP_REQUESTS.with_label_values(&labels).inc();
+            metrics::HTTP_REQUEST_DURATION
+                .with_label_values(&labels)
+                .observe(start.elapsed().as_secs_f64());
+            res
+        })
+    }
+}
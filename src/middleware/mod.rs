@@ -1,3 +1,7 @@
 pub mod auth;
 pub mod logger;
-pub mod cors;
\ No newline at end of file
+pub mod metrics;
+pub mod request_id;
+pub mod cors;
+pub mod security_headers;
+pub mod trace;
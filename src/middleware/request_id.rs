@@ -0,0 +1,152 @@
+use std::future::{ready, Ready};
+
+use crate::log_context;
+
+use actix_web::{
+    body::{self, BoxBody, MessageBody},
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::{self, HeaderName, HeaderValue},
+    web::Bytes,
+    Error,
+};
+use futures_util::future::LocalBoxFuture;
+
+pub const X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// the name of the request id in the log context
+pub const FIELD: &str = "request_id";
+
+/// take the `X-Request-Id` of the request or generate one, log everything done for the request
+/// with it and send it back in the response, and in the body of a json error as `request_id`
+///
+/// an error returned by an inner middleware skips the header, return `req.error_response` there
+pub struct RequestId;
+
+impl<S, B> Transform<S, ServiceRequest> for RequestId
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RequestIdMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestIdMiddleware { service }))
+    }
+}
+
+pub struct RequestIdMiddleware<S> {
+    service: S,
+}
+
+/// the incoming id is kept if it is short and plain, so it is safe in a log line and a header
+fn request_id(incoming: Option<&HeaderValue>) -> String {
+    incoming
+        .and_then(|x| x.to_str().ok())
+        .filter(|x| !x.is_empty() && x.len() <= 128)
+        .filter(|x| {
+            x.chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        })
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().simple().to_string())
+}
+
+/// the id of the request being handled
+pub fn current() -> Option<String> {
+    log_context::get(FIELD)
+}
+
+/// add `request_id` to a json object, none when the body is not one
+fn with_request_id(body: &[u8], id: &str) -> Option<Bytes> {
+    let mut value = serde_json::from_slice::<serde_json::Value>(body).ok()?;
+    value
+        .as_object_mut()?
+        .insert("request_id".into(), serde_json::Value::from(id));
+    serde_json::to_vec(&value).ok().map(Bytes::from)
+}
+
+/// the error responses tell the id, so a client can report it
+async fn error_body<B>(res: ServiceResponse<B>, id: &str) -> ServiceResponse<BoxBody>
+where
+    B: MessageBody + 'static,
+{
+    let is_json = res
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|x| x.as_bytes().starts_with(b"application/json"));
+    if !is_json || !(res.status().is_client_error() || res.status().is_server_error()) {
+        return res.map_into_boxed_body();
+    }
+    let (req, res) = res.into_parts();
+    let (res, body) = res.into_parts();
+    let body = match body::to_bytes(body).await {
+        Ok(x) => with_request_id(&x, id).unwrap_or(x),
+        Err(_) => Bytes::new(),
+    };
+    ServiceResponse::new(req, res.set_body(body).map_into_boxed_body())
+}
+
+impl<S, B> Service<ServiceRequest> for RequestIdMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let id = request_id(req.headers().get(X_REQUEST_ID));
+        let fields = vec![(FIELD, id.clone())];
+        let fut = {
+            // the inner middlewares may log before their futures are polled
+            let _guard = log_context::enter(&fields);
+            self.service.call(req)
+        };
+
+        Box::pin(log_context::scope(fields, async move {
+            let mut res = error_body(fut.await?, &id).await;
+            // checked in `request_id`, or generated
+            let value = HeaderValue::from_str(&id).unwrap();
+            res.headers_mut().insert(X_REQUEST_ID, value);
+            Ok(res)
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_id() {
+        let incoming = HeaderValue::from_static("3f2a-b.c_d");
+        assert_eq!("3f2a-b.c_d", request_id(Some(&incoming)));
+
+        let generated = request_id(None);
+        assert_eq!(32, generated.len());
+
+        for bad in ["", "a b", "a\"b", &"a".repeat(129)] {
+            let incoming = HeaderValue::from_str(bad).unwrap();
+            assert_ne!(bad, request_id(Some(&incoming)));
+        }
+    }
+
+    #[test]
+    fn test_with_request_id() {
+        let res = with_request_id(br#"{"msg":"invalid email","code":400}"#, "3f2a").unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&res).unwrap();
+        assert_eq!("3f2a", value["request_id"]);
+        assert_eq!("invalid email", value["msg"]);
+        assert!(with_request_id(b"[1]", "3f2a").is_none());
+        assert!(with_request_id(b"not json", "3f2a").is_none());
+    }
+}
@@ -0,0 +1,153 @@
+use std::future::{ready, Ready};
+
+use crate::config;
+
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::{self, HeaderName, HeaderValue},
+    Error,
+};
+use futures_util::future::LocalBoxFuture;
+
+/// add the security headers in config to every response, unless the handler has set them
+///
+/// the headers are taken from the config on every response, so they follow a reload
+pub struct SecurityHeaders;
+
+impl<S, B> Transform<S, ServiceRequest> for SecurityHeaders
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = SecurityHeadersMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(SecurityHeadersMiddleware { service }))
+    }
+}
+
+pub struct SecurityHeadersMiddleware<S> {
+    service: S,
+}
+
+/// the Content-Security-Policy of a path, the longest matching prefix in the overrides wins
+fn content_security_policy<'a>(cfg: &'a config::SecurityHeaders, path: &str) -> &'a str {
+    cfg.csp_overrides
+        .iter()
+        .filter(|(prefix, _)| path.starts_with(prefix.as_str()))
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map(|(_, csp)| csp.as_str())
+        .unwrap_or(&cfg.content_security_policy)
+}
+
+/// the headers of a response to the path, an empty setting leaves its header out
+fn headers(cfg: &config::SecurityHeaders, path: &str, https: bool) -> Vec<(HeaderName, String)> {
+    let mut res = Vec::new();
+    if !cfg.enabled {
+        return res;
+    }
+    // browsers ignore it over http
+    if https && cfg.hsts_max_age > 0 {
+        let mut hsts = format!("max-age={}", cfg.hsts_max_age);
+        if cfg.hsts_include_subdomains {
+            hsts.push_str("; includeSubDomains");
+        }
+        res.push((header::STRICT_TRANSPORT_SECURITY, hsts));
+    }
+    if cfg.content_type_options {
+        res.push((header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()));
+    }
+    let csp = content_security_policy(cfg, path);
+    for (name, value) in [
+        (header::X_FRAME_OPTIONS, cfg.frame_options.as_str()),
+        (header::REFERRER_POLICY, cfg.referrer_policy.as_str()),
+        (header::CONTENT_SECURITY_POLICY, csp),
+    ] {
+        if !value.is_empty() {
+            res.push((name, value.to_string()));
+        }
+    }
+    res
+}
+
+impl<S, B> Service<ServiceRequest> for SecurityHeadersMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let path = req.path().to_string();
+        let https = req.connection_info().scheme() == "https";
+        let fut = self.service.call(req);
+
+        Box::pin(async move {
+            let mut res = fut.await?;
+            let config = config::cfg();
+            let res_headers = res.headers_mut();
+            for (name, value) in headers(&config.security_headers, &path, https) {
+                if res_headers.contains_key(&name) {
+                    continue;
+                }
+                match HeaderValue::from_str(&value) {
+                    Ok(v) => {
+                        res_headers.insert(name, v);
+                    }
+                    Err(err) => log::error!("invalid {} header: {}", name, err),
+                }
+            }
+            Ok(res)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_headers() {
+        let cfg = config::SecurityHeaders::default();
+        let find = |headers: &[(HeaderName, String)], name| {
+            headers
+                .iter()
+                .find(|(k, _)| *k == name)
+                .map(|(_, v)| v.clone())
+        };
+
+        let api = headers(&cfg, "/api/login", false);
+        assert!(find(&api, header::STRICT_TRANSPORT_SECURITY).is_none());
+        assert_eq!(
+            Some(cfg.content_security_policy.clone()),
+            find(&api, header::CONTENT_SECURITY_POLICY)
+        );
+        assert_eq!(
+            Some("nosniff".to_string()),
+            find(&api, header::X_CONTENT_TYPE_OPTIONS)
+        );
+
+        let swagger = headers(&cfg, "/swagger/index.html", true);
+        assert!(find(&swagger, header::STRICT_TRANSPORT_SECURITY).is_some());
+        assert_eq!(
+            cfg.csp_overrides.get("/swagger").cloned(),
+            find(&swagger, header::CONTENT_SECURITY_POLICY)
+        );
+
+        let disabled = config::SecurityHeaders {
+            enabled: false,
+            ..Default::default()
+        };
+        assert!(headers(&disabled, "/api/login", true).is_empty());
+    }
+}
@@ -0,0 +1,133 @@
+use std::future::{ready, Ready};
+
+use crate::middleware::request_id;
+
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::HeaderMap,
+    Error,
+};
+use futures_util::future::LocalBoxFuture;
+use opentelemetry::{global, propagation::Extractor};
+use tracing::{field::Empty, Instrument};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// open a span for every request, the spans of the handler, the services and the dao calls are
+/// nested in it
+///
+/// the trace of the caller is continued when it sends `traceparent`
+pub struct Trace;
+
+impl<S, B> Transform<S, ServiceRequest> for Trace
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = TraceMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(TraceMiddleware { service }))
+    }
+}
+
+pub struct TraceMiddleware<S> {
+    service: S,
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl<'a> Extractor for HeaderExtractor<'a> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|x| x.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|x| x.as_str()).collect()
+    }
+}
+
+impl<S, B> Service<ServiceRequest> for TraceMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        // the pattern keeps the spans of e.g. /api/user/{id} together
+        let route = req
+            .match_pattern()
+            .unwrap_or_else(|| "unmatched".to_string());
+        let method = req.method().to_string();
+        let span = tracing::info_span!(
+            "http request",
+            otel.name = format!("{} {}", method, route),
+            otel.kind = "server",
+            otel.status_code = Empty,
+            http.request.method = method,
+            http.route = route,
+            url.path = req.path(),
+            http.response.status_code = Empty,
+            request_id = request_id::current().unwrap_or_default(),
+        );
+        let parent = global::get_text_map_propagator(|x| x.extract(&HeaderExtractor(req.headers())));
+        let _ = span.set_parent(parent);
+
+        let fut = span.in_scope(|| self.service.call(req));
+        let res_span = span.clone();
+        Box::pin(
+            async move {
+                let res = fut.await;
+                let status = match &res {
+                    Ok(res) => res.status(),
+                    Err(err) => err.as_response_error().status_code(),
+                };
+                res_span.record("http.response.status_code", status.as_u16());
+                if status.is_server_error() {
+                    res_span.record("otel.status_code", "ERROR");
+                }
+                res
+            }
+            .instrument(span),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::header::{HeaderName, HeaderValue};
+    use opentelemetry::{propagation::TextMapPropagator, trace::TraceContextExt};
+    use opentelemetry_sdk::propagation::TraceContextPropagator;
+
+    #[test]
+    fn test_extract_traceparent() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            HeaderName::from_static("traceparent"),
+            HeaderValue::from_static("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"),
+        );
+        let cx = TraceContextPropagator::new().extract(&HeaderExtractor(&headers));
+        let span = cx.span();
+        let span_context = span.span_context();
+        assert!(span_context.is_remote());
+        assert_eq!(
+            "4bf92f3577b34da6a3ce929d0e0e4736",
+            span_context.trace_id().to_string()
+        );
+        assert_eq!("00f067aa0ba902b7", span_context.span_id().to_string());
+
+        let cx = TraceContextPropagator::new().extract(&HeaderExtractor(&HeaderMap::new()));
+        assert!(!cx.span().span_context().is_valid());
+    }
+}
@@ -0,0 +1,94 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+#[derive(ToSchema, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ChatMessage {
+    pub id: i64,
+    pub room: String,
+    /// none when the sender has been deleted
+    pub user_id: Option<i64>,
+    /// the session id of the sender
+    pub from_id: String,
+    pub from_name: String,
+    pub content: String,
+    pub created_at: String,
+}
+
+/// a page of the history, the oldest message first
+#[derive(ToSchema, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ChatHistory {
+    pub messages: Vec<ChatMessage>,
+    /// `before` of the page of the older messages, none when there are no more
+    pub next: Option<i64>,
+}
+
+#[derive(ToSchema, Deserialize)]
+pub struct ChatHistoryResponse {
+    pub data: ChatHistory,
+}
+
+#[derive(IntoParams, Deserialize, Debug)]
+#[into_params(parameter_in = Query)]
+pub struct HistoryQuery {
+    /// the messages older than this id, the latest ones when none
+    pub before: Option<i64>,
+    /// how many messages, `chat.page_size` when none, at most `chat.max_page_size`
+    pub limit: Option<i64>,
+}
+
+#[derive(ToSchema, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct DirectMessage {
+    pub id: i64,
+    pub from_user_id: i64,
+    pub from_name: String,
+    pub to_user_id: i64,
+    pub content: String,
+    pub created_at: String,
+    /// none until the recipient has read it
+    pub read_at: Option<String>,
+}
+
+/// a page of the messages between two users, the oldest message first
+#[derive(ToSchema, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct DirectHistory {
+    pub messages: Vec<DirectMessage>,
+    /// `before` of the page of the older messages, none when there are no more
+    pub next: Option<i64>,
+}
+
+#[derive(ToSchema, Deserialize)]
+pub struct DirectHistoryResponse {
+    pub data: DirectHistory,
+}
+
+/// the direct messages with another user
+#[derive(ToSchema, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Conversation {
+    /// the other user
+    pub user_id: i64,
+    /// the name of the other user, the email when it has none
+    pub name: String,
+    /// the messages from the other user which have not been read
+    pub unread: i64,
+    pub last_message: DirectMessage,
+}
+
+#[derive(ToSchema, Deserialize)]
+pub struct ConversationListResponse {
+    pub data: Vec<Conversation>,
+}
+
+/// how far the user has read a room
+#[derive(ToSchema, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RoomUnread {
+    pub room: String,
+    /// none when the user has read nothing of the room
+    pub last_read_id: Option<i64>,
+    /// the messages of the others after `last_read_id`
+    pub unread: i64,
+}
+
+#[derive(ToSchema, Deserialize)]
+pub struct RoomUnreadListResponse {
+    pub data: Vec<RoomUnread>,
+}
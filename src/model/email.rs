@@ -0,0 +1,34 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[derive(ToSchema, sqlx::Type, Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[sqlx(type_name = "email_outbox_status")]
+pub enum OutboxStatus {
+    /// waiting for the first delivery or a retry
+    Pending,
+    Sent,
+    /// gave up after too many failed attempts
+    Dead,
+}
+
+#[derive(ToSchema, Serialize, Deserialize, Clone, Debug)]
+pub struct OutboxMail {
+    pub id: i64,
+    pub recipient: String,
+    pub subject: String,
+    pub status: OutboxStatus,
+    pub attempts: i32,
+    pub next_attempt_at: String,
+    pub last_error: Option<String>,
+    pub created_at: String,
+    pub updated_at: Option<String>,
+    pub sent_at: Option<String>,
+    /// the request which has queued the email
+    pub request_id: Option<String>,
+}
+
+#[derive(ToSchema, Deserialize)]
+pub struct OutboxMailListResponse {
+    pub data: Vec<OutboxMail>,
+    pub total: usize,
+}
@@ -1 +1,15 @@
-pub mod user;
\ No newline at end of file
+pub mod chat;
+pub mod email;
+pub mod room;
+pub mod user;
+
+use serde::{Deserialize, Deserializer};
+
+/// distinguish an explicit null (`Some(None)`) from an omitted field (`None`)
+fn deserialize_patch<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
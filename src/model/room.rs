@@ -0,0 +1,145 @@
+use super::deserialize_patch;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[derive(ToSchema, sqlx::Type, Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[sqlx(type_name = "room_visibility")]
+#[serde(rename_all = "snake_case")]
+pub enum RoomVisibility {
+    /// listed, everyone can join
+    Public,
+    /// listed, joined with an invitation
+    InviteOnly,
+    /// only seen by its members, joined with an invitation
+    Private,
+}
+
+/// the variants are in the order of their rank
+#[derive(
+    ToSchema, sqlx::Type, Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord,
+)]
+#[sqlx(type_name = "room_role")]
+#[serde(rename_all = "snake_case")]
+pub enum RoomRole {
+    Member,
+    Moderator,
+    Owner,
+}
+
+impl RoomRole {
+    /// whether it may kick, ban or mute a member with the role `target`
+    pub fn outranks(self, target: RoomRole) -> bool {
+        self >= RoomRole::Moderator && self > target
+    }
+}
+
+#[derive(ToSchema, Serialize, Deserialize, Clone, Debug)]
+pub struct Room {
+    /// the name the sessions join
+    pub id: String,
+    /// none for the rooms of the system, e.g. `main`
+    pub owner_id: Option<i64>,
+    pub title: String,
+    pub topic: Option<String>,
+    pub visibility: RoomVisibility,
+    pub created_at: String,
+    pub updated_at: Option<String>,
+}
+
+#[derive(ToSchema, Deserialize)]
+pub struct RoomResponse {
+    pub data: Room,
+}
+
+#[derive(ToSchema, Deserialize)]
+pub struct RoomListResponse {
+    pub data: Vec<Room>,
+    pub total: usize,
+}
+
+#[derive(ToSchema, Serialize, Deserialize, Clone, Debug)]
+pub struct RoomMember {
+    pub user_id: i64,
+    /// the name of the user, the email when it has none
+    pub name: String,
+    pub role: RoomRole,
+    /// none when the member is not muted
+    pub muted_until: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(ToSchema, Deserialize)]
+pub struct RoomMemberListResponse {
+    pub data: Vec<RoomMember>,
+}
+
+#[derive(ToSchema, Deserialize, Debug)]
+pub struct CreateRoomReq {
+    /// the name the sessions join, without whitespace, at most 64 characters
+    pub id: String,
+    pub title: String,
+    pub topic: Option<String>,
+    /// public when none
+    pub visibility: Option<RoomVisibility>,
+}
+
+/// only the owner changes the visibility, omitted fields stay unchanged, an explicit null clears
+/// the topic
+#[derive(ToSchema, Deserialize, Debug)]
+pub struct UpdateRoomReq {
+    pub title: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_patch")]
+    #[schema(value_type = Option<String>)]
+    pub topic: Option<Option<String>>,
+    pub visibility: Option<RoomVisibility>,
+}
+
+#[derive(ToSchema, Deserialize, Debug)]
+pub struct SetRoomRoleReq {
+    /// member or moderator, the owner is the creator of the room
+    pub role: RoomRole,
+}
+
+#[derive(ToSchema, Deserialize, Debug)]
+pub struct MuteReq {
+    /// how long the member is muted, none unmutes it
+    pub seconds: Option<i64>,
+}
+
+#[derive(ToSchema, Deserialize)]
+pub struct MuteResponse {
+    /// until when the member is muted, none when it is not
+    pub data: Option<String>,
+}
+
+/// an invitation of the current user, it is taken when the user accepts it or joins the room
+#[derive(ToSchema, Serialize, Deserialize, Clone, Debug)]
+pub struct RoomInvitation {
+    pub room: String,
+    pub title: String,
+    pub visibility: RoomVisibility,
+    /// none when the user who invited has been deleted
+    pub invited_by: Option<i64>,
+    pub created_at: String,
+}
+
+#[derive(ToSchema, Deserialize)]
+pub struct RoomInvitationListResponse {
+    pub data: Vec<RoomInvitation>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_update_room_req_patch() {
+        let req: UpdateRoomReq =
+            serde_json::from_str(r#"{"topic": null, "visibility": "invite_only"}"#).unwrap();
+        assert_eq!(Some(None), req.topic);
+        assert_eq!(Some(RoomVisibility::InviteOnly), req.visibility);
+        assert!(req.title.is_none());
+
+        let req: UpdateRoomReq = serde_json::from_str(r#"{"title": "rust"}"#).unwrap();
+        assert_eq!(None, req.topic);
+    }
+}
@@ -1,3 +1,4 @@
+use super::deserialize_patch;
 use serde::{Deserialize, Serialize};
 use serde_json::{Map, Value};
 use util_redis::derive::{from_redis, to_redis};
@@ -33,7 +34,14 @@ pub struct User {
     pub email: String,
     pub status: UserStatus,
     pub name: Option<String>,
+    /// E.164, e.g. +8613800138000
     pub mobile: Option<String>,
+    /// ISO 3166-1 alpha-2, e.g. CN
+    pub mobile_country: Option<String>,
+    pub avatar_url: Option<String>,
+    pub bio: Option<String>,
+    pub locale: Option<String>,
+    pub timezone: Option<String>,
     pub laston: Option<String>,
     pub created_at: String,
     pub updated_at: Option<String>,
@@ -104,7 +112,17 @@ pub struct CurrentUser {
     pub email: String,
     pub status: UserStatus,
     pub name: Option<String>,
+    /// E.164, e.g. +8613800138000
     pub mobile: Option<String>,
+    /// ISO 3166-1 alpha-2, e.g. CN
+    pub mobile_country: Option<String>,
+    pub avatar_url: Option<String>,
+    pub bio: Option<String>,
+    pub locale: Option<String>,
+    pub timezone: Option<String>,
+    /// ui settings of the user
+    #[schema(value_type = Object)]
+    pub preferences: Option<Value>,
     pub laston: Option<String>,
     pub created_at: String,
     pub updated_at: Option<String>,
@@ -119,10 +137,39 @@ pub struct LoginReq {
     pub pwd: String,
 }
 
+/// where a login comes from
+#[derive(Debug, Clone, Default)]
+pub struct LoginClient {
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    /// a stable id of the device given by the client, the user agent is used without it
+    pub device_id: Option<String>,
+}
+
+#[derive(ToSchema, Serialize, Deserialize, Clone, Debug)]
+pub struct LoginRecord {
+    pub id: i64,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub success: bool,
+    /// why the login failed
+    pub reason: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(ToSchema, Deserialize)]
+pub struct LoginRecordListResponse {
+    pub data: Vec<LoginRecord>,
+    pub total: usize,
+}
+
 #[derive(ToSchema, Deserialize)]
 pub struct SendEmailCodeReq {
     pub email: String,
     pub from: SendEmailCodeFrom,
+    /// locale of the email to register, e.g. zh-CN
+    #[schema(example = "en")]
+    pub locale: Option<String>,
 }
 
 #[derive(ToSchema, Deserialize)]
@@ -135,6 +182,19 @@ pub struct ChangePasswordReq {
     pub pwd: String,
 }
 
+#[derive(ToSchema, Deserialize)]
+pub struct SendResetPasswordLinkReq {
+    pub email: String,
+}
+
+#[derive(ToSchema, Deserialize)]
+pub struct ResetPasswordReq {
+    /// the token in the reset password link
+    pub token: String,
+    /// password
+    pub pwd: String,
+}
+
 #[derive(ToSchema, Deserialize)]
 pub struct LoginDataResponse {
     pub data: String,
@@ -161,11 +221,34 @@ pub struct SendEmailResponse {
     pub data: usize,
 }
 
+/// updates the current user, omitted fields stay unchanged, an explicit null clears the field
 #[derive(ToSchema, Deserialize)]
 pub struct UserUpdateReq {
-    pub id: i64,
-    pub name: Option<String>,
-    pub mobile: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_patch")]
+    #[schema(value_type = Option<String>)]
+    pub name: Option<Option<String>>,
+    /// either in E.164 or a national number of `mobile_country`
+    #[serde(default, deserialize_with = "deserialize_patch")]
+    #[schema(value_type = Option<String>, example = "+8613800138000")]
+    pub mobile: Option<Option<String>>,
+    /// ISO 3166-1 alpha-2, defaults to CN, demanded when the calling code is shared, e.g. +1
+    #[schema(example = "CN")]
+    pub mobile_country: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_patch")]
+    #[schema(value_type = Option<String>)]
+    pub bio: Option<Option<String>>,
+    /// e.g. en-US
+    #[serde(default, deserialize_with = "deserialize_patch")]
+    #[schema(value_type = Option<String>, example = "en-US")]
+    pub locale: Option<Option<String>>,
+    /// IANA time zone name, e.g. Asia/Shanghai
+    #[serde(default, deserialize_with = "deserialize_patch")]
+    #[schema(value_type = Option<String>, example = "Asia/Shanghai")]
+    pub timezone: Option<Option<String>>,
+    /// ui settings, must be a json object
+    #[serde(default, deserialize_with = "deserialize_patch")]
+    #[schema(value_type = Option<Object>)]
+    pub preferences: Option<Option<Value>>,
 }
 
 #[derive(ToSchema, Deserialize)]
@@ -179,10 +262,27 @@ pub struct RegisterReq {
     pub pwd: String,
     pub code: String,
     pub name: Option<String>,
+    /// either in E.164 or a national number of `mobile_country`
     pub mobile: Option<String>,
+    /// ISO 3166-1 alpha-2, defaults to CN, demanded when the calling code is shared, e.g. +1
+    pub mobile_country: Option<String>,
 }
 
 #[derive(ToSchema, Deserialize)]
 pub struct UserDeleteReq {
     pub ids: Vec<i64>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_user_update_req_patch() {
+        let req: UserUpdateReq =
+            serde_json::from_str(r#"{"id": 1, "name": "evolve", "bio": null}"#).unwrap();
+        assert_eq!(Some(Some("evolve".to_string())), req.name);
+        assert_eq!(Some(None), req.bio);
+        assert_eq!(None, req.mobile);
+        assert!(req.preferences.is_none());
+    }
+}
@@ -0,0 +1,37 @@
+use utoipa::OpenApi;
+use util_response::{ MsgResponse, MsgResponseWithErrCode };
+use crate::api::chat as chat_controller;
+use crate::model::chat as chat_model;
+use crate::openapi::security::SecurityAddon;
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        chat_controller::history,
+        chat_controller::conversations,
+        chat_controller::direct_history,
+        chat_controller::read_direct,
+        chat_controller::unread,
+    ),
+    components(
+        schemas(
+            chat_model::ChatMessage,
+            chat_model::ChatHistory,
+            chat_model::ChatHistoryResponse,
+            chat_model::DirectMessage,
+            chat_model::DirectHistory,
+            chat_model::DirectHistoryResponse,
+            chat_model::Conversation,
+            chat_model::ConversationListResponse,
+            chat_model::RoomUnread,
+            chat_model::RoomUnreadListResponse,
+            MsgResponse,
+            MsgResponseWithErrCode,
+        )
+    ),
+    tags(
+        (name = "chat", description = "chat history and direct message endpoints.")
+    ),
+    modifiers(&SecurityAddon)
+)]
+pub struct ApiDoc;
@@ -0,0 +1,27 @@
+use utoipa::OpenApi;
+use util_response::{ MsgResponse, MsgResponseWithErrCode };
+use crate::api::email as email_controller;
+use crate::model::email as email_model;
+use crate::openapi::security::SecurityAddon;
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        email_controller::failed,
+        email_controller::retry,
+    ),
+    components(
+        schemas(
+            email_model::OutboxStatus,
+            email_model::OutboxMail,
+            email_model::OutboxMailListResponse,
+            MsgResponse,
+            MsgResponseWithErrCode,
+        )
+    ),
+    tags(
+        (name = "email", description = "email delivery endpoints for admins.")
+    ),
+    modifiers(&SecurityAddon)
+)]
+pub struct ApiDoc;
@@ -1,6 +1,10 @@
+pub mod chat;
+pub mod email;
 pub mod role;
+pub mod room;
 pub mod security;
 pub mod user;
+pub mod ws;
 
 use utoipa::OpenApi;
 use utoipa_swagger_ui::{SwaggerUi, Url};
@@ -17,7 +21,8 @@ pub fn swaggerui() -> SwaggerUi {
     //     "/api-doc/user.json",
     //     openapi::user::ApiDoc::openapi().clone(),
     // ))
-    let urls = vec![
+    #[allow(unused_mut)]
+    let mut urls = vec![
         (
             Url::new("user", "/api-doc/user.json"),
             crate::openapi::user::ApiDoc::openapi().clone(),
@@ -26,6 +31,23 @@ pub fn swaggerui() -> SwaggerUi {
             Url::new("role", "/api-doc/role.json"),
             crate::openapi::role::ApiDoc::openapi().clone(),
         ),
+        (
+            Url::new("email", "/api-doc/email.json"),
+            crate::openapi::email::ApiDoc::openapi().clone(),
+        ),
+        (
+            Url::new("chat", "/api-doc/chat.json"),
+            crate::openapi::chat::ApiDoc::openapi().clone(),
+        ),
+        (
+            Url::new("room", "/api-doc/room.json"),
+            crate::openapi::room::ApiDoc::openapi().clone(),
+        ),
     ];
+    #[cfg(feature = "ws")]
+    urls.push((
+        Url::new("ws", "/api-doc/ws.json"),
+        crate::openapi::ws::ApiDoc::openapi().clone(),
+    ));
     SwaggerUi::new("/swagger/{_:.*}").urls(urls)
 }
@@ -0,0 +1,49 @@
+use utoipa::OpenApi;
+use util_response::{ MsgResponse, MsgResponseWithErrCode };
+use crate::api::room as room_controller;
+use crate::model::room as room_model;
+use crate::openapi::security::SecurityAddon;
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        room_controller::create,
+        room_controller::query,
+        room_controller::get,
+        room_controller::update,
+        room_controller::members,
+        room_controller::set_role,
+        room_controller::invite,
+        room_controller::kick,
+        room_controller::ban,
+        room_controller::unban,
+        room_controller::mute,
+        room_controller::invitations,
+        room_controller::accept,
+    ),
+    components(
+        schemas(
+            room_model::RoomVisibility,
+            room_model::RoomRole,
+            room_model::Room,
+            room_model::RoomResponse,
+            room_model::RoomListResponse,
+            room_model::RoomMember,
+            room_model::RoomMemberListResponse,
+            room_model::CreateRoomReq,
+            room_model::UpdateRoomReq,
+            room_model::SetRoomRoleReq,
+            room_model::MuteReq,
+            room_model::MuteResponse,
+            room_model::RoomInvitation,
+            room_model::RoomInvitationListResponse,
+            MsgResponse,
+            MsgResponseWithErrCode,
+        )
+    ),
+    tags(
+        (name = "room", description = "chat room, membership, moderation and invitation endpoints.")
+    ),
+    modifiers(&SecurityAddon)
+)]
+pub struct ApiDoc;
@@ -9,11 +9,14 @@ use crate::openapi::security::SecurityAddon;
     paths(
         user_controller::login,
         user_controller::change_pwd,
+        user_controller::send_reset_pwd_link,
+        user_controller::reset_pwd,
         user_controller::send_email_code,
         user_controller::register,
         user_controller::search,
         user_controller::get,
         user_controller::get_current_user,
+        user_controller::get_logins,
         user_controller::update,
         user_controller::delete,
         user_controller::validate_exist_email,
@@ -33,6 +36,8 @@ use crate::openapi::security::SecurityAddon;
             user_model::UserType, 
             user_model::SendEmailCodeFrom,
             user_model::ChangePasswordReq,
+            user_model::SendResetPasswordLinkReq,
+            user_model::ResetPasswordReq,
             user_model::SendEmailCodeReq,
             user_model::LoginDataResponse,
             user_model::UserGetResponse,
@@ -42,6 +47,8 @@ use crate::openapi::security::SecurityAddon;
             MsgResponse,
             MsgResponseWithErrCode,
             user_model::UserDeleteReq,
+            user_model::LoginRecord,
+            user_model::LoginRecordListResponse,
         )
     ),
     tags(
@@ -0,0 +1,29 @@
+#![cfg(feature = "ws")]
+
+use utoipa::OpenApi;
+use crate::model::chat as chat_model;
+use crate::ws::protocol;
+
+/// the frames of the websocket protocol `chat.v1`, there are no paths, the frames are sent over
+/// `/ws/ws/{token}`
+#[derive(OpenApi)]
+#[openapi(
+    components(
+        schemas(
+            protocol::ClientFrame,
+            protocol::ClientMessage,
+            protocol::ServerFrame,
+            protocol::ServerMessage,
+            protocol::ErrorCode,
+            protocol::RemovedReason,
+            chat_model::ChatMessage,
+            chat_model::DirectMessage,
+            chat_model::Conversation,
+            chat_model::RoomUnread,
+        )
+    ),
+    tags(
+        (name = "ws", description = "websocket frames, for generating the types of the clients.")
+    )
+)]
+pub struct ApiDoc;
@@ -0,0 +1,201 @@
+use crate::{
+    config,
+    dao::pg::{
+        chat_message::{self as pg_chat_message_dao, NewChatMessage},
+        direct_message::{self as pg_direct_message_dao, NewDirectMessage},
+        room_read as pg_room_read_dao,
+    },
+    model::chat as chat_model,
+};
+use tracing::instrument;
+use util_error::{validate_error, BasicResult};
+
+/// the limit asked for, within the size of a page in config
+fn page_size(limit: Option<i64>) -> i64 {
+    let cfg = config::cfg();
+    limit
+        .unwrap_or(cfg.chat.page_size)
+        .clamp(1, cfg.chat.max_page_size)
+}
+
+/// the page in time order, with the cursor of the older page when there is one
+///
+/// `messages` are fetched with one more than `limit`, the extra one tells an older page is left
+fn page(messages: Vec<chat_model::ChatMessage>, limit: i64) -> chat_model::ChatHistory {
+    let (messages, next) = in_time_order(messages, limit, |x| x.id);
+    chat_model::ChatHistory { messages, next }
+}
+
+/// the page of the direct messages, the same as [`page`]
+fn direct_page(messages: Vec<chat_model::DirectMessage>, limit: i64) -> chat_model::DirectHistory {
+    let (messages, next) = in_time_order(messages, limit, |x| x.id);
+    chat_model::DirectHistory { messages, next }
+}
+
+fn in_time_order<T>(mut messages: Vec<T>, limit: i64, id: fn(&T) -> i64) -> (Vec<T>, Option<i64>) {
+    let next = match messages.len() as i64 > limit {
+        true => {
+            messages.truncate(limit as usize);
+            messages.last().map(id)
+        }
+        false => None,
+    };
+    messages.reverse();
+    (messages, next)
+}
+
+/// the guests of the `test_ws` feature share the id -1, they are not users in the database
+pub fn is_guest(user_id: i64) -> bool {
+    user_id <= 0
+}
+
+/// none when the sender is a guest, whose messages are not kept
+#[instrument(skip_all)]
+pub async fn save(
+    room: &str,
+    user_id: i64,
+    from_id: &str,
+    from_name: &str,
+    content: &str,
+) -> BasicResult<Option<chat_model::ChatMessage>> {
+    if is_guest(user_id) {
+        return Ok(None);
+    }
+    let res = pg_chat_message_dao::insert(&NewChatMessage {
+        room,
+        user_id,
+        from_id,
+        from_name,
+        content,
+    })
+    .await?;
+    Ok(Some(res.into()))
+}
+
+/// the messages of the room older than `before`, the latest ones when none
+#[instrument(skip_all)]
+pub async fn history(
+    room: &str,
+    before: Option<i64>,
+    limit: Option<i64>,
+) -> BasicResult<chat_model::ChatHistory> {
+    let limit = page_size(limit);
+    let messages = pg_chat_message_dao::query(room, before, limit + 1).await?;
+    Ok(page(
+        messages.into_iter().map(|x| x.into()).collect(),
+        limit,
+    ))
+}
+
+/// none when the recipient does not exist
+#[instrument(skip_all)]
+pub async fn send_direct(
+    from_user_id: i64,
+    from_name: &str,
+    to_user_id: i64,
+    content: &str,
+) -> BasicResult<Option<chat_model::DirectMessage>> {
+    if content.trim().is_empty() {
+        return validate_error!("content is required").into();
+    }
+    if from_user_id == to_user_id {
+        return validate_error!("can not send a message to yourself").into();
+    }
+    if is_guest(from_user_id) || is_guest(to_user_id) {
+        return validate_error!("guests can not send or receive direct messages").into();
+    }
+    let res = pg_direct_message_dao::insert(&NewDirectMessage {
+        from_user_id,
+        from_name,
+        to_user_id,
+        content,
+    })
+    .await?;
+    Ok(res.map(|x| x.into()))
+}
+
+/// the messages between the two users older than `before`, the latest ones when none
+#[instrument(skip_all)]
+pub async fn direct_history(
+    user_id: i64,
+    other_user_id: i64,
+    before: Option<i64>,
+    limit: Option<i64>,
+) -> BasicResult<chat_model::DirectHistory> {
+    let limit = page_size(limit);
+    let messages = pg_direct_message_dao::query(user_id, other_user_id, before, limit + 1).await?;
+    Ok(direct_page(
+        messages.into_iter().map(|x| x.into()).collect(),
+        limit,
+    ))
+}
+
+/// the conversations of the user with their unread counts, the latest first
+#[instrument(skip_all)]
+pub async fn conversations(user_id: i64) -> BasicResult<Vec<chat_model::Conversation>> {
+    let res = pg_direct_message_dao::conversations(user_id).await?;
+    Ok(res.into_iter().map(|x| x.into()).collect())
+}
+
+/// the messages from the other user have been read
+#[instrument(skip_all)]
+pub async fn mark_read(user_id: i64, other_user_id: i64) -> BasicResult<()> {
+    pg_direct_message_dao::mark_read(user_id, other_user_id).await?;
+    Ok(())
+}
+
+/// the author of the message of the room, none when the author has been deleted
+#[instrument(skip_all)]
+pub async fn author(room: &str, message_id: i64) -> BasicResult<Option<i64>> {
+    match pg_chat_message_dao::get(message_id).await? {
+        Some(x) if x.room == room => Ok(x.user_id),
+        _ => validate_error!(format!("message: {} is not in room: {}", message_id, room)).into(),
+    }
+}
+
+/// the user has read the room up to the message, returns how far it has read
+#[instrument(skip_all)]
+pub async fn read_room(room: &str, user_id: i64, message_id: i64) -> BasicResult<i64> {
+    match pg_room_read_dao::mark(room, user_id, message_id).await? {
+        Some(x) => Ok(x),
+        None => validate_error!(format!("message: {} is not in room: {}", message_id, room)).into(),
+    }
+}
+
+/// the rooms of the user with their unread counts
+#[instrument(skip_all)]
+pub async fn unread_rooms(user_id: i64) -> BasicResult<Vec<chat_model::RoomUnread>> {
+    let res = pg_room_read_dao::unread(user_id).await?;
+    Ok(res.into_iter().map(|x| x.into()).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(id: i64) -> chat_model::ChatMessage {
+        chat_model::ChatMessage {
+            id,
+            room: "main".into(),
+            user_id: Some(1),
+            from_id: "a@x.com".into(),
+            from_name: "a".into(),
+            content: "hi".into(),
+            created_at: "".into(),
+        }
+    }
+
+    #[test]
+    fn test_page() {
+        let full = page(vec![message(9), message(8), message(7), message(6)], 3);
+        assert_eq!(
+            full.messages.iter().map(|x| x.id).collect::<Vec<_>>(),
+            vec![7, 8, 9]
+        );
+        assert_eq!(full.next, Some(7));
+
+        let last = page(vec![message(2), message(1)], 3);
+        assert_eq!(last.next, None);
+        assert_eq!(page(vec![], 3).messages, vec![]);
+    }
+}
@@ -0,0 +1,24 @@
+use crate::{dao::pg::email_outbox as pg_email_outbox_dao, model::email as email_model};
+use tracing::instrument;
+use util_error::{validate_error, BasicResult};
+use util_response::Pagination;
+
+/// the emails which have given up delivering, the latest first
+#[instrument(skip_all)]
+pub async fn failed(page: &Pagination) -> BasicResult<(Vec<email_model::OutboxMail>, usize)> {
+    let status = email_model::OutboxStatus::Dead;
+    let (data, total) = tokio::try_join!(
+        pg_email_outbox_dao::query_by_status(status, page),
+        pg_email_outbox_dao::count_by_status(status)
+    )?;
+    Ok((data.into_iter().map(|x| x.into()).collect(), total))
+}
+
+/// deliver a dead email again
+#[instrument(skip_all)]
+pub async fn retry(id: i64) -> BasicResult<()> {
+    if pg_email_outbox_dao::requeue(id).await? == 0 {
+        return validate_error!(format!("email: {} is not a failed delivery", id)).into();
+    }
+    Ok(())
+}
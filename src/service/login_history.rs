@@ -0,0 +1,209 @@
+use crate::{
+    dao::pg::{
+        login_history::{self as pg_login_history_dao, NewLoginHistory},
+        user as pg_user_dao,
+    },
+    email::{self as mailer, template::Template},
+    model::user as user_model,
+};
+use sha2::Digest;
+use std::net::IpAddr;
+use tracing::instrument;
+use util_datetime::FormatDateTime;
+use util_error::BasicResult;
+use util_response::Pagination;
+
+/// the network a login comes from, /24 of ipv4 or /48 of ipv6
+fn ip_range(ip: &str) -> Option<String> {
+    match ip.parse::<IpAddr>().ok()? {
+        IpAddr::V4(v4) => {
+            let [a, b, c, _] = v4.octets();
+            Some(format!("{a}.{b}.{c}.0/24"))
+        }
+        IpAddr::V6(v6) => {
+            let [a, b, c, ..] = v6.segments();
+            Some(format!("{a:x}:{b:x}:{c:x}::/48"))
+        }
+    }
+}
+
+/// identify a device by the id it gives, or by its user agent
+fn fingerprint(client: &user_model::LoginClient) -> String {
+    let source = client
+        .device_id
+        .as_deref()
+        .or(client.user_agent.as_deref())
+        .unwrap_or_default();
+    format!("{:x}", sha2::Sha256::digest(source.as_bytes()))
+}
+
+/// at most `max` chars of `x`, the width of its column
+fn truncate(x: &str, max: usize) -> &str {
+    match x.char_indices().nth(max) {
+        Some((i, _)) => &x[..i],
+        None => x,
+    }
+}
+
+/// a login history whose values fit in the columns, whatever the client has sent
+fn new_history<'a>(
+    user_id: Option<i64>,
+    email: &'a str,
+    client: &'a user_model::LoginClient,
+    ip_range: Option<&'a str>,
+    fingerprint: &'a str,
+    reason: Option<&'a str>,
+) -> NewLoginHistory<'a> {
+    NewLoginHistory {
+        user_id,
+        email: truncate(email, 255),
+        ip: client.ip.as_deref().map(|x| truncate(x, 45)),
+        ip_range,
+        user_agent: client.user_agent.as_deref().map(|x| truncate(x, 512)),
+        fingerprint,
+        success: reason.is_none(),
+        reason: reason.map(|x| truncate(x, 255)),
+    }
+}
+
+async fn insert(
+    user_id: Option<i64>,
+    email: &str,
+    client: &user_model::LoginClient,
+    fingerprint: &str,
+    reason: Option<&str>,
+) -> BasicResult<()> {
+    let ip_range = client.ip.as_deref().and_then(ip_range);
+    let history = new_history(
+        user_id,
+        email,
+        client,
+        ip_range.as_deref(),
+        fingerprint,
+        reason,
+    );
+    pg_login_history_dao::insert(&history).await?;
+    Ok(())
+}
+
+/// a login history never fails the login
+#[instrument(skip_all)]
+pub async fn record_failure(
+    user_id: Option<i64>,
+    email: &str,
+    client: &user_model::LoginClient,
+    reason: &str,
+) {
+    if let Err(err) = insert(user_id, email, client, &fingerprint(client), Some(reason)).await {
+        log::error!("record login failure of {} err: {:?}", email, err);
+    }
+}
+
+/// record the login and alert the user if it comes from an unseen device or ip range
+#[instrument(skip_all)]
+pub async fn record_success(user: &pg_user_dao::User, client: &user_model::LoginClient) {
+    if let Err(err) = try_record_success(user, client).await {
+        log::error!("record login of {} err: {:?}", user.email, err);
+    }
+}
+
+async fn try_record_success(
+    user: &pg_user_dao::User,
+    client: &user_model::LoginClient,
+) -> BasicResult<()> {
+    let fingerprint = fingerprint(client);
+    let ip_range = client.ip.as_deref().and_then(ip_range);
+    let (total, same_device, same_range) =
+        pg_login_history_dao::count_seen(user.id, &fingerprint, ip_range.as_deref()).await?;
+
+    // the alert goes out even if the login can not be recorded
+    if let Err(err) = insert(Some(user.id), &user.email, client, &fingerprint, None).await {
+        log::error!("record login of {} err: {:?}", user.email, err);
+    }
+
+    // nothing to compare with on the first login
+    if total > 0 && (same_device == 0 || same_range == 0) {
+        let time = chrono::Utc::now().to_default();
+        let vars = [
+            ("name", user.name.as_deref().unwrap_or(&user.email)),
+            ("time", &time),
+            ("ip", client.ip.as_deref().unwrap_or("unknown")),
+            (
+                "user_agent",
+                client.user_agent.as_deref().unwrap_or("unknown"),
+            ),
+        ];
+        mailer::send(
+            &user.email,
+            Template::LoginAlert,
+            user.locale.as_deref(),
+            &vars,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// the logins of a user, the latest first
+#[instrument(skip_all)]
+pub async fn query(
+    user_id: i64,
+    page: &Pagination,
+) -> BasicResult<(Vec<user_model::LoginRecord>, usize)> {
+    let (data, total) = tokio::try_join!(
+        pg_login_history_dao::query(user_id, page),
+        pg_login_history_dao::count(user_id)
+    )?;
+    Ok((data.into_iter().map(|x| x.into()).collect(), total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ip_range() {
+        assert_eq!(Some("10.1.2.0/24".to_string()), ip_range("10.1.2.3"));
+        assert_eq!(
+            Some("2001:db8:85a3::/48".to_string()),
+            ip_range("2001:db8:85a3:8d3:1319:8a2e:370:7348")
+        );
+        assert_eq!(None, ip_range("unknown"));
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let mut client = user_model::LoginClient {
+            ip: None,
+            user_agent: Some("Mozilla/5.0".to_string()),
+            device_id: None,
+        };
+        let by_agent = fingerprint(&client);
+        client.device_id = Some("device".to_string());
+        let by_device = fingerprint(&client);
+        assert_ne!(by_agent, by_device);
+        // the device id wins over the user agent
+        client.user_agent = Some("curl/8.0".to_string());
+        assert_eq!(by_device, fingerprint(&client));
+    }
+
+    #[test]
+    fn test_new_history() {
+        let client = user_model::LoginClient {
+            ip: Some("10.1.2.3".to_string()),
+            user_agent: Some("Mozilla/5.0 ".to_string() + &"浏览器".repeat(200)),
+            device_id: None,
+        };
+        let history = new_history(None, "tom@evolve.com", &client, None, "f", Some("wrong"));
+        let user_agent = history.user_agent.unwrap();
+        assert_eq!(512, user_agent.chars().count());
+        assert!(client
+            .user_agent
+            .as_deref()
+            .unwrap()
+            .starts_with(user_agent));
+        assert_eq!(Some("10.1.2.3"), history.ip);
+        assert_eq!("tom@evolve.com", history.email);
+        assert!(!history.success);
+    }
+}
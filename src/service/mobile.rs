@@ -0,0 +1,241 @@
+use fancy_regex::Regex;
+use util_error::{validate_error, BasicResult};
+
+/// the country of a number which is typed in without calling code
+pub const DEFAULT_COUNTRY: &str = "CN";
+
+struct CountryRule {
+    /// ISO 3166-1 alpha-2
+    country: &'static str,
+    calling_code: &'static str,
+    /// the national significant number of a mobile, without trunk prefix
+    pattern: &'static str,
+}
+
+const RULES: &[CountryRule] = &[
+    CountryRule {
+        country: "CN",
+        calling_code: "86",
+        pattern: r#"^1[3-9][0-9]{9}$"#,
+    },
+    CountryRule {
+        country: "HK",
+        calling_code: "852",
+        pattern: r#"^[4-9][0-9]{7}$"#,
+    },
+    CountryRule {
+        country: "MO",
+        calling_code: "853",
+        pattern: r#"^6[0-9]{7}$"#,
+    },
+    CountryRule {
+        country: "TW",
+        calling_code: "886",
+        pattern: r#"^9[0-9]{8}$"#,
+    },
+    CountryRule {
+        country: "US",
+        calling_code: "1",
+        pattern: r#"^[2-9][0-9]{2}[2-9][0-9]{6}$"#,
+    },
+    CountryRule {
+        country: "CA",
+        calling_code: "1",
+        pattern: r#"^[2-9][0-9]{2}[2-9][0-9]{6}$"#,
+    },
+    CountryRule {
+        country: "GB",
+        calling_code: "44",
+        pattern: r#"^7[0-9]{9}$"#,
+    },
+    CountryRule {
+        country: "DE",
+        calling_code: "49",
+        pattern: r#"^1[5-7][0-9]{8,9}$"#,
+    },
+    CountryRule {
+        country: "FR",
+        calling_code: "33",
+        pattern: r#"^[67][0-9]{8}$"#,
+    },
+    CountryRule {
+        country: "JP",
+        calling_code: "81",
+        pattern: r#"^[789]0[0-9]{8}$"#,
+    },
+    CountryRule {
+        country: "KR",
+        calling_code: "82",
+        pattern: r#"^1[0-9]{8,9}$"#,
+    },
+    CountryRule {
+        country: "SG",
+        calling_code: "65",
+        pattern: r#"^[89][0-9]{7}$"#,
+    },
+    CountryRule {
+        country: "IN",
+        calling_code: "91",
+        pattern: r#"^[6-9][0-9]{9}$"#,
+    },
+    CountryRule {
+        country: "AU",
+        calling_code: "61",
+        pattern: r#"^4[0-9]{8}$"#,
+    },
+    CountryRule {
+        country: "RU",
+        calling_code: "7",
+        pattern: r#"^9[0-9]{9}$"#,
+    },
+    CountryRule {
+        country: "BR",
+        calling_code: "55",
+        pattern: r#"^[1-9]{2}9[0-9]{8}$"#,
+    },
+];
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Mobile {
+    /// E.164, e.g. +8613800000000
+    pub e164: String,
+    /// ISO 3166-1 alpha-2, e.g. CN
+    pub country: String,
+}
+
+fn is_valid(rule: &CountryRule, national: &str) -> BasicResult<bool> {
+    if national.is_empty() || !national.chars().all(|c| c.is_ascii_digit()) {
+        return Ok(false);
+    }
+    let reg = Regex::new(rule.pattern)?;
+    Ok(reg.is_match(national)?)
+}
+
+/// strip the separators people type in, such as spaces, dashes and parentheses
+fn strip(input: &str) -> String {
+    input
+        .chars()
+        .filter(|c| !matches!(c, ' ' | '-' | '.' | '(' | ')'))
+        .collect()
+}
+
+/// validate a mobile with the rule of its country and normalize it into E.164
+///
+/// `country` is used when the number has no calling code, and is the country of a calling code
+/// which is shared by several countries, e.g. +1 of US and CA, so the country the user chose is
+/// kept, it defaults to [`DEFAULT_COUNTRY`]
+pub fn parse(input: &str, country: Option<&str>) -> BasicResult<Mobile> {
+    let number = strip(input);
+    let international = number
+        .strip_prefix('+')
+        .or_else(|| number.strip_prefix("00"));
+
+    let candidates: Vec<(&CountryRule, &str)> = match international {
+        Some(digits) => RULES
+            .iter()
+            .filter_map(|x| digits.strip_prefix(x.calling_code).map(|n| (x, n)))
+            .collect(),
+        None => {
+            let country = country.unwrap_or(DEFAULT_COUNTRY);
+            let rule = RULES
+                .iter()
+                .find(|x| x.country.eq_ignore_ascii_case(country))
+                .ok_or_else(|| validate_error!(format!("unsupported country: {}", country)))?;
+            // the trunk prefix is not a part of the national significant number
+            vec![(rule, number.strip_prefix('0').unwrap_or(&number))]
+        }
+    };
+
+    let mut valid = Vec::new();
+    for (rule, national) in candidates {
+        if is_valid(rule, national)? {
+            valid.push((rule, national));
+        }
+    }
+    let chosen = valid
+        .iter()
+        .find(|(x, _)| country.is_some_and(|c| x.country.eq_ignore_ascii_case(c)));
+    let (rule, national) = match (chosen, valid.as_slice()) {
+        (Some(x), _) | (None, [x]) => x,
+        (None, []) => return validate_error!("invalid mobile").into(),
+        (None, _) => {
+            let countries: Vec<&str> = valid.iter().map(|(x, _)| x.country).collect();
+            return validate_error!(format!(
+                "invalid mobile: the country is demanded, one of {}",
+                countries.join(", ")
+            ))
+            .into();
+        }
+    };
+    Ok(Mobile {
+        e164: format!("+{}{}", rule.calling_code, national),
+        country: rule.country.to_string(),
+    })
+}
+
+/// the key word to search the stored mobiles with, which are in E.164
+///
+/// only a key word which looks like a mobile is normalized, that is a whole number of the
+/// [`DEFAULT_COUNTRY`] or one with its calling code, e.g. +86138001, other digits may be an id or
+/// a part of a name or an email, none when it is left as it is
+pub fn search_term(key_word: &str) -> Option<String> {
+    if let Ok(x) = parse(key_word, None) {
+        return Some(x.e164);
+    }
+    match key_word.strip_prefix('+') {
+        Some(x) if !x.is_empty() && x.chars().all(|c| c.is_ascii_digit()) => {
+            Some(format!("+{}", x))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let expected = Mobile {
+            e164: "+8613800138000".to_string(),
+            country: "CN".to_string(),
+        };
+        assert_eq!(expected, parse("138-0013-8000", None).unwrap());
+        assert_eq!(expected, parse("+86 138 0013 8000", None).unwrap());
+        assert_eq!(expected, parse("0086 13800138000", Some("US")).unwrap());
+
+        let res = parse("+1 (604) 555-0123", Some("CA")).unwrap();
+        assert_eq!("+16045550123", res.e164);
+        assert_eq!("CA", res.country);
+
+        let res = parse("07700 900123", Some("gb")).unwrap();
+        assert_eq!("+447700900123", res.e164);
+
+        // +1 is shared by US and CA, the country is not guessed
+        assert!(parse("+1 604 555 0123", None).is_err());
+        let res = parse("+1 604 555 0123", Some("US")).unwrap();
+        assert_eq!("US", res.country);
+
+        assert!(parse("12345", None).is_err());
+        assert!(parse("13800138000", Some("XX")).is_err());
+    }
+
+    #[test]
+    fn test_search_term() {
+        assert_eq!(
+            Some("+8613800138000".to_string()),
+            search_term("13800138000")
+        );
+        assert_eq!(
+            Some("+8613800138000".to_string()),
+            search_term("0086 13800138000")
+        );
+        assert_eq!(Some("+1604555".to_string()), search_term("+1604555"));
+        // a part of a number, an id or digits of a name are searched as they are
+        assert_eq!(None, search_term("138001"));
+        assert_eq!(None, search_term("0044770"));
+        assert_eq!(None, search_term("12345"));
+        assert_eq!(None, search_term("evolve"));
+        assert_eq!(None, search_term("+"));
+    }
+}
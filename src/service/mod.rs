@@ -1 +1,7 @@
+pub mod chat;
+pub mod email;
+pub mod login_history;
+pub mod mobile;
+pub mod password;
+pub mod room;
 pub mod user;
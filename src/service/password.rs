@@ -0,0 +1,111 @@
+use crate::config;
+use lazy_static::lazy_static;
+use std::collections::HashSet;
+
+lazy_static! {
+    static ref COMMON_PASSWORDS: HashSet<&'static str> =
+        include_str!("../../static/passwords/common.txt")
+            .lines()
+            .map(str::trim)
+            .filter(|x| !x.is_empty())
+            .collect();
+}
+
+/// a part of the email or name shorter than this is not considered as personal
+const PERSONAL_MIN_LENGTH: usize = 3;
+
+/// whether a class is required, how to tell a char of it and how to describe it
+type CharClass = (bool, fn(char) -> bool, &'static str);
+
+/// check a password against the policy in config
+///
+/// returns every rule the password fails, empty if it passes
+pub fn check(pwd: &str, email: &str, name: Option<&str>) -> Vec<String> {
+    check_with(&config::cfg().password, pwd, email, name)
+}
+
+fn check_with(
+    policy: &config::Password,
+    pwd: &str,
+    email: &str,
+    name: Option<&str>,
+) -> Vec<String> {
+    let mut errors = Vec::new();
+
+    let length = pwd.chars().count();
+    if length < policy.min_length {
+        errors.push(format!("length>={} is demanded", policy.min_length));
+    }
+    if length > policy.max_length {
+        errors.push(format!("length<={} is demanded", policy.max_length));
+    }
+
+    let classes: [CharClass; 4] = [
+        (policy.require_lowercase, char::is_lowercase, "a-z"),
+        (policy.require_uppercase, char::is_uppercase, "A-Z"),
+        (policy.require_digit, |c| c.is_ascii_digit(), "0-9"),
+        (
+            policy.require_symbol,
+            |c| !c.is_alphanumeric() && !c.is_whitespace(),
+            "a symbol",
+        ),
+    ];
+    for (required, is_class, desc) in classes {
+        if required && !pwd.chars().any(is_class) {
+            errors.push(format!("{} is demanded", desc));
+        }
+    }
+
+    let lower = pwd.to_lowercase();
+    if policy.reject_common && COMMON_PASSWORDS.contains(lower.as_str()) {
+        errors.push("it is a commonly used password".to_string());
+    }
+
+    if policy.reject_personal {
+        let personal = [email.split('@').next(), name];
+        if personal
+            .into_iter()
+            .flatten()
+            .any(|x| x.chars().count() >= PERSONAL_MIN_LENGTH && lower.contains(&x.to_lowercase()))
+        {
+            errors.push("it should not contain your email or name".to_string());
+        }
+    }
+
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_with() {
+        let policy = config::Password {
+            min_length: 8,
+            max_length: 16,
+            require_lowercase: true,
+            require_uppercase: true,
+            require_digit: true,
+            require_symbol: true,
+            reject_common: true,
+            reject_personal: true,
+            history: 0,
+        };
+        let check = |pwd| check_with(&policy, pwd, "evolve@icloud.com", Some("yu"));
+
+        assert!(check("Correct-Horse-9").is_empty());
+        assert_eq!(4, check("password").len());
+        assert_eq!(
+            vec!["it should not contain your email or name".to_string()],
+            check("My-Evolve-9")
+        );
+        assert_eq!(
+            vec![
+                "length<=16 is demanded".to_string(),
+                "0-9 is demanded".to_string()
+            ],
+            check("correct horse battery Staple!")
+        );
+    }
+}
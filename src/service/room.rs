@@ -0,0 +1,339 @@
+use crate::{
+    config,
+    dao::pg::room::{self as pg_room_dao, Ban, Member, NewRoom, Room},
+    model::room::{self as room_model, RoomRole, RoomVisibility},
+};
+use tracing::instrument;
+use util_datetime::FormatDateTime;
+use util_error::{validate_error, BasicResult};
+use util_response::Pagination;
+
+const ROOM_ID_MAX_LENGTH: usize = 64;
+
+/// the id is sent in the frames and the redis keys, it has no whitespace
+fn validate_id(id: &str) -> BasicResult<()> {
+    if id.is_empty() || id.chars().count() > ROOM_ID_MAX_LENGTH {
+        return validate_error!(format!(
+            "room id must have 1 to {} characters",
+            ROOM_ID_MAX_LENGTH
+        ))
+        .into();
+    }
+    if id.chars().any(|x| x.is_whitespace() || x.is_control()) {
+        return validate_error!("room id must not have whitespace").into();
+    }
+    Ok(())
+}
+
+/// the refusal of a user who is banned or kicked from the room
+fn kept_out<T>(room: &str, ban: Ban) -> BasicResult<T> {
+    match ban.expires_at {
+        Some(x) => validate_error!(format!(
+            "you are kicked from room: {} until {}",
+            room,
+            x.to_default()
+        ))
+        .into(),
+        None => validate_error!(format!("you are banned from room: {}", room)).into(),
+    }
+}
+
+/// the room with the membership of the user, a private room is not seen by the others
+async fn access(room: &str, user_id: i64) -> BasicResult<(Room, Option<Member>)> {
+    let (x, member, ban) = tokio::try_join!(
+        pg_room_dao::get(room),
+        pg_room_dao::get_member(room, user_id),
+        pg_room_dao::get_ban(room, user_id)
+    )?;
+    let Some(x) = x.filter(|x| x.visibility != RoomVisibility::Private || member.is_some()) else {
+        return validate_error!(format!("room: {} does not exist", room)).into();
+    };
+    if let Some(ban) = ban {
+        return kept_out(room, ban);
+    }
+    Ok((x, member))
+}
+
+/// the role of the user in the room, none when it is not a member
+async fn role(room: &str, user_id: i64) -> BasicResult<Option<RoomRole>> {
+    let res = pg_room_dao::get_member(room, user_id).await?;
+    Ok(res.map(|x| x.role))
+}
+
+/// the membership of `user_id` when `by` outranks it in the room
+async fn moderate(room: &str, by: i64, user_id: i64) -> BasicResult<Option<Member>> {
+    if by == user_id {
+        return validate_error!("you can not moderate yourself").into();
+    }
+    let (member, target) = tokio::try_join!(
+        pg_room_dao::get_member(room, by),
+        pg_room_dao::get_member(room, user_id)
+    )?;
+    let target_role = target.as_ref().map_or(RoomRole::Member, |x| x.role);
+    if !member.is_some_and(|x| x.role.outranks(target_role)) {
+        return validate_error!(format!(
+            "you can not moderate user: {} in room: {}",
+            user_id, room
+        ))
+        .into();
+    }
+    Ok(target)
+}
+
+#[instrument(skip_all)]
+pub async fn create(
+    owner_id: i64,
+    req: &room_model::CreateRoomReq,
+) -> BasicResult<room_model::Room> {
+    validate_id(&req.id)?;
+    if req.title.trim().is_empty() {
+        return validate_error!("title is required").into();
+    }
+    let res = pg_room_dao::insert(&NewRoom {
+        id: &req.id,
+        owner_id,
+        title: &req.title,
+        topic: req.topic.as_deref(),
+        visibility: req.visibility.unwrap_or(RoomVisibility::Public),
+    })
+    .await?;
+    match res {
+        Some(x) => Ok(x.into()),
+        None => validate_error!(format!("room: {} exists already", req.id)).into(),
+    }
+}
+
+#[instrument(skip_all)]
+pub async fn get(room: &str, user_id: i64) -> BasicResult<room_model::Room> {
+    let (res, _) = access(room, user_id).await?;
+    Ok(res.into())
+}
+
+/// the rooms the user sees, the latest first
+#[instrument(skip_all)]
+pub async fn query(user_id: i64, page: &Pagination) -> BasicResult<(Vec<room_model::Room>, usize)> {
+    let (data, total) = tokio::try_join!(
+        pg_room_dao::query(user_id, page),
+        pg_room_dao::count(user_id)
+    )?;
+    Ok((data.into_iter().map(|x| x.into()).collect(), total))
+}
+
+/// the moderators change the title and the topic, the owner also the visibility
+#[instrument(skip_all)]
+pub async fn update(
+    room: &str,
+    user_id: i64,
+    req: &room_model::UpdateRoomReq,
+) -> BasicResult<room_model::Room> {
+    let role = role(room, user_id).await?;
+    let allowed = match req.visibility {
+        Some(_) => role == Some(RoomRole::Owner),
+        None => role >= Some(RoomRole::Moderator),
+    };
+    if !allowed {
+        return validate_error!(format!("you can not update room: {}", room)).into();
+    }
+    if req.title.as_ref().is_some_and(|x| x.trim().is_empty()) {
+        return validate_error!("title is required").into();
+    }
+    let res = pg_room_dao::update(
+        room,
+        req.title.as_deref(),
+        req.topic.as_ref().map(|x| x.as_deref()),
+        req.visibility,
+    )
+    .await?;
+    Ok(res.into())
+}
+
+#[instrument(skip_all)]
+pub async fn members(room: &str, user_id: i64) -> BasicResult<Vec<room_model::RoomMember>> {
+    access(room, user_id).await?;
+    let res = pg_room_dao::members(room).await?;
+    Ok(res.into_iter().map(|x| x.into()).collect())
+}
+
+/// the owner makes a member a moderator or back
+#[instrument(skip_all)]
+pub async fn set_role(room: &str, by: i64, user_id: i64, role: RoomRole) -> BasicResult<()> {
+    if role == RoomRole::Owner {
+        return validate_error!("a room has only one owner").into();
+    }
+    if self::role(room, by).await? != Some(RoomRole::Owner) {
+        return validate_error!(format!("only the owner sets the roles of room: {}", room)).into();
+    }
+    if by == user_id || pg_room_dao::set_role(room, user_id, role).await? == 0 {
+        return validate_error!(format!(
+            "user: {} is not a member of room: {}",
+            user_id, room
+        ))
+        .into();
+    }
+    Ok(())
+}
+
+/// the moderators invite, the invitation is taken when the user joins
+#[instrument(skip_all)]
+pub async fn invite(room: &str, by: i64, user_id: i64) -> BasicResult<()> {
+    if role(room, by).await? < Some(RoomRole::Moderator) {
+        return validate_error!(format!("you can not invite to room: {}", room)).into();
+    }
+    if pg_room_dao::get_ban(room, user_id).await?.is_some() {
+        return validate_error!(format!("user: {} is banned from room: {}", user_id, room)).into();
+    }
+    if pg_room_dao::invite(room, user_id, by).await? == 0 {
+        return validate_error!(format!(
+            "user: {} does not exist or has been invited already",
+            user_id
+        ))
+        .into();
+    }
+    Ok(())
+}
+
+/// let the user into the room, a public room makes it a member and the others take its invitation
+#[instrument(skip_all)]
+pub async fn join(room: &str, user_id: i64) -> BasicResult<()> {
+    let (x, member, ban) = tokio::try_join!(
+        pg_room_dao::get(room),
+        pg_room_dao::get_member(room, user_id),
+        pg_room_dao::get_ban(room, user_id)
+    )?;
+    let Some(x) = x else {
+        return validate_error!(format!("room: {} does not exist", room)).into();
+    };
+    if let Some(ban) = ban {
+        return kept_out(room, ban);
+    }
+    if member.is_some() {
+        return Ok(());
+    }
+    if x.visibility == RoomVisibility::Public {
+        pg_room_dao::add_member(room, user_id).await?;
+        return Ok(());
+    }
+    if pg_room_dao::accept_invitation(room, user_id).await? {
+        return Ok(());
+    }
+    match x.visibility {
+        // the invited users are the only ones who know of a private room
+        RoomVisibility::Private => validate_error!(format!("room: {} does not exist", room)).into(),
+        _ => validate_error!(format!("room: {} is joined with an invitation", room)).into(),
+    }
+}
+
+/// a member of the room which is not muted, anyone who is not banned or kicked in a public room
+#[instrument(skip_all)]
+pub async fn authorize_send(room: &str, user_id: i64) -> BasicResult<()> {
+    let (x, member) = access(room, user_id).await?;
+    match member {
+        Some(Member {
+            muted_until: Some(until),
+            ..
+        }) if until > chrono::Utc::now() => validate_error!(format!(
+            "you are muted in room: {} until {}",
+            room,
+            until.to_default()
+        ))
+        .into(),
+        Some(_) => Ok(()),
+        None if x.visibility == RoomVisibility::Public => Ok(()),
+        None => validate_error!(format!("you are not a member of room: {}", room)).into(),
+    }
+}
+
+/// the history of a public room is read by anyone who is not banned, the others by their members
+#[instrument(skip_all)]
+pub async fn authorize_read(room: &str, user_id: i64) -> BasicResult<()> {
+    let (x, member) = access(room, user_id).await?;
+    if x.visibility != RoomVisibility::Public && member.is_none() {
+        return validate_error!(format!("you are not a member of room: {}", room)).into();
+    }
+    Ok(())
+}
+
+/// remove the user from the room, it may join again after `chat.kick_cooldown`
+#[instrument(skip_all)]
+pub async fn kick(room: &str, by: i64, user_id: i64) -> BasicResult<()> {
+    moderate(room, by, user_id).await?;
+    let expires_at =
+        chrono::Utc::now() + chrono::Duration::seconds(config::cfg().chat.kick_cooldown);
+    pg_room_dao::kick(room, user_id, by, expires_at).await?;
+    Ok(())
+}
+
+/// remove the user from the room until it is unbanned
+#[instrument(skip_all)]
+pub async fn ban(room: &str, by: i64, user_id: i64) -> BasicResult<()> {
+    moderate(room, by, user_id).await?;
+    pg_room_dao::ban(room, user_id, by).await?;
+    Ok(())
+}
+
+#[instrument(skip_all)]
+pub async fn unban(room: &str, by: i64, user_id: i64) -> BasicResult<()> {
+    moderate(room, by, user_id).await?;
+    pg_room_dao::unban(room, user_id).await?;
+    Ok(())
+}
+
+/// mute the member for `seconds`, none unmutes it, returns until when it is muted
+#[instrument(skip_all)]
+pub async fn mute(
+    room: &str,
+    by: i64,
+    user_id: i64,
+    seconds: Option<i64>,
+) -> BasicResult<Option<String>> {
+    if seconds.is_some_and(|x| x <= 0) {
+        return validate_error!("seconds must be positive").into();
+    }
+    if moderate(room, by, user_id).await?.is_none() {
+        return validate_error!(format!(
+            "user: {} is not a member of room: {}",
+            user_id, room
+        ))
+        .into();
+    }
+    let until = seconds.map(|x| chrono::Utc::now() + chrono::Duration::seconds(x));
+    pg_room_dao::set_muted_until(room, user_id, until).await?;
+    Ok(until.map(|x| x.to_default()))
+}
+
+/// the invitations of the user, the latest first
+#[instrument(skip_all)]
+pub async fn invitations(user_id: i64) -> BasicResult<Vec<room_model::RoomInvitation>> {
+    let res = pg_room_dao::invitations(user_id).await?;
+    Ok(res.into_iter().map(|x| x.into()).collect())
+}
+
+/// make the user a member of the room it has been invited to
+#[instrument(skip_all)]
+pub async fn accept(room: &str, user_id: i64) -> BasicResult<()> {
+    if !pg_room_dao::accept_invitation(room, user_id).await? {
+        return validate_error!(format!("you have no invitation to room: {}", room)).into();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_id() {
+        assert!(validate_id("rust").is_ok());
+        assert!(validate_id("").is_err());
+        assert!(validate_id("rust lang").is_err());
+        assert!(validate_id(&"x".repeat(ROOM_ID_MAX_LENGTH + 1)).is_err());
+    }
+
+    #[test]
+    fn test_outranks() {
+        assert!(RoomRole::Owner.outranks(RoomRole::Moderator));
+        assert!(RoomRole::Moderator.outranks(RoomRole::Member));
+        assert!(!RoomRole::Moderator.outranks(RoomRole::Moderator));
+        assert!(!RoomRole::Member.outranks(RoomRole::Member));
+    }
+}
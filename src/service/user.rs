@@ -1,10 +1,16 @@
 use crate::{
+    config,
     dao::{
         meilisearch::{self as meilisearch_dao, user as meilisearch_user_dao},
         pg::user as pg_user_dao,
         redis::user as redis_user_dao,
     },
+    email::{self as mailer, template::Template},
     model::user as user_model,
+    service::{
+        login_history as login_history_service, mobile as mobile_service,
+        password as password_service,
+    },
 };
 
 use futures::TryFutureExt;
@@ -12,22 +18,43 @@ use rand::Rng;
 
 use serde::{Deserialize, Serialize};
 
-use util_error::{hint, unauthorized, BasicResult};
+use tracing::instrument;
+
+use util_error::{hint, unauthorized, validate_error, BasicResult};
 use util_response::Pagination;
 
 mod private {
     use base64::{engine::general_purpose, Engine as _};
 
     const EMAIL_VALIDATE_REGEX: &str = r#"\w[-\w.+]*@([A-Za-z0-9][-A-Za-z0-9]+\.)+[A-Za-z]{2,14}"#;
-    const PWD_VALIDATE_REGEX: &str = r#"(?=.*[a-z])(?=.*[0-9])[a-zA-Z0-9]{6,18}"#;
-    const MOBILE_VALIDATE_REGEX: &str = r#"0?(13|14|15|17|18|19)[0-9]{9}"#;
+    const LOCALE_VALIDATE_REGEX: &str = r#"^[a-z]{2,3}(-[A-Z][a-z]{3})?(-([A-Z]{2}|[0-9]{3}))?$"#;
+    const TIMEZONE_VALIDATE_REGEX: &str = r#"^(UTC|[A-Z][A-Za-z_]+(/[A-Za-z0-9_+-]+){1,2})$"#;
+    const PREFERENCES_MAX_SIZE: usize = 8 * 1024;
+
+    /// a validation rule of a profile field
+    enum Rule {
+        MaxLength(usize),
+        Regex(&'static str),
+    }
+
+    /// the validation rules of the profile fields, a field which is absent here is not validated
+    const PROFILE_RULES: &[(&str, &[Rule])] = &[
+        ("name", &[Rule::MaxLength(64)]),
+        ("bio", &[Rule::MaxLength(512)]),
+        ("locale", &[Rule::Regex(LOCALE_VALIDATE_REGEX)]),
+        (
+            "timezone",
+            &[Rule::MaxLength(64), Rule::Regex(TIMEZONE_VALIDATE_REGEX)],
+        ),
+    ];
 
     use std::cmp::Ordering;
 
     use super::{
-        meilisearch_dao, pg_user_dao, redis_user_dao, user_model, BasicResult, Deserialize,
-        Serialize,
+        meilisearch_dao, password_service, pg_user_dao, redis_user_dao, user_model, BasicResult,
+        Deserialize, Serialize,
     };
+    use crate::config;
     use chrono::{DateTime, TimeZone, Utc};
     use fancy_regex::Regex;
     use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
@@ -57,6 +84,14 @@ mod private {
                   // nbf: usize, // Optional. Not Before (as UTC timestamp)
     }
 
+    #[derive(Debug, Serialize, Deserialize)]
+    pub(super) struct ResetPwdClaims {
+        pub(super) aud: String, // email
+        exp: u64,
+        iat: u64,
+        pub(super) jti: String, // the id kept in redis until the token is used
+    }
+
     pub(super) fn hash_password(password: impl AsRef<str>, salt: impl AsRef<str>) -> String {
         let mut hasher = sha2::Sha512::new();
         hasher.update(password.as_ref());
@@ -100,27 +135,106 @@ mod private {
         }
     }
 
-    pub(super) fn validate_pwd(pwd: &str) -> BasicResult<()> {
-        match pwd.is_empty() {
-            true => Err(validate_error!("please type in passwd")),
-            _ => {
-                let reg = Regex::new(PWD_VALIDATE_REGEX)?; //6位字母+数字,字母开头
-                match reg.is_match(pwd)? {
-                    false => {
-                        validate_error!("invalid passowrd: length>=6, a-z and 0-9 is demanded")
-                            .into()
+    pub(super) async fn validate_pwd(
+        pwd: &str,
+        email: &str,
+        name: Option<&str>,
+        user: Option<&pg_user_dao::User>,
+    ) -> BasicResult<()> {
+        if pwd.is_empty() {
+            return validate_error!("please type in passwd").into();
+        }
+        let mut errors = password_service::check(pwd, email, name);
+        if let Some(user) = user {
+            if used_recently(user, pwd).await? {
+                errors.push(format!(
+                    "it should not be one of your last {} passwords",
+                    config::cfg().password.history
+                ));
+            }
+        }
+        match errors.is_empty() {
+            true => Ok(()),
+            _ => validate_error!(format!("invalid password: {}", errors.join("; "))).into(),
+        }
+    }
+
+    /// whether the password is the current one or one in the history of the user
+    async fn used_recently(user: &pg_user_dao::User, pwd: &str) -> BasicResult<bool> {
+        let limit = config::cfg().password.history;
+        if limit <= 0 {
+            return Ok(false);
+        }
+        if check_pwd(pwd, &user.salt, user.pwd.as_deref()).is_ok() {
+            return Ok(true);
+        }
+        // the history starts with the current password
+        let history = pg_user_dao::get_pwd_history(user.id, limit + 1).await?;
+        Ok(in_history(pwd, &history, limit))
+    }
+
+    /// whether the password is one of the `limit` previous ones in `history`, which is the latest
+    /// first and starts with the current password
+    pub(super) fn in_history(pwd: &str, history: &[pg_user_dao::PwdHistory], limit: i64) -> bool {
+        history
+            .iter()
+            .take(limit as usize + 1)
+            .any(|x| hash_password(pwd, &x.salt) == x.pwd)
+    }
+
+    pub(super) fn validate_profile_field(field: &str, value: &str) -> BasicResult<()> {
+        let rules = PROFILE_RULES
+            .iter()
+            .find(|(name, _)| *name == field)
+            .map(|(_, rules)| *rules)
+            .unwrap_or_default();
+        for rule in rules {
+            match rule {
+                Rule::MaxLength(max) => {
+                    if value.chars().count() > *max {
+                        return validate_error!(format!(
+                            "invalid {}: length<={} is demanded",
+                            field, max
+                        ))
+                        .into();
+                    }
+                }
+                Rule::Regex(pattern) => {
+                    let reg = Regex::new(pattern)?;
+                    if !reg.is_match(value)? {
+                        return validate_error!(format!("invalid {}", field)).into();
                     }
-                    _ => Ok(()),
                 }
             }
         }
+        Ok(())
     }
 
-    pub(super) fn validate_mobile(mobile: &str) -> BasicResult<()> {
-        let reg = Regex::new(MOBILE_VALIDATE_REGEX)?;
-        match reg.is_match(mobile)? {
-            false => validate_error!("invalid mobile").into(),
-            _ => Ok(()),
+    pub(super) fn validate_preferences(preferences: &serde_json::Value) -> BasicResult<()> {
+        if !preferences.is_object() {
+            return validate_error!("invalid preferences: a json object is demanded").into();
+        }
+        if preferences.to_string().len() > PREFERENCES_MAX_SIZE {
+            return validate_error!(format!(
+                "invalid preferences: size<={} bytes is demanded",
+                PREFERENCES_MAX_SIZE
+            ))
+            .into();
+        }
+        Ok(())
+    }
+
+    /// the reply without sending a change password code, when the email is not registered or a
+    /// code is pending, which is the same for both not to reveal whether the email has been
+    /// registered
+    pub(super) fn skip_change_pwd_code(
+        registered: bool,
+        pending: bool,
+        expired_seconds: u64,
+    ) -> Option<u64> {
+        match registered && !pending {
+            true => None,
+            _ => Some(expired_seconds),
         }
     }
 
@@ -146,6 +260,15 @@ mod private {
         }
     }
 
+    /// the user of the email if it has been registered and not deleted
+    pub(super) async fn find_available_user(
+        email: &str,
+    ) -> BasicResult<Option<pg_user_dao::User>> {
+        validate_email(email)?;
+        let res = pg_user_dao::find_by_email(email).await?;
+        Ok(res.filter(|x| x.deleted_at.is_none()))
+    }
+
     pub(super) async fn validate_not_exist_email(email: &str) -> BasicResult<()> {
         validate_email(email)?;
         match pg_user_dao::get_by_email(email).await {
@@ -166,6 +289,52 @@ mod private {
         Ok(claims.claims.aud)
     }
 
+    pub(super) fn sign_reset_pwd_token(
+        email: &str,
+        id: &str,
+        now: &DateTime<Utc>,
+    ) -> BasicResult<String> {
+        let config = config::cfg();
+        let cfg = &config.verification;
+        let iat = now.timestamp() as u64;
+        let claims = ResetPwdClaims {
+            aud: email.to_string(),
+            exp: iat + cfg.link_ttl,
+            iat,
+            jti: id.to_string(),
+        };
+        let token = jsonwebtoken::encode(
+            &Header::new(TOKEN.algorithm),
+            &claims,
+            &EncodingKey::from_secret(cfg.secret.as_ref()),
+        )?;
+        Ok(token)
+    }
+
+    pub(super) fn check_reset_pwd_token(token: &str) -> BasicResult<ResetPwdClaims> {
+        let validation = Validation::new(TOKEN.algorithm);
+        let claims = jsonwebtoken::decode::<ResetPwdClaims>(
+            token,
+            &DecodingKey::from_secret(config::cfg().verification.secret.as_ref()),
+            &validation,
+        )
+        .map_err(|_| validate_error!("the link is invalid or has expired"))?;
+        Ok(claims.claims)
+    }
+
+    pub(super) async fn update_pwd(user: &pg_user_dao::User, new_pwd: &str) -> BasicResult<u64> {
+        let salt = salt();
+        let pwd = hash_password(new_pwd, &salt);
+        // users created before the history have none, the password being replaced is kept first
+        if let Some(current) = &user.pwd {
+            let at = user.updated_at.unwrap_or(user.created_at);
+            pg_user_dao::seed_pwd_history(user.id, &user.salt, current, &at).await?;
+        }
+        let res = pg_user_dao::update_pwd(user.id, &salt, &pwd).await?;
+        pg_user_dao::insert_pwd_history(user.id, &salt, &pwd).await?;
+        Ok(res)
+    }
+
     pub(super) async fn set_current_user(
         user: &pg_user_dao::User,
         now: &DateTime<Utc>,
@@ -197,6 +366,12 @@ mod private {
                 status: user.status.clone(),
                 name: user.name.clone(),
                 mobile: user.mobile.clone(),
+                mobile_country: user.mobile_country.clone(),
+                avatar_url: user.avatar_url.clone(),
+                bio: user.bio.clone(),
+                locale: user.locale.clone(),
+                timezone: user.timezone.clone(),
+                preferences: user.preferences.clone(),
                 laston: user.laston.map(|x| x.to_default()),
                 created_at: user.created_at.to_default(),
                 updated_at: user.updated_at.map(|x| x.to_default()),
@@ -221,18 +396,25 @@ mod private {
     }
 }
 
+#[instrument(skip_all)]
 pub async fn search(
     key_word: &str,
     page: &Pagination,
 ) -> BasicResult<(Vec<user_model::SearchedUser>, usize)> {
-    meilisearch_user_dao::search(key_word, page).await
+    // mobiles are indexed in E.164, so is a key word which looks like a mobile
+    match mobile_service::search_term(key_word) {
+        Some(x) => meilisearch_user_dao::search(&x, page).await,
+        None => meilisearch_user_dao::search(key_word, page).await,
+    }
 }
 
+#[instrument(skip_all)]
 pub async fn get(id: i64) -> BasicResult<user_model::User> {
     let res = pg_user_dao::get(id).await?.into();
     Ok(res)
 }
 
+#[instrument(skip_all)]
 pub async fn get_all() -> BasicResult<Vec<user_model::User>> {
     let res = pg_user_dao::get_all()
         .await?
@@ -242,111 +424,274 @@ pub async fn get_all() -> BasicResult<Vec<user_model::User>> {
     Ok(res)
 }
 
+#[instrument(skip_all)]
 pub async fn register(
     email: &str,
     code: &str,
     pwd: &str,
     name: Option<&str>,
     mobile: Option<&str>,
+    mobile_country: Option<&str>,
 ) -> BasicResult<user_model::User> {
     private::validate_not_exist_email(email).await?;
-    private::validate_pwd(pwd)?;
+    private::validate_pwd(pwd, email, name, None).await?;
     private::validate_email_code(email, &user_model::SendEmailCodeFrom::Register, code).await?;
-    if let Some(x) = mobile {
-        private::validate_mobile(x)?;
-    }
+    let mobile = mobile
+        .map(|x| mobile_service::parse(x, mobile_country))
+        .transpose()?;
     let salt = private::salt();
     let pwd = private::hash_password(pwd, &salt);
 
-    let current_user = pg_user_dao::insert(email, &salt, &pwd, name, mobile).await?;
+    let current_user = pg_user_dao::insert(
+        email,
+        &salt,
+        &pwd,
+        name,
+        mobile.as_ref().map(|x| x.e164.as_str()),
+        mobile.as_ref().map(|x| x.country.as_str()),
+    )
+    .await?;
+    pg_user_dao::insert_pwd_history(current_user.id, &salt, &pwd).await?;
     private::update_search(current_user.clone()).await?;
     Ok(current_user.into())
 }
 
-pub async fn login(email: &str, pwd: &str) -> BasicResult<String> {
-    let mut user = private::validate_exist_email(email).await?;
+#[instrument(skip_all)]
+pub async fn login(
+    email: &str,
+    pwd: &str,
+    client: &user_model::LoginClient,
+) -> BasicResult<String> {
+    // a malformed email or a failing database is not a login attempt to record
+    private::validate_email(email)?;
+    let mut user = match pg_user_dao::find_by_email(email).await? {
+        Some(x) if x.deleted_at.is_none() => x,
+        Some(x) => {
+            login_history_service::record_failure(Some(x.id), email, client, "deleted email")
+                .await;
+            return validate_error!("email has already been deleted").into();
+        }
+        None => {
+            login_history_service::record_failure(None, email, client, "unknown email").await;
+            return validate_error!(format!("email: {} is not exist", email)).into();
+        }
+    };
+
+    if let Err(err) = private::check_pwd(pwd, &user.salt, user.pwd.as_deref()) {
+        login_history_service::record_failure(Some(user.id), email, client, "wrong password")
+            .await;
+        return Err(err);
+    }
 
-    private::check_pwd(pwd, &user.salt, user.pwd.as_deref())?;
     let now = chrono::Utc::now();
     user.laston = Some(now);
     let user_copy = user.clone();
 
-    let (_, _, set_current_user_result) = tokio::try_join!(
+    let res = tokio::try_join!(
         pg_user_dao::update_laston(user.id, &now).map_err(|err| err.into()),
         private::update_search(user_copy),
         private::set_current_user(&user, &now),
-    )?;
-
-    return Ok(set_current_user_result);
+    );
+    match res {
+        Ok((_, _, token)) => {
+            login_history_service::record_success(&user, client).await;
+            Ok(token)
+        }
+        Err(err) => {
+            login_history_service::record_failure(Some(user.id), email, client, "internal error")
+                .await;
+            Err(err)
+        }
+    }
 }
 
+#[instrument(skip_all)]
 pub async fn validate_not_exist_email(email: &str) -> BasicResult<()> {
     private::validate_not_exist_email(email).await
 }
 
+#[instrument(skip_all)]
 pub async fn validate_exist_email(email: &str) -> BasicResult<user_model::User> {
     Ok(private::validate_exist_email(email).await?.into())
 }
 
+#[instrument(skip_all)]
 pub async fn delete(ids: &[i64]) -> BasicResult<u64> {
     let (pg_del_res, _) = tokio::try_join!(
         pg_user_dao::delete(ids).map_err(|err| err.into()),
         meilisearch_dao::delete(meilisearch_dao::USER_LIST_INDEX, ids)
     )?;
+
+    // the users have been deleted whether they are notified or not
+    let notify = async {
+        let users = pg_user_dao::get_by_ids(ids).await?;
+        let receivers: Vec<_> = users
+            .iter()
+            .map(|x| {
+                let name = x.name.as_deref().unwrap_or(&x.email);
+                let vars = vec![("name", name), ("email", x.email.as_str())];
+                (x.email.as_str(), x.locale.as_deref(), vars)
+            })
+            .collect();
+        mailer::send_many(Template::AccountDeletion, &receivers).await
+    };
+    if let Err(err) = notify.await {
+        log::error!("notify deletion of users {:?} err: {:?}", ids, err);
+    }
     Ok(pg_del_res)
 }
 
+#[instrument(skip_all)]
 pub async fn change_pwd(email: &str, code: &str, new_pwd: &str) -> BasicResult<u64> {
-    let user = validate_exist_email(email).await?;
+    // check the code first, which does not reveal whether the email has been registered
     private::validate_email_code(email, &user_model::SendEmailCodeFrom::ChangePwd, code).await?;
-    private::validate_pwd(new_pwd)?;
+    let user = private::validate_exist_email(email).await?;
+    private::validate_pwd(new_pwd, &user.email, user.name.as_deref(), Some(&user)).await?;
+    private::update_pwd(&user, new_pwd).await
+}
 
-    let salt = private::salt();
-    let pwd = private::hash_password(new_pwd, &salt);
-    let res = pg_user_dao::update_pwd(user.id, &salt, &pwd).await?;
+/// send a single-use link to reset password
+///
+/// it succeeds whether the email has been registered or not, not to reveal it
+#[instrument(skip_all)]
+pub async fn send_reset_pwd_link(email: &str) -> BasicResult<()> {
+    let user = match private::find_available_user(email).await? {
+        Some(user) => user,
+        None => return Ok(()),
+    };
 
-    Ok(res)
+    let config = config::cfg();
+    let cfg = &config.verification;
+    let id = uuid::Uuid::new_v4().to_string();
+    let token = private::sign_reset_pwd_token(&user.email, &id, &chrono::Utc::now())?;
+    let link = cfg.reset_link.replace("{token}", &token);
+    let minutes = (cfg.link_ttl / 60).to_string();
+    let vars = [
+        ("name", user.name.as_deref().unwrap_or(&user.email)),
+        ("link", &link),
+        ("minutes", &minutes),
+    ];
+    let (cache_token_res, send_link_res) = tokio::join!(
+        redis_user_dao::set_reset_pwd_token(&user.email, &id, cfg.link_ttl),
+        mailer::send(
+            &user.email,
+            Template::ResetPwd,
+            user.locale.as_deref(),
+            &vars
+        )
+    );
+    let _ = cache_token_res?;
+    let _ = send_link_res?;
+    Ok(())
+}
+
+#[instrument(skip_all)]
+pub async fn reset_pwd(token: &str, new_pwd: &str) -> BasicResult<u64> {
+    let claims = private::check_reset_pwd_token(token)?;
+    let invalid = || validate_error!("the link is invalid or has been used");
+    if redis_user_dao::get_reset_pwd_token(&claims.aud).await? != Some(claims.jti.clone()) {
+        return Err(invalid());
+    }
+
+    let user = private::validate_exist_email(&claims.aud).await?;
+    private::validate_pwd(new_pwd, &user.email, user.name.as_deref(), Some(&user)).await?;
+
+    // the token is consumed only when the password is going to be changed
+    if redis_user_dao::take_reset_pwd_token(&claims.aud).await? != Some(claims.jti) {
+        return Err(invalid());
+    }
+    private::update_pwd(&user, new_pwd).await
 }
 
+/// `locale` is the locale of the email for a user to be registered, a registered user gets it in
+/// the locale of the profile
+#[instrument(skip_all)]
 pub async fn send_email_code(
     email: &str,
     from: &user_model::SendEmailCodeFrom,
+    locale: Option<&str>,
 ) -> BasicResult<u64> {
-    match from {
-        user_model::SendEmailCodeFrom::Register => validate_not_exist_email(email).await?,
+    let expired_seconds = config::cfg().verification.code_ttl;
+
+    let user = match from {
+        user_model::SendEmailCodeFrom::Register => {
+            validate_not_exist_email(email).await?;
+            if redis_user_dao::exist_email_code(email, from).await? {
+                return hint!("the validation code has already send to your mail box, please check or resend after a few minutes").into();
+            }
+            None
+        }
         user_model::SendEmailCodeFrom::ChangePwd => {
-            private::validate_exist_email(email).await?;
-            ()
+            let user = private::find_available_user(email).await?;
+            let pending = redis_user_dao::exist_email_code(email, from).await?;
+            if let Some(x) = private::skip_change_pwd_code(user.is_some(), pending, expired_seconds)
+            {
+                return Ok(x);
+            }
+            user
         }
-    }
-
-    if redis_user_dao::exist_email_code(email, from).await? {
-        return hint!("the validation code has already send to your mail box, please check or resend after a few minutes").into();
-    }
-
-    let code = rand::thread_rng().gen_range(100000..999999);
+    };
 
-    let expired_seconds = 120;
+    let code = rand::thread_rng().gen_range(100000..999999).to_string();
+    let minutes = (expired_seconds / 60).to_string();
+    let (template, name, locale) = match &user {
+        Some(user) => (
+            Template::ChangePwd,
+            user.name.as_deref().unwrap_or(email),
+            user.locale.as_deref(),
+        ),
+        None => (Template::Register, email, locale),
+    };
+    let vars = [("name", name), ("code", &code), ("minutes", &minutes)];
 
-    let body = format!("the validation code is: {}", code);
     let (cache_code_res, send_code_res) = tokio::join!(
-        redis_user_dao::set_email_code(email, from, code.to_string(), expired_seconds),
-        util_email::send(email, "validation code", &body)
+        redis_user_dao::set_email_code(email, from, code.clone(), expired_seconds),
+        mailer::send(email, template, locale, &vars)
     );
     let _ = cache_code_res?;
     let _ = send_code_res?;
     Ok(expired_seconds)
 }
 
+#[instrument(skip_all)]
 pub async fn update(
     id: i64,
-    mobile: Option<&str>,
-    name: Option<&str>,
+    mut req: user_model::UserUpdateReq,
 ) -> BasicResult<user_model::User> {
-    if let Some(mobile) = mobile {
-        private::validate_mobile(mobile)?;
+    let fields = [
+        ("name", &req.name),
+        ("bio", &req.bio),
+        ("locale", &req.locale),
+        ("timezone", &req.timezone),
+    ];
+    for (field, value) in fields {
+        if let Some(Some(value)) = value {
+            private::validate_profile_field(field, value)?;
+        }
+    }
+    if let Some(Some(preferences)) = &req.preferences {
+        private::validate_preferences(preferences)?;
     }
-    let res = pg_user_dao::update(id, name, mobile).await?;
+    // the mobile is stored in E.164, along with its country
+    if let Some(mobile) = &req.mobile {
+        let mobile = mobile
+            .as_deref()
+            .map(|x| mobile_service::parse(x, req.mobile_country.as_deref()))
+            .transpose()?;
+        req.mobile_country = mobile.as_ref().map(|x| x.country.clone());
+        req.mobile = Some(mobile.map(|x| x.e164));
+    }
+    let res = pg_user_dao::update(id, &req).await?;
+    if redis_user_dao::exist_current_user(&res.email).await? {
+        private::set_current_user(&res, &chrono::Utc::now()).await?;
+    }
+    private::update_search(res.clone()).await?;
+    Ok(res.into())
+}
+
+#[instrument(skip_all)]
+pub async fn update_avatar(id: i64, avatar_url: &str) -> BasicResult<user_model::User> {
+    let res = pg_user_dao::update_avatar(id, avatar_url).await?;
     if redis_user_dao::exist_current_user(&res.email).await? {
         private::set_current_user(&res, &chrono::Utc::now()).await?;
     }
@@ -358,6 +703,7 @@ pub fn check(token: &str) -> BasicResult<String> {
     private::check_token(token)
 }
 
+#[instrument(skip_all)]
 pub async fn get_current_user(email: &str) -> BasicResult<user_model::CurrentUser> {
     let res = redis_user_dao::get_current_user(email)
         .await
@@ -365,6 +711,7 @@ pub async fn get_current_user(email: &str) -> BasicResult<user_model::CurrentUse
     Ok(res)
 }
 
+#[instrument(skip_all)]
 pub async fn load_search() -> BasicResult<()> {
     let data = get_all().await?;
     let documents = data
@@ -375,3 +722,36 @@ pub async fn load_search() -> BasicResult<()> {
     meilisearch_dao::reload(meilisearch_dao::USER_LIST_INDEX, &documents, Some("id")).await?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_in_history() {
+        // the current password and 6 previous ones, the latest first
+        let history: Vec<pg_user_dao::PwdHistory> = (0..7)
+            .map(|i| {
+                let salt = private::salt();
+                pg_user_dao::PwdHistory {
+                    pwd: private::hash_password(format!("password{}", i), &salt),
+                    salt,
+                }
+            })
+            .collect();
+        assert!(private::in_history("password0", &history, 5));
+        assert!(private::in_history("password5", &history, 5));
+        assert!(!private::in_history("password6", &history, 5));
+        assert!(!private::in_history("password1", &history, 0));
+    }
+
+    #[test]
+    fn test_skip_change_pwd_code() {
+        // a repeated request gets the same reply whether the email has been registered or not
+        let unknown = private::skip_change_pwd_code(false, false, 300);
+        assert_eq!(Some(300), unknown);
+        assert_eq!(unknown, private::skip_change_pwd_code(true, true, 300));
+        assert_eq!(unknown, private::skip_change_pwd_code(false, true, 300));
+        assert_eq!(None, private::skip_change_pwd_code(true, false, 300));
+    }
+}
@@ -1,6 +1,9 @@
-use crate::model::user::CurrentUser;
+use crate::config;
+use crate::model::user::{CurrentUser, LoginClient, UserType};
 use crate::service::user as user_service;
-use actix_web::HttpRequest;
+use actix_web::error::InternalError;
+use actix_web::http::{header, StatusCode};
+use actix_web::{HttpRequest, ResponseError};
 use util_error::BasicResult;
 use util_error::unauthorized;
 
@@ -15,10 +18,54 @@ pub async fn get_current_user(req: &HttpRequest) -> BasicResult<CurrentUser> {
     Ok(res)
 }
 
+/// where the request comes from, the client may identify its device with the `device-id` header
+pub fn get_login_client(req: &HttpRequest) -> LoginClient {
+    let header = |name: &str| {
+        req.headers()
+            .get(name)
+            .and_then(|x| x.to_str().ok())
+            .map(|x| x.to_string())
+    };
+    let peer = req.peer_addr().map(|x| x.ip());
+    // the forwarded headers are set by the client unless a trusted proxy is in front
+    let trusted = peer.is_some_and(|x| {
+        config::cfg()
+            .trusted_proxies
+            .iter()
+            .any(|p| p.parse::<std::net::IpAddr>().is_ok_and(|p| p == x))
+    });
+    let ip = match trusted {
+        true => req
+            .connection_info()
+            .realip_remote_addr()
+            .map(|x| x.to_string()),
+        _ => peer.map(|x| x.to_string()),
+    };
+    LoginClient {
+        ip,
+        user_agent: header(header::USER_AGENT.as_str()),
+        device_id: header("device-id"),
+    }
+}
+
+/// the current user, who must be an admin, a user who is not is forbidden
+pub async fn get_current_admin(req: &HttpRequest) -> actix_web::Result<CurrentUser> {
+    let res = get_current_user(req).await?;
+    match res.r#type {
+        UserType::Admin | UserType::SuperAdmin => Ok(res),
+        UserType::Normal => {
+            let err = unauthorized!("admin is demanded");
+            let mut res = err.error_response();
+            *res.status_mut() = StatusCode::FORBIDDEN;
+            Err(InternalError::from_response(err, res).into())
+        }
+    }
+}
+
 pub async fn get_current_user_by_token(token: &str) -> BasicResult<CurrentUser> {
     #[cfg(feature = "test_ws")]
     {
-        use crate::model::user::{UserStatus, UserType};
+        use crate::model::user::UserStatus;
         use rand::Rng;
         use std::ops::Add;
         use util_datetime::FormatDateTime;
@@ -34,6 +81,12 @@ pub async fn get_current_user_by_token(token: &str) -> BasicResult<CurrentUser>
                     rand::thread_rng().gen_range(10000..99999)
                 )),
                 mobile: None,
+                mobile_country: None,
+                avatar_url: None,
+                bio: None,
+                locale: None,
+                timezone: None,
+                preferences: None,
                 laston: None,
                 created_at: now.to_default(),
                 updated_at: None,
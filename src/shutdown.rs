@@ -0,0 +1,111 @@
+use crate::config;
+use actix_web::dev::ServerHandle;
+use std::future::Future;
+use std::time::Duration;
+use tokio::signal::unix::{signal, SignalKind};
+
+/// wait for SIGTERM or SIGINT, the servers leave them to us so the order of the shutdown is ours
+pub async fn wait() {
+    let mut terminate = match signal(SignalKind::terminate()) {
+        Ok(v) => Some(v),
+        Err(err) => {
+            log::error!("listen to SIGTERM err: {}", err);
+            None
+        }
+    };
+    let terminated = async {
+        match terminate.as_mut() {
+            Some(v) => v.recv().await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::select! {
+        _ = terminated => log::info!("SIGTERM received"),
+        _ = tokio::signal::ctrl_c() => log::info!("SIGINT received"),
+    }
+}
+
+/// stop accepting connections, close the long lived sessions, e.g. the websocket ones which also
+/// clean up this node in the hub, then wait for the requests in flight
+///
+/// it gives up when it takes longer than `shutdown.timeout`
+pub async fn graceful<F>(servers: &[ServerHandle], close_sessions: F)
+where
+    F: Future<Output = ()>,
+{
+    let timeout = Duration::from_secs(config::cfg().shutdown.timeout);
+    drain(servers, close_sessions, timeout).await
+}
+
+async fn drain<F>(servers: &[ServerHandle], close_sessions: F, timeout: Duration)
+where
+    F: Future<Output = ()>,
+{
+    let drain = async {
+        for server in servers {
+            server.pause().await;
+        }
+        log::info!("stopped accepting connections");
+
+        close_sessions.await;
+
+        futures::future::join_all(servers.iter().map(|x| x.stop(true))).await;
+    };
+    if tokio::time::timeout(timeout, drain).await.is_err() {
+        log::warn!("shutdown is not done in {:?}, exit anyway", timeout);
+        futures::future::join_all(servers.iter().map(|x| x.stop(false))).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{rt::task::JoinHandle, App, HttpServer};
+
+    /// a running server and the task which ends when it has stopped
+    fn server() -> (ServerHandle, JoinHandle<()>) {
+        let server = HttpServer::new(App::new)
+            .workers(1)
+            .disable_signals()
+            .bind(("127.0.0.1", 0))
+            .unwrap()
+            .run();
+        let handle = server.handle();
+        let running = actix_web::rt::spawn(async move {
+            let _ = server.await;
+        });
+        (handle, running)
+    }
+
+    #[actix_web::test]
+    async fn test_drain() {
+        let (handle, running) = server();
+        let mut closed = false;
+        let close_sessions = async {
+            // the server is paused, not stopped, while the sessions are closed
+            assert!(!running.is_finished());
+            closed = true;
+        };
+        drain(&[handle], close_sessions, Duration::from_secs(5)).await;
+        assert!(closed);
+        assert!(tokio::time::timeout(Duration::from_secs(1), running)
+            .await
+            .is_ok());
+    }
+
+    #[actix_web::test]
+    async fn test_drain_timeout() {
+        let (handle, running) = server();
+        let start = std::time::Instant::now();
+        drain(
+            &[handle],
+            std::future::pending(),
+            Duration::from_millis(100),
+        )
+        .await;
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(tokio::time::timeout(Duration::from_secs(1), running)
+            .await
+            .is_ok());
+    }
+}
@@ -0,0 +1,141 @@
+use crate::config;
+use once_cell::sync::OnceCell;
+use opentelemetry::{global, trace::TracerProvider as _, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{
+    error::OTelSdkResult,
+    propagation::TraceContextPropagator,
+    trace::{Sampler, SdkTracerProvider, SpanData, SpanExporter},
+    Resource,
+};
+use std::time::Duration;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use util_error::{business_error, BasicResult};
+
+static PROVIDER: OnceCell<SdkTracerProvider> = OnceCell::new();
+
+/// print every span to the log, with its duration and attributes
+#[derive(Debug)]
+struct StdoutExporter;
+
+impl SpanExporter for StdoutExporter {
+    async fn export(&self, batch: Vec<SpanData>) -> OTelSdkResult {
+        for span in batch {
+            let elapsed = span
+                .end_time
+                .duration_since(span.start_time)
+                .unwrap_or_default();
+            let attributes: Vec<_> = span
+                .attributes
+                .iter()
+                .map(|KeyValue { key, value, .. }| format!("{}={}", key, value))
+                .collect();
+            log::info!(
+                "span {} trace_id={} span_id={} parent_id={} elapsed={:?} {}",
+                span.name,
+                span.span_context.trace_id(),
+                span.span_context.span_id(),
+                span.parent_span_id,
+                elapsed,
+                attributes.join(" ")
+            );
+        }
+        Ok(())
+    }
+}
+
+/// a trace started here is kept by `ratio`, a trace continued from a caller follows its decision
+fn sampler(ratio: f64) -> Sampler {
+    Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(ratio)))
+}
+
+/// the provider exporting to the exporter in config, none when tracing is off
+fn provider(cfg: &config::Config) -> BasicResult<Option<SdkTracerProvider>> {
+    let builder = SdkTracerProvider::builder()
+        .with_sampler(sampler(cfg.telemetry.sample_ratio))
+        .with_resource(Resource::builder().with_service_name(cfg.name.clone()).build());
+    let provider = match &cfg.telemetry.exporter {
+        config::TraceExporter::None => return Ok(None),
+        config::TraceExporter::Otlp { endpoint } => {
+            let exporter = opentelemetry_otlp::SpanExporter::builder()
+                .with_http()
+                .with_endpoint(endpoint)
+                .build()
+                .map_err(|err| business_error!(format!("init otlp exporter err: {}", err)))?;
+            builder.with_batch_exporter(exporter).build()
+        }
+        config::TraceExporter::Stdout => builder.with_simple_exporter(StdoutExporter).build(),
+    };
+    Ok(Some(provider))
+}
+
+/// record the `tracing` spans and export them as OpenTelemetry spans
+///
+/// the spans are only recorded here, the logs are still written by log4rs
+pub fn init(cfg: &config::Config) -> BasicResult<()> {
+    let Some(provider) = provider(cfg)? else {
+        return Ok(());
+    };
+
+    // continue the traces of the callers sending `traceparent`
+    global::set_text_map_propagator(TraceContextPropagator::new());
+    let tracer = provider.tracer(cfg.name.clone());
+    global::set_tracer_provider(provider.clone());
+    tracing_subscriber::registry()
+        .with(tracing_opentelemetry::layer().with_tracer(tracer))
+        .try_init()
+        .map_err(|err| business_error!(format!("init tracing err: {}", err)))?;
+    let _ = PROVIDER.set(provider);
+    log::info!("telemetry inited");
+    Ok(())
+}
+
+/// export the spans left before the process exits
+pub fn shutdown() {
+    if let Some(provider) = PROVIDER.get() {
+        if let Err(err) = provider.shutdown_with_timeout(Duration::from_secs(5)) {
+            log::error!("shutdown telemetry err: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::{SamplingDecision, SpanKind, TraceId};
+    use opentelemetry_sdk::trace::ShouldSample;
+
+    fn decision(sampler: &Sampler, trace_id: u128) -> SamplingDecision {
+        sampler
+            .should_sample(
+                None,
+                TraceId::from(trace_id),
+                "test",
+                &SpanKind::Server,
+                &[],
+                &[],
+            )
+            .decision
+    }
+
+    #[test]
+    fn test_sampler() {
+        let trace_id = 0x4bf92f3577b34da6a3ce929d0e0e4736;
+        assert_eq!(
+            SamplingDecision::RecordAndSample,
+            decision(&sampler(1.0), trace_id)
+        );
+        assert_eq!(SamplingDecision::Drop, decision(&sampler(0.0), trace_id));
+    }
+
+    #[test]
+    fn test_provider() {
+        let mut cfg = config::Config::default();
+        cfg.telemetry.exporter = config::TraceExporter::None;
+        assert!(provider(&cfg).unwrap().is_none());
+
+        cfg.telemetry.exporter = config::TraceExporter::Stdout;
+        let provider = provider(&cfg).unwrap().unwrap();
+        provider.shutdown().unwrap();
+    }
+}
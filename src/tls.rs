@@ -0,0 +1,150 @@
+use crate::config;
+use actix_web::{dev::Server, http::header, web, App, HttpRequest, HttpResponse, HttpServer};
+use arc_swap::ArcSwap;
+use openssl::ssl::{
+    select_next_proto, AlpnError, SniError, SslAcceptor, SslAcceptorBuilder, SslContext,
+    SslFiletype, SslMethod, SslVerifyMode,
+};
+use std::fs;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use util_error::{business_error, BasicResult};
+
+/// the protocols offered through ALPN, preferred first
+const ALPN: &[u8] = b"\x02h2\x08http/1.1";
+
+/// the certificate, the key, the client CAs and ALPN set up from config
+fn builder(cfg: &config::Tls) -> BasicResult<SslAcceptorBuilder> {
+    let mut builder = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls_server())
+        .map_err(|err| business_error!(format!("init tls err: {}", err)))?;
+    builder
+        .set_certificate_chain_file(&cfg.cert)
+        .map_err(|err| business_error!(format!("load tls.cert {} err: {}", cfg.cert, err)))?;
+    builder
+        .set_private_key_file(&cfg.key, SslFiletype::PEM)
+        .map_err(|err| business_error!(format!("load tls.key {} err: {}", cfg.key, err)))?;
+    builder
+        .check_private_key()
+        .map_err(|err| business_error!(format!("tls.key does not match tls.cert: {}", err)))?;
+    if !cfg.client_ca.is_empty() {
+        builder.set_ca_file(&cfg.client_ca).map_err(|err| {
+            business_error!(format!("load tls.client_ca {} err: {}", cfg.client_ca, err))
+        })?;
+        let mode = match cfg.client_cert_required {
+            true => SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT,
+            _ => SslVerifyMode::PEER,
+        };
+        builder.set_verify(mode);
+    }
+    builder.set_alpn_select_callback(|_, client| {
+        select_next_proto(ALPN, client).ok_or(AlpnError::NOACK)
+    });
+    Ok(builder)
+}
+
+/// the acceptor for `HttpServer::bind_openssl`, which negotiates http/2 through ALPN
+///
+/// the client certificates are checked against `client_ca` when it is set, the certificate and
+/// the key are reloaded in background when their files change
+pub fn acceptor(cfg: &config::Tls) -> BasicResult<SslAcceptorBuilder> {
+    let current = Arc::new(ArcSwap::from_pointee(builder(cfg)?.build().into_context()));
+
+    // called on every handshake, with or without SNI, the switched connection takes the
+    // certificate, the client CAs and ALPN from the latest context
+    let mut res = builder(cfg)?;
+    let ctx = current.clone();
+    res.set_servername_callback(move |ssl, _| {
+        ssl.set_ssl_context(&ctx.load())
+            .map_err(|_| SniError::ALERT_FATAL)
+    });
+
+    watch(cfg, current);
+    Ok(res)
+}
+
+fn modified_at(cfg: &config::Tls) -> Vec<Option<SystemTime>> {
+    [&cfg.cert, &cfg.key]
+        .into_iter()
+        .map(|x| fs::metadata(x).and_then(|x| x.modified()).ok())
+        .collect()
+}
+
+/// reload the certificate when its files change, e.g. after a renewal
+fn watch(cfg: &config::Tls, current: Arc<ArcSwap<SslContext>>) {
+    let cfg = cfg.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(cfg.reload_interval));
+        let mut modified = modified_at(&cfg);
+        loop {
+            interval.tick().await;
+            let now = modified_at(&cfg);
+            if now == modified {
+                continue;
+            }
+            // a renewal may write the cert and the key one by one, retry until they match
+            match builder(&cfg) {
+                Ok(v) => {
+                    current.store(Arc::new(v.build().into_context()));
+                    modified = now;
+                    log::info!("tls certificate reloaded");
+                }
+                Err(err) => log::error!(
+                    "reload tls certificate err, keep the current one: {:?}",
+                    err
+                ),
+            }
+        }
+    });
+}
+
+/// the https url of the request, `host` may carry the port of the plain listener
+fn location(host: &str, port: u16, path: &str) -> String {
+    let host = match host.strip_prefix('[') {
+        // ipv6, e.g. [::1]:8881
+        Some(v) => format!("[{}]", v.split(']').next().unwrap_or_default()),
+        _ => host.split(':').next().unwrap_or_default().to_string(),
+    };
+    match port {
+        443 => format!("https://{}{}", host, path),
+        _ => format!("https://{}:{}{}", host, port, path),
+    }
+}
+
+async fn redirect(req: HttpRequest, port: web::Data<u16>) -> HttpResponse {
+    let path = req.uri().path_and_query().map_or("/", |x| x.as_str());
+    let location = location(req.connection_info().host(), **port, path);
+    HttpResponse::PermanentRedirect()
+        .insert_header((header::LOCATION, location))
+        .finish()
+}
+
+/// the plain http listener on `port` which sends every request to https
+pub fn redirect_server(cfg: &config::Config) -> std::io::Result<Server> {
+    let port = cfg.tls.port;
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(web::Data::new(port))
+            .default_service(web::to(redirect))
+    })
+    .workers(1)
+    .disable_signals()
+    .shutdown_timeout(cfg.shutdown.timeout)
+    .bind((cfg.host.as_str(), cfg.port))?
+    .run();
+    Ok(server)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_location() {
+        assert_eq!(
+            "https://evolve.com:8443/api/ping?a=1",
+            location("evolve.com:8881", 8443, "/api/ping?a=1")
+        );
+        assert_eq!("https://evolve.com/", location("evolve.com", 443, "/"));
+        assert_eq!("https://[::1]:8443/", location("[::1]:8881", 8443, "/"));
+    }
+}
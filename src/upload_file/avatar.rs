@@ -0,0 +1,118 @@
+use crate::config;
+use crate::service::user as user_service;
+use crate::session;
+use actix_files::Files;
+use actix_multipart::Multipart;
+use actix_web::web::{self, Json};
+use actix_web::{HttpRequest, Responder, Result};
+use futures_util::TryStreamExt as _;
+use image::{imageops::FilterType, ImageFormat, ImageReader, Limits};
+use sha2::Digest;
+use std::io::Cursor;
+use std::path::Path;
+use util_error::{business_error, validate_error, BasicResult};
+use util_response::{data, prelude::*};
+
+/// where the stored avatars are served, see [`files`]
+const URL_PREFIX: &str = "/file/avatar";
+
+/// serve the stored avatars and their thumbnails
+///
+/// a thumbnail of an avatar `{hash}.{ext}` is available as `{hash}_{size}.png`
+pub fn files() -> Files {
+    Files::new("/avatar", &config::cfg().avatar.dir)
+}
+
+fn extension(format: ImageFormat) -> BasicResult<&'static str> {
+    match format {
+        ImageFormat::Png => Ok("png"),
+        ImageFormat::Jpeg => Ok("jpg"),
+        ImageFormat::Gif => Ok("gif"),
+        ImageFormat::WebP => Ok("webp"),
+        _ => validate_error!("invalid avatar: png, jpeg, gif or webp is demanded").into(),
+    }
+}
+
+/// validate the image, store it under the hash of its content and generate the thumbnails
+///
+/// returns the url of the stored avatar
+fn store(bytes: Vec<u8>) -> BasicResult<String> {
+    let format = image::guess_format(&bytes)
+        .map_err(|_| validate_error!("invalid avatar: unrecognized image type"))?;
+    let ext = extension(format)?;
+
+    let config = config::cfg();
+    let cfg = &config.avatar;
+
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(cfg.max_dimension);
+    limits.max_image_height = Some(cfg.max_dimension);
+    let mut reader = ImageReader::with_format(Cursor::new(&bytes), format);
+    reader.limits(limits);
+    let img = reader
+        .decode()
+        .map_err(|err| validate_error!(format!("invalid avatar: {}", err)))?;
+
+    let hash = format!("{:x}", sha2::Sha256::digest(&bytes));
+    let filename = format!("{hash}.{ext}");
+
+    let dir = Path::new(&cfg.dir);
+    std::fs::create_dir_all(dir)?;
+
+    let path = dir.join(&filename);
+    // same content, same name: nothing to do if it has already been stored
+    if !path.exists() {
+        let mut written = Vec::new();
+        let res = (|| -> BasicResult<()> {
+            for &size in cfg.thumbnail_sizes.iter() {
+                let thumbnail = dir.join(format!("{hash}_{size}.png"));
+                img.resize_to_fill(size, size, FilterType::Lanczos3)
+                    .save_with_format(&thumbnail, ImageFormat::Png)
+                    .map_err(|err| business_error!(format!("generate thumbnail err: {}", err)))?;
+                written.push(thumbnail);
+            }
+            // the avatar goes last, its existence tells the thumbnails are complete
+            std::fs::write(&path, &bytes)?;
+            Ok(())
+        })();
+        if res.is_err() {
+            // leave no orphan thumbnails or partial avatar behind
+            for x in written.iter().chain([&path]) {
+                let _ = std::fs::remove_file(x);
+            }
+        }
+        res?;
+    }
+
+    Ok(format!("{URL_PREFIX}/{filename}"))
+}
+
+pub async fn upload(req: HttpRequest, mut payload: Multipart) -> Result<impl Responder> {
+    let user = session::get_current_user(&req).await?;
+    let max_size = config::cfg().avatar.max_size;
+
+    // only the first field is taken as the avatar
+    let mut bytes = Vec::new();
+    if let Some(mut field) = payload.try_next().await? {
+        while let Some(chunk) = field.try_next().await? {
+            if bytes.len() + chunk.len() > max_size {
+                return Err(validate_error!(format!(
+                    "invalid avatar: size should not exceed {} bytes",
+                    max_size
+                ))
+                .into());
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+    }
+
+    if bytes.is_empty() {
+        return Err(validate_error!("please choose an avatar").into());
+    }
+
+    // decoding and resizing are blocking operations, use threadpool
+    let avatar_url = web::block(move || store(bytes)).await??;
+
+    let res = user_service::update_avatar(user.id, &avatar_url).await?;
+    Ok(Json(data!(res)))
+}
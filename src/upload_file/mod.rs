@@ -1,5 +1,7 @@
 #![cfg(feature = "upload_file")]
 
+pub mod avatar;
+
 use std::io::Write;
 
 use actix_multipart::Multipart;
@@ -13,8 +15,15 @@ macro_rules! serve_upload_file {
     ($app: expr) => {
         $app = $app.service(
             scope("/file")
+                .wrap(middleware::cors::cors(|x| &x.file))
                 .service(upload_file::upload_page)
-                .service(upload_file::upload),
+                .service(upload_file::upload)
+                .service(
+                    actix_web::web::resource("/avatar")
+                        .wrap(middleware::auth::Auth)
+                        .route(actix_web::web::post().to(upload_file::avatar::upload)),
+                )
+                .service(upload_file::avatar::files()),
         );
     };
 }
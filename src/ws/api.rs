@@ -1,11 +1,15 @@
 #![cfg(feature = "ws")]
 
+use crate::log_context;
+use crate::middleware::request_id;
 use crate::session as user_session;
+use crate::ws::protocol::Protocol;
 use crate::ws::server::ChatServerHandle;
 use crate::ws::session as ws_session;
 use actix_files::NamedFile;
 use actix_web::{
     get,
+    http::header::{self, HeaderValue},
     web::{self, Path},
 };
 use actix_web::{HttpRequest, Responder, Result};
@@ -23,18 +27,37 @@ async fn connect(
     stream: web::Payload,
     chat_server: web::Data<ChatServerHandle>,
 ) -> Result<impl Responder> {
-    let (res, session, msg_stream) = actix_ws::handle(&req, stream)?;
+    let (protocol, subprotocol) = Protocol::negotiate(&req);
+    let (mut res, session, msg_stream) = actix_ws::handle(&req, stream)?;
+    // the browser drops the connection when the subprotocol it asked for is not answered
+    if let Some(subprotocol) = subprotocol {
+        res.headers_mut().insert(
+            header::SEC_WEBSOCKET_PROTOCOL,
+            HeaderValue::from_static(subprotocol),
+        );
+    }
     let token = token.to_string();
     let user = user_session::get_current_user_by_token(&token).await?;
-    let id = user.email;
-    let name = user.name.unwrap_or(id.clone());
-    
-    spawn_local(ws_session::chat_ws(
-        id,
-        name,
-        (**chat_server).clone(),
-        session,
-        msg_stream,
+    // every connection is a session of its own, a user may have several at once
+    let id = uuid::Uuid::new_v4().simple().to_string();
+    let name = user.name.unwrap_or(user.email);
+
+    // the session outlives the request, it logs with its own connection id besides the request id
+    let fields = vec![
+        (request_id::FIELD, request_id::current().unwrap_or_default()),
+        ("conn_id", id.clone()),
+    ];
+    spawn_local(log_context::scope(
+        fields,
+        ws_session::chat_ws(
+            user.id,
+            id,
+            name,
+            (**chat_server).clone(),
+            protocol,
+            session,
+            msg_stream,
+        ),
     ));
 
     Ok(res)
@@ -1,4 +1,6 @@
+use crate::config;
 use crate::dao::redis::lua_script;
+use crate::metrics;
 use redis::aio::ConnectionLike;
 use redis::FromRedisValue;
 use serde::{Deserialize, Serialize};
@@ -7,6 +9,7 @@ use std::{collections::HashMap, sync::Arc};
 use tokio::sync::mpsc::{self, Sender, UnboundedReceiver, UnboundedSender};
 use tokio::sync::oneshot;
 use tokio::sync::Mutex;
+use tracing::instrument;
 use util_error::business_error;
 use util_error::BasicResult;
 use util_redis::derive::{from_redis, to_redis};
@@ -27,6 +30,23 @@ impl Default for RoomChangeType {
 
 const MESSAGE_CHANNEL: &str = "message";
 
+const DIRECT_CHANNEL: &str = "direct";
+
+fn room_channel(room: &str) -> String {
+    format!("{}_{}", room, MESSAGE_CHANNEL)
+}
+
+/// every node with a session of the user subscribes to it
+fn user_channel(user_id: i64) -> String {
+    format!("user_{}_{}", user_id, DIRECT_CHANNEL)
+}
+
+const NODES_KEY: &str = "ws_nodes";
+
+fn node_key(node: &str) -> String {
+    format!("ws_node_{}", node)
+}
+
 #[to_redis]
 #[from_redis]
 pub struct UpdateRooms(pub HashMap<String, HashMap<String, String>>);
@@ -55,6 +75,23 @@ pub struct MessageForHub {
     pub content: String,
 }
 
+/// a push to every session of the user `to`, except the session `skip` which has sent it
+#[from_redis]
+#[to_redis]
+#[derive(Debug)]
+pub struct DirectForHub {
+    pub to: i64,
+    pub skip: Option<String>,
+    pub content: String,
+}
+
+/// what the subscriptions of the node receive
+#[derive(Debug)]
+pub enum HubMessage {
+    Room(MessageForHub),
+    Direct(DirectForHub),
+}
+
 #[from_redis]
 #[to_redis]
 pub struct RetrieveRroomsReq {
@@ -76,49 +113,116 @@ impl RetrieveRroomsReq {
     }
 }
 
+/// a room membership of a session on a dead node, which has been removed
+#[derive(Debug, PartialEq)]
+pub struct ReapedSession {
+    pub room: String,
+    pub id: String,
+    pub name: String,
+}
+
+impl ReapedSession {
+    /// the script returns room, id and name of every membership one after another
+    fn from_flat(values: Vec<String>) -> Vec<Self> {
+        values
+            .chunks_exact(3)
+            .map(|x| Self {
+                room: x[0].clone(),
+                id: x[1].clone(),
+                name: x[2].clone(),
+            })
+            .collect()
+    }
+}
+
 pub trait Hub {
     async fn subscribe_room(&self, room: &str) -> BasicResult<()>;
     async fn unsubscribe_room(&self, room: &str) -> BasicResult<()>;
     async fn publish(&self, message: MessageForHub) -> BasicResult<()>;
+    async fn subscribe_user(&self, user_id: i64) -> BasicResult<()>;
+    async fn unsubscribe_user(&self, user_id: i64) -> BasicResult<()>;
+    async fn publish_direct(&self, message: DirectForHub) -> BasicResult<()>;
     async fn clean(&self, rooms: &HashMap<String, HashSet<String>>) -> BasicResult<()>;
     async fn change_rooms(&self, req: ChangeRoomReq) -> BasicResult<()>;
     async fn retrieve_rooms(&self, req: RetrieveRroomsReq) -> BasicResult<UpdateRooms>;
+    /// keep the node alive, true when it has been taken for dead and its sessions reaped
+    async fn heartbeat(&self) -> BasicResult<bool>;
+    async fn reap(&self) -> BasicResult<Vec<ReapedSession>>;
 }
 
 pub struct RedisHub {
-    message_tx: UnboundedSender<MessageForHub>,
+    /// this node, the owner of the sessions it adds
+    node: String,
+    message_tx: UnboundedSender<HubMessage>,
+    /// channel to the closing of its subscription
     channels: Arc<Mutex<HashMap<String, (Sender<()>, oneshot::Receiver<()>)>>>,
 }
 impl RedisHub {
-    pub fn new() -> (Self, UnboundedReceiver<MessageForHub>) {
+    pub fn new() -> (Self, UnboundedReceiver<HubMessage>) {
         let (msg_tx, msg_rx) = mpsc::unbounded_channel();
         (
             Self {
+                node: uuid::Uuid::new_v4().simple().to_string(),
                 message_tx: msg_tx,
                 channels: Default::default(),
             },
             msg_rx,
         )
     }
+
+    /// subscribe once to the channel, its payloads are passed to the server by `wrap`
+    async fn subscribe<T>(&self, channel: String, wrap: fn(T) -> HubMessage) -> BasicResult<()>
+    where
+        T: FromRedisValue + 'static,
+    {
+        let mut channels = self.channels.lock().await;
+        if !channels.contains_key(&channel) {
+            let message_tx = self.message_tx.clone();
+            let (close_tx, close_done_rx) = util_redis::subscribe(&channel, move |msg| {
+                let payload = msg.get_payload::<T>().unwrap();
+                metrics::HUB_MESSAGES_RECEIVED.inc();
+                message_tx.send(wrap(payload)).unwrap();
+            })
+            .await?;
+            channels.insert(channel, (close_tx, close_done_rx));
+        }
+        Ok(())
+    }
+
+    async fn unsubscribe(&self, channel: &str) -> BasicResult<()> {
+        let mut channels = self.channels.lock().await;
+        if let Some((close, close_done)) = channels.remove(channel) {
+            close.send(()).await.unwrap();
+            close_done.await.unwrap(); //waiting for close done
+        }
+        Ok(())
+    }
 }
 
 impl Hub for RedisHub {
+    #[instrument(skip_all, fields(db.system = "redis"))]
     async fn publish(&self, message: MessageForHub) -> BasicResult<()> {
-        let res = util_redis::publish(
-            format!("{}_{}", message.room.clone(), MESSAGE_CHANNEL),
-            message,
-        )
-        .await?;
+        let res = util_redis::publish(room_channel(&message.room), message).await?;
+        metrics::HUB_MESSAGES_PUBLISHED.inc();
+        Ok(res)
+    }
+
+    #[instrument(skip_all, fields(db.system = "redis"))]
+    async fn publish_direct(&self, message: DirectForHub) -> BasicResult<()> {
+        let res = util_redis::publish(user_channel(message.to), message).await?;
+        metrics::HUB_MESSAGES_PUBLISHED.inc();
         Ok(res)
     }
 
+    #[instrument(skip_all, fields(db.system = "redis"))]
     async fn change_rooms(&self, change: ChangeRoomReq) -> BasicResult<()> {
         // let input = serde_json::to_string(&change).unwrap();
         let mut cmd = redis::cmd("evalsha");
 
-        cmd.arg(lua_script::ROOMS_CHANGE.get().await.as_str()) //sha
+        cmd.arg(lua_script::rooms_change().await?) //sha
             .arg(0) //keys number
-            .arg(&change);
+            .arg(&change)
+            .arg(&self.node);
 
         let value = util_redis::conn().await?.req_packed_command(&cmd).await?;
 
@@ -131,10 +235,11 @@ impl Hub for RedisHub {
         Ok(())
     }
 
+    #[instrument(skip_all, fields(db.system = "redis"))]
     async fn retrieve_rooms(&self, req: RetrieveRroomsReq) -> BasicResult<UpdateRooms> {
         let mut cmd = redis::cmd("evalsha");
 
-        cmd.arg(lua_script::ROOMS_RETRIEVE.get().await.as_str()) //sha
+        cmd.arg(lua_script::rooms_retrieve().await?) //sha
             .arg(0) //keys number
             .arg(&req);
 
@@ -144,6 +249,7 @@ impl Hub for RedisHub {
         Ok(data)
     }
 
+    #[instrument(skip_all, fields(db.system = "redis"))]
     async fn clean(&self, rooms: &HashMap<String, HashSet<String>>) -> BasicResult<()> {
         for (room, sessions) in rooms.iter() {
             for id in sessions {
@@ -153,34 +259,101 @@ impl Hub for RedisHub {
                     name: None,
                     r#type: RoomChangeType::Del,
                 })
-                .await
-                .unwrap();
+                .await?;
             }
         }
+
+        // the node is gone, rather than dead until its ttl expires
+        let mut cmd = redis::cmd("del");
+        cmd.arg(node_key(&self.node));
+        let mut conn = util_redis::conn().await?;
+        conn.req_packed_command(&cmd).await?;
+        let mut cmd = redis::cmd("srem");
+        cmd.arg(NODES_KEY).arg(&self.node);
+        conn.req_packed_command(&cmd).await?;
         Ok(())
     }
 
+    #[instrument(skip_all, fields(db.system = "redis"))]
+    async fn heartbeat(&self) -> BasicResult<bool> {
+        let mut cmd = redis::cmd("set");
+        cmd.arg(node_key(&self.node))
+            .arg(chrono::Utc::now().timestamp())
+            .arg("ex")
+            .arg(config::cfg().presence.node_ttl);
+        let mut conn = util_redis::conn().await?;
+        conn.req_packed_command(&cmd).await?;
+        // the reaper takes the node out of the set along with its sessions, so it is added back only
+        // on the first heartbeat or after the ttl has lapsed and the node has been reaped
+        let mut cmd = redis::cmd("sadd");
+        cmd.arg(NODES_KEY).arg(&self.node);
+        let value = conn.req_packed_command(&cmd).await?;
+        let reaped = i64::from_redis_value(&value)? == 1;
+        // a redis restarted without persistence has lost the scripts
+        lua_script::ensure_loaded().await?;
+        Ok(reaped)
+    }
+
+    #[instrument(skip_all, fields(db.system = "redis"))]
+    async fn reap(&self) -> BasicResult<Vec<ReapedSession>> {
+        let mut cmd = redis::cmd("evalsha");
+
+        cmd.arg(lua_script::nodes_reap().await?) //sha
+            .arg(0) //keys number
+            .arg(&self.node);
+
+        let value = util_redis::conn().await?.req_packed_command(&cmd).await?;
+
+        let values = Vec::<String>::from_redis_value(&value)?;
+        Ok(ReapedSession::from_flat(values))
+    }
+
+    #[instrument(skip_all, fields(db.system = "redis"))]
     async fn subscribe_room(&self, room: &str) -> BasicResult<()> {
-        let mut channels = self.channels.lock().await;
-        if !channels.contains_key(room) {
-            let message_tx = self.message_tx.clone();
-            let (close_tx, close_done_rx) =
-                util_redis::subscribe(&format!("{}_{}", room, MESSAGE_CHANNEL), move |msg| {
-                    let payload = msg.get_payload::<MessageForHub>().unwrap();
-                    message_tx.send(payload).unwrap();
-                })
-                .await?;
-            channels.insert(room.to_string(), (close_tx, close_done_rx));
-        }
-        Ok(())
+        self.subscribe(room_channel(room), HubMessage::Room).await
     }
 
+    #[instrument(skip_all, fields(db.system = "redis"))]
     async fn unsubscribe_room(&self, room: &str) -> BasicResult<()> {
-        let mut channels = self.channels.lock().await;
-        if let Some((close, close_done)) = channels.remove(room) {
-            close.send(()).await.unwrap();
-            close_done.await.unwrap(); //waiting for close done
-        }
-        Ok(())
+        self.unsubscribe(&room_channel(room)).await
+    }
+
+    #[instrument(skip_all, fields(db.system = "redis"))]
+    async fn subscribe_user(&self, user_id: i64) -> BasicResult<()> {
+        self.subscribe(user_channel(user_id), HubMessage::Direct)
+            .await
+    }
+
+    #[instrument(skip_all, fields(db.system = "redis"))]
+    async fn unsubscribe_user(&self, user_id: i64) -> BasicResult<()> {
+        self.unsubscribe(&user_channel(user_id)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reaped_session_from_flat() {
+        let values = ["main", "a@x.com", "a", "rust", "a@x.com", "a"]
+            .iter()
+            .map(|x| x.to_string())
+            .collect();
+        assert_eq!(
+            ReapedSession::from_flat(values),
+            vec![
+                ReapedSession {
+                    room: "main".into(),
+                    id: "a@x.com".into(),
+                    name: "a".into(),
+                },
+                ReapedSession {
+                    room: "rust".into(),
+                    id: "a@x.com".into(),
+                    name: "a".into(),
+                },
+            ]
+        );
     }
 }
@@ -3,6 +3,7 @@
 pub mod api;
 pub mod session;
 pub mod hub;
+pub mod protocol;
 pub mod server;
 
 /// init websocket
@@ -32,6 +33,7 @@ macro_rules! serve_ws {
         let cmd_tx = $cmd_tx.clone();
         $app = $app.service(
             scope("/ws")
+                .wrap(middleware::cors::cors(|x| &x.ws))
                 .app_data(web::Data::new(cmd_tx))
                 .service(ws::api::index)
                 .service(ws::api::connect),
@@ -39,12 +41,3 @@ macro_rules! serve_ws {
         log::info!("ws is serving")
     };
 }
-
-// #[macro_export]
-// macro_rules! clean_ws {
-//     ($hub:expr,$rooms:expr) => {
-//         use ws::hub::Hub;
-//         $hub.clean($rooms).await?;
-//         log::info!("ws cleaned")
-//     };
-// }
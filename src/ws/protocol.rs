@@ -0,0 +1,639 @@
+//! the frames of the websocket protocol
+//!
+//! a client sends a [`ClientFrame`], its `id` comes back in the [`ServerMessage::Ack`] or the
+//! [`ServerMessage::Error`] for it, the server pushes [`ServerFrame`]s
+//!
+//! the legacy text protocol, with `/join` etc. and pushes like `message:{json}`, is kept for the
+//! clients which ask for the `chat.legacy` subprotocol
+
+use crate::model::chat as chat_model;
+use actix_web::{http::header, HttpRequest};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use utoipa::ToSchema;
+
+/// the version of the frames, a frame of another version is refused
+pub const VERSION: u8 = 1;
+
+/// the subprotocol of the json frames, it is used when the client asks for none
+pub const SUBPROTOCOL_JSON: &str = "chat.v1";
+
+/// the subprotocol of the legacy text protocol
+pub const SUBPROTOCOL_LEGACY: &str = "chat.legacy";
+
+const UPDATE_SESSION_PRE: &str = "update_session:";
+const LIST_PRE: &str = "list:";
+const JOIN_ROOM_PRE: &str = "join_room:";
+const QUIT_ROOM_PRE: &str = "quit_room:";
+const UPDATE_NAME_PRE: &str = "update_name:";
+const MESSAGE_PRE: &str = "message:";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Protocol {
+    Json,
+    Legacy,
+}
+
+impl Protocol {
+    /// the protocol asked for in `Sec-WebSocket-Protocol`, with the subprotocol to answer
+    pub fn negotiate(req: &HttpRequest) -> (Self, Option<&'static str>) {
+        let offered: Vec<&str> = req
+            .headers()
+            .get_all(header::SEC_WEBSOCKET_PROTOCOL)
+            .filter_map(|x| x.to_str().ok())
+            .flat_map(|x| x.split(','))
+            .map(|x| x.trim())
+            .collect();
+        if offered.contains(&SUBPROTOCOL_JSON) {
+            (Protocol::Json, Some(SUBPROTOCOL_JSON))
+        } else if offered.contains(&SUBPROTOCOL_LEGACY) {
+            (Protocol::Legacy, Some(SUBPROTOCOL_LEGACY))
+        } else {
+            (Protocol::Json, None)
+        }
+    }
+
+    /// the request id and the message of a frame from the client
+    pub fn parse(&self, text: &str) -> Result<(Option<String>, ClientMessage), ServerMessage> {
+        match self {
+            Protocol::Json => {
+                let frame: ClientFrame =
+                    serde_json::from_str(text).map_err(|err| ServerMessage::Error {
+                        id: None,
+                        code: ErrorCode::InvalidFrame,
+                        message: err.to_string(),
+                    })?;
+                if frame.v != VERSION {
+                    return Err(ServerMessage::Error {
+                        id: frame.id,
+                        code: ErrorCode::UnsupportedVersion,
+                        message: format!("version {} is not supported, use {}", frame.v, VERSION),
+                    });
+                }
+                Ok((frame.id, frame.body))
+            }
+            Protocol::Legacy => parse_legacy(text).map(|x| (None, x)),
+        }
+    }
+
+    /// the texts sent to the client, none when the protocol has no such frame
+    pub fn render(&self, msg: ServerMessage) -> Vec<String> {
+        match self {
+            Protocol::Json => vec![serde_json::to_string(&ServerFrame {
+                v: VERSION,
+                body: msg,
+            })
+            .unwrap()],
+            Protocol::Legacy => msg.legacy(),
+        }
+    }
+}
+
+/// a frame from the client, e.g. `{"v":1,"id":"7","body":{"type":"join","room":"rust"}}`
+#[derive(ToSchema, Serialize, Deserialize, Debug)]
+pub struct ClientFrame {
+    /// the version of the protocol, 1
+    pub v: u8,
+    /// answered with an ack or an error carrying the same id, none for no ack
+    pub id: Option<String>,
+    pub body: ClientMessage,
+}
+
+#[derive(ToSchema, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    /// the rooms of the session with their sessions
+    List,
+    Join {
+        room: String,
+    },
+    Quit {
+        room: String,
+    },
+    Name {
+        name: String,
+    },
+    /// a message to `room`, the current room when none, the session must have joined it
+    Message {
+        room: Option<String>,
+        content: String,
+    },
+    /// the messages of `room`, the current room when none, older than the message `before`
+    History {
+        room: Option<String>,
+        before: Option<i64>,
+        limit: Option<i64>,
+    },
+    /// a private message to the user `to`, it is kept until the user reads it
+    Direct {
+        to: i64,
+        content: String,
+    },
+    /// the direct messages with the user `with`, older than the message `before`
+    DirectHistory {
+        with: i64,
+        before: Option<i64>,
+        limit: Option<i64>,
+    },
+    /// the conversations of the user with their unread counts
+    Conversations,
+    /// the direct messages from the user `with` have been read
+    ReadDirect {
+        with: i64,
+    },
+    /// remove the member from the room, it may join again after `chat.kick_cooldown`
+    Kick {
+        room: String,
+        user_id: i64,
+    },
+    /// remove the member from the room until it is unbanned
+    Ban {
+        room: String,
+        user_id: i64,
+    },
+    Unban {
+        room: String,
+        user_id: i64,
+    },
+    /// mute the member for `seconds`, none unmutes it
+    Mute {
+        room: String,
+        user_id: i64,
+        seconds: Option<i64>,
+    },
+    /// the message of `room`, the current room when none, has reached the client
+    Delivered {
+        room: Option<String>,
+        message_id: i64,
+    },
+    /// the user has read `room`, the current room when none, up to the message
+    Read {
+        room: Option<String>,
+        message_id: i64,
+    },
+    /// the user is typing in `room`, the current room when none, it is dropped when sent too often
+    Typing {
+        room: Option<String>,
+    },
+    /// the unread counts of the rooms
+    Unread,
+}
+
+/// a frame pushed to the client
+#[derive(ToSchema, Serialize, Deserialize, Debug)]
+pub struct ServerFrame {
+    /// the version of the protocol, 1
+    pub v: u8,
+    pub body: ServerMessage,
+}
+
+#[derive(ToSchema, Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RemovedReason {
+    Kicked,
+    Banned,
+}
+
+#[derive(ToSchema, Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// the frame is not json or does not match the schema
+    InvalidFrame,
+    UnsupportedVersion,
+    /// a field is empty or out of range
+    InvalidArgument,
+    /// e.g. quitting the default room, or joining a room without an invitation
+    NotAllowed,
+    /// the server failed, the request may be sent again
+    Internal,
+}
+
+#[derive(ToSchema, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    /// the request with the id has been done
+    Ack { id: String },
+    /// the request with the id, or a frame without one, has failed
+    Error {
+        id: Option<String>,
+        code: ErrorCode,
+        message: String,
+    },
+    /// the current room and name of the session
+    Session { room: String, name: String },
+    /// room id to session id to session name
+    Rooms {
+        rooms: HashMap<String, HashMap<String, String>>,
+    },
+    Joined {
+        session_id: String,
+        name: String,
+        room: String,
+    },
+    Quit {
+        session_id: String,
+        name: String,
+        room: String,
+    },
+    NameChanged {
+        session_id: String,
+        name: String,
+        old_name: String,
+    },
+    Message {
+        id: String,
+        room: String,
+        from_id: String,
+        from_name: String,
+        content: String,
+        time: String,
+    },
+    /// a page of the history, the oldest message first, also sent on joining a room
+    History {
+        room: String,
+        messages: Vec<chat_model::ChatMessage>,
+        /// `before` of the older page, none when there are no more
+        next: Option<i64>,
+    },
+    /// a direct message to or from the user, pushed to every session of both users
+    Direct { message: chat_model::DirectMessage },
+    /// a page of the direct messages with the user `with`, the oldest message first
+    DirectHistory {
+        with: i64,
+        messages: Vec<chat_model::DirectMessage>,
+        /// `before` of the older page, none when there are no more
+        next: Option<i64>,
+    },
+    /// the conversations of the user, the latest first, also sent on connecting
+    Conversations {
+        conversations: Vec<chat_model::Conversation>,
+    },
+    /// a moderator has removed the user from the room, its sessions quit it
+    Removed { room: String, reason: RemovedReason },
+    /// a moderator has muted the user in the room until `until`, none when unmuted
+    Muted { room: String, until: Option<String> },
+    /// the message has reached a client of the user, it is sent to the author only
+    Delivered {
+        room: String,
+        message_id: i64,
+        user_id: i64,
+        name: String,
+    },
+    /// the user has read the room up to the message
+    Read {
+        room: String,
+        message_id: i64,
+        user_id: i64,
+        name: String,
+    },
+    /// the user is typing, nothing is sent when it stops
+    Typing {
+        room: String,
+        user_id: i64,
+        name: String,
+    },
+    /// the rooms of the user with their unread counts, also sent on connecting
+    Unread { rooms: Vec<chat_model::RoomUnread> },
+}
+
+#[derive(Serialize)]
+struct UpdateSession<'a> {
+    room: &'a str,
+    name: &'a str,
+}
+
+#[derive(Serialize)]
+struct RoomChange<'a> {
+    session_id: &'a str,
+    name: &'a str,
+    room: &'a str,
+}
+
+#[derive(Serialize)]
+struct UpdateName<'a> {
+    session_id: &'a str,
+    name: &'a str,
+    old_name: &'a str,
+}
+
+#[derive(Serialize)]
+struct MessageContent<'a> {
+    id: u128,
+    room: &'a str,
+    from_id: &'a str,
+    from_name: &'a str,
+    content: &'a str,
+    time: &'a str,
+}
+
+fn legacy_message(msg: &MessageContent) -> String {
+    format!("{MESSAGE_PRE}{}", serde_json::to_string(msg).unwrap())
+}
+
+impl ServerMessage {
+    /// the texts of the push in the legacy protocol, it has no acks and gets the history as messages
+    ///
+    /// the direct messages and the receipts are only in the json protocol
+    fn legacy(&self) -> Vec<String> {
+        let res = match self {
+            ServerMessage::Ack { .. }
+            | ServerMessage::Direct { .. }
+            | ServerMessage::DirectHistory { .. }
+            | ServerMessage::Conversations { .. }
+            | ServerMessage::Delivered { .. }
+            | ServerMessage::Read { .. }
+            | ServerMessage::Typing { .. }
+            | ServerMessage::Unread { .. } => return vec![],
+            ServerMessage::History { messages, .. } => {
+                return messages
+                    .iter()
+                    .map(|x| {
+                        legacy_message(&MessageContent {
+                            id: x.id as u128,
+                            room: &x.room,
+                            from_id: &x.from_id,
+                            from_name: &x.from_name,
+                            content: &x.content,
+                            time: &x.created_at,
+                        })
+                    })
+                    .collect()
+            }
+            ServerMessage::Error { message, .. } => format!("!!! {}", message),
+            ServerMessage::Removed { room, reason } => match reason {
+                RemovedReason::Kicked => format!("!!! you are kicked from room: {}", room),
+                RemovedReason::Banned => format!("!!! you are banned from room: {}", room),
+            },
+            ServerMessage::Muted { room, until } => match until {
+                Some(until) => format!("!!! you are muted in room: {} until {}", room, until),
+                None => format!("!!! you are unmuted in room: {}", room),
+            },
+            ServerMessage::Session { room, name } => format!(
+                "{UPDATE_SESSION_PRE}{}",
+                serde_json::to_string(&UpdateSession { room, name }).unwrap()
+            ),
+            ServerMessage::Rooms { rooms } => {
+                format!("{LIST_PRE}{}", serde_json::to_string(rooms).unwrap())
+            }
+            ServerMessage::Joined {
+                session_id,
+                name,
+                room,
+            } => format!(
+                "{JOIN_ROOM_PRE}{}",
+                serde_json::to_string(&RoomChange {
+                    session_id,
+                    name,
+                    room
+                })
+                .unwrap()
+            ),
+            ServerMessage::Quit {
+                session_id,
+                name,
+                room,
+            } => format!(
+                "{QUIT_ROOM_PRE}{}",
+                serde_json::to_string(&RoomChange {
+                    session_id,
+                    name,
+                    room
+                })
+                .unwrap()
+            ),
+            ServerMessage::NameChanged {
+                session_id,
+                name,
+                old_name,
+            } => format!(
+                "{UPDATE_NAME_PRE}{}",
+                serde_json::to_string(&UpdateName {
+                    session_id,
+                    name,
+                    old_name
+                })
+                .unwrap()
+            ),
+            ServerMessage::Message {
+                id,
+                room,
+                from_id,
+                from_name,
+                content,
+                time,
+            } => legacy_message(&MessageContent {
+                // the id of the history, or a random one when the message has not been saved
+                id: id
+                    .parse()
+                    .ok()
+                    .or_else(|| uuid::Uuid::parse_str(id).ok().map(|x| x.as_u128()))
+                    .unwrap_or_default(),
+                room,
+                from_id,
+                from_name,
+                content,
+                time,
+            }),
+        };
+        vec![res]
+    }
+
+    /// the json passed through the hub, every node renders it for the protocol of its sessions
+    pub fn to_hub(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+fn invalid_argument(message: impl Into<String>) -> ServerMessage {
+    ServerMessage::Error {
+        id: None,
+        code: ErrorCode::InvalidArgument,
+        message: message.into(),
+    }
+}
+
+/// `/list`, `/join <room>`, `/quit <room>`, `/name <name>`, anything else is a message
+fn parse_legacy(text: &str) -> Result<ClientMessage, ServerMessage> {
+    // strip leading and trailing whitespace (spaces, newlines, etc.)
+    let msg = text.trim();
+    if !msg.starts_with('/') {
+        return Ok(ClientMessage::Message {
+            room: None,
+            content: msg.to_string(),
+        });
+    }
+
+    let mut cmd_args = msg.splitn(2, ' ');
+    // unwrap: we have guaranteed non-zero string length already
+    match (cmd_args.next().unwrap(), cmd_args.next()) {
+        ("/list", _) => Ok(ClientMessage::List),
+        ("/join", Some(room)) => Ok(ClientMessage::Join {
+            room: room.to_string(),
+        }),
+        ("/quit", Some(room)) => Ok(ClientMessage::Quit {
+            room: room.to_string(),
+        }),
+        ("/join" | "/quit", None) => Err(invalid_argument("room name is required")),
+        ("/name", Some(name)) => Ok(ClientMessage::Name {
+            name: name.to_string(),
+        }),
+        ("/name", None) => Err(invalid_argument("name is required")),
+        _ => Err(ServerMessage::Error {
+            id: None,
+            code: ErrorCode::InvalidFrame,
+            message: format!("unknown command: {msg}"),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[test]
+    fn test_negotiate() {
+        let req = TestRequest::default()
+            .insert_header((header::SEC_WEBSOCKET_PROTOCOL, "chat.legacy"))
+            .to_http_request();
+        assert_eq!(
+            Protocol::negotiate(&req),
+            (Protocol::Legacy, Some(SUBPROTOCOL_LEGACY))
+        );
+        let req = TestRequest::default()
+            .insert_header((header::SEC_WEBSOCKET_PROTOCOL, "chat.legacy, chat.v1"))
+            .to_http_request();
+        assert_eq!(
+            Protocol::negotiate(&req),
+            (Protocol::Json, Some(SUBPROTOCOL_JSON))
+        );
+        assert_eq!(
+            Protocol::negotiate(&TestRequest::default().to_http_request()),
+            (Protocol::Json, None)
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        let (id, msg) = Protocol::Json
+            .parse(r#"{"v":1,"id":"7","body":{"type":"join","room":"rust"}}"#)
+            .unwrap();
+        assert_eq!(id.as_deref(), Some("7"));
+        assert_eq!(
+            msg,
+            ClientMessage::Join {
+                room: "rust".into()
+            }
+        );
+        assert_eq!(
+            Protocol::Json
+                .parse(r#"{"v":1,"id":null,"body":{"type":"direct","to":2,"content":"hi"}}"#)
+                .unwrap()
+                .1,
+            ClientMessage::Direct {
+                to: 2,
+                content: "hi".into()
+            }
+        );
+        assert_eq!(
+            Protocol::Json
+                .parse(r#"{"v":1,"body":{"type":"read","message_id":7}}"#)
+                .unwrap(),
+            (
+                None,
+                ClientMessage::Read {
+                    room: None,
+                    message_id: 7
+                }
+            )
+        );
+        assert!(matches!(
+            Protocol::Json.parse(r#"{"v":2,"id":"7","body":{"type":"list"}}"#),
+            Err(ServerMessage::Error {
+                code: ErrorCode::UnsupportedVersion,
+                ..
+            })
+        ));
+        assert!(matches!(
+            Protocol::Json.parse("/join rust"),
+            Err(ServerMessage::Error {
+                code: ErrorCode::InvalidFrame,
+                ..
+            })
+        ));
+
+        assert_eq!(
+            Protocol::Legacy.parse(" /join rust ").unwrap().1,
+            ClientMessage::Join {
+                room: "rust".into()
+            }
+        );
+        assert_eq!(
+            Protocol::Legacy.parse("hi").unwrap().1,
+            ClientMessage::Message {
+                room: None,
+                content: "hi".into()
+            }
+        );
+        assert_eq!(
+            Protocol::Legacy.render(Protocol::Legacy.parse("/name").unwrap_err()),
+            vec!["!!! name is required".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let msg = ServerMessage::Joined {
+            session_id: "a@x.com".into(),
+            name: "a".into(),
+            room: "rust".into(),
+        };
+        assert_eq!(
+            Protocol::Json.render(serde_json::from_str(&msg.to_hub()).unwrap()),
+            vec![
+                r#"{"v":1,"body":{"type":"joined","session_id":"a@x.com","name":"a","room":"rust"}}"#
+                    .to_string()
+            ]
+        );
+        assert_eq!(
+            Protocol::Legacy.render(msg),
+            vec![r#"join_room:{"session_id":"a@x.com","name":"a","room":"rust"}"#.to_string()]
+        );
+        assert!(Protocol::Legacy
+            .render(ServerMessage::Ack { id: "7".into() })
+            .is_empty());
+        assert!(Protocol::Legacy
+            .render(ServerMessage::Conversations {
+                conversations: vec![]
+            })
+            .is_empty());
+        assert_eq!(
+            Protocol::Legacy.render(ServerMessage::Removed {
+                room: "rust".into(),
+                reason: RemovedReason::Banned,
+            }),
+            vec!["!!! you are banned from room: rust".to_string()]
+        );
+
+        let history = ServerMessage::History {
+            room: "main".into(),
+            messages: vec![chat_model::ChatMessage {
+                id: 7,
+                room: "main".into(),
+                user_id: Some(1),
+                from_id: "a@x.com".into(),
+                from_name: "a".into(),
+                content: "hi".into(),
+                created_at: "2026-10-19 08:00:00".into(),
+            }],
+            next: None,
+        };
+        assert_eq!(
+            Protocol::Legacy.render(history),
+            vec![
+                r#"message:{"id":7,"room":"main","from_id":"a@x.com","from_name":"a","content":"hi","time":"2026-10-19 08:00:00"}"#
+                    .to_string()
+            ]
+        );
+    }
+}
@@ -1,10 +1,18 @@
 #![cfg(feature = "ws")]
-use crate::ws::hub::{self, ChangeRoomReq, MessageForHub, RetrieveRroomsReqType, RoomChangeType};
+use crate::metrics;
+use crate::config;
+use crate::service::chat as chat_service;
+use crate::ws::hub::{
+    self, ChangeRoomReq, DirectForHub, HubMessage, MessageForHub, ReapedSession,
+    RetrieveRroomsReqType, RoomChangeType,
+};
+use crate::ws::protocol::ServerMessage;
 use futures_util::future::{select, Either};
 use std::{
     collections::{HashMap, HashSet},
     io,
     sync::Arc,
+    time::Duration,
 };
 use tokio::{
     pin,
@@ -13,6 +21,7 @@ use tokio::{
         oneshot, Mutex,
     },
 };
+use tracing::Instrument;
 
 use super::hub::{RetrieveRroomsReq, UpdateRooms};
 use util_error::BasicResult;
@@ -34,6 +43,7 @@ pub enum Command {
     Connect {
         conn_tx: mpsc::UnboundedSender<Msg>,
         res_tx: oneshot::Sender<SessionID>,
+        user_id: i64,
         id: String,
         name: String,
     },
@@ -56,7 +66,7 @@ pub enum Command {
     Join {
         conn: SessionID,
         room: RoomID,
-        res_tx: oneshot::Sender<()>,
+        res_tx: oneshot::Sender<BasicResult<()>>,
     },
 
     Quit {
@@ -65,6 +75,12 @@ pub enum Command {
         res_tx: oneshot::Sender<()>,
     },
 
+    Joined {
+        conn: SessionID,
+        room: RoomID,
+        res_tx: oneshot::Sender<bool>,
+    },
+
     Name {
         name: String,
         conn: SessionID,
@@ -77,9 +93,43 @@ pub enum Command {
         msg: Msg,
         res_tx: oneshot::Sender<()>,
     },
+
+    Direct {
+        to: i64,
+        skip: Option<SessionID>,
+        msg: Msg,
+        res_tx: oneshot::Sender<()>,
+    },
+    Shutdown {
+        res_tx: oneshot::Sender<()>,
+    },
     Close {
         res_tx: oneshot::Sender<()>,
     },
+    Ping {
+        res_tx: oneshot::Sender<()>,
+    },
+}
+
+impl Command {
+    /// the name in the spans
+    fn name(&self) -> &'static str {
+        match self {
+            Command::Connect { .. } => "connect",
+            Command::Disconnect { .. } => "disconnect",
+            Command::GetRoomsBySessionID { .. } => "get_rooms_by_session_id",
+            Command::GetRoomsByRoomID { .. } => "get_rooms_by_room_id",
+            Command::Join { .. } => "join",
+            Command::Quit { .. } => "quit",
+            Command::Joined { .. } => "joined",
+            Command::Name { .. } => "name",
+            Command::Message { .. } => "message",
+            Command::Direct { .. } => "direct",
+            Command::Shutdown { .. } => "shutdown",
+            Command::Close { .. } => "close",
+            Command::Ping { .. } => "ping",
+        }
+    }
 }
 
 /// A multi-room chat server.
@@ -98,8 +148,17 @@ where
     /// Map of room name to participant IDs in that room.
     rooms: Arc<Mutex<HashMap<RoomID, HashSet<SessionID>>>>,
 
+    /// user id to the sessions of the user on this node, which get the direct messages
+    users: HashMap<i64, HashSet<SessionID>>,
+
+    /// the names of the sessions on this node, told to their rooms when the node leaves them
+    names: HashMap<SessionID, String>,
+
     /// hub
     hub: H,
+
+    /// set by [`shutdown`](Self::shutdown), no session is registered any more
+    closing: bool,
 }
 
 pub const DEFAULT_ROOM: &str = "main";
@@ -115,7 +174,10 @@ where
             Self {
                 sessions: HashMap::new(),
                 rooms: rooms.clone(),
+                users: HashMap::new(),
+                names: HashMap::new(),
                 hub: hub,
+                closing: false,
             },
             ChatServerHandle { cmd_tx },
             cmd_rx,
@@ -161,17 +223,48 @@ where
         }
     }
 
+    /// send a direct message to the sessions of the user on this node, except `skip`
+    fn send_direct(&self, to: i64, skip: Option<SessionID>, msg: String) {
+        let Some(sessions) = self.users.get(&to) else {
+            return;
+        };
+        for conn_id in sessions {
+            if skip.as_ref() == Some(conn_id) {
+                continue;
+            }
+            if let Some(tx) = self.sessions.get(conn_id) {
+                // the session may be gone already, it is removed on its disconnect
+                let _ = tx.send(msg.clone());
+            }
+        }
+    }
+
     /// Register new session and assign unique ID to this session
     async fn connect(
         &mut self,
         tx: mpsc::UnboundedSender<Msg>,
+        user_id: i64,
         id: String,
         name: String,
     ) -> BasicResult<SessionID> {
+        // the sender is dropped, so the session is closed right away
+        if self.closing {
+            return Ok(id);
+        }
         self.hub.subscribe_room(DEFAULT_ROOM).await?;
 
+        // the first session of the user on this node subscribes to the direct messages, which
+        // guests sharing one id have none of
+        if !chat_service::is_guest(user_id) {
+            if !self.users.contains_key(&user_id) {
+                self.hub.subscribe_user(user_id).await?;
+            }
+            self.users.entry(user_id).or_default().insert(id.clone());
+        }
+
         // register session with random connection IDF
         self.sessions.insert(id.clone(), tx);
+        self.names.insert(id.clone(), name.clone());
 
         // auto join session to main room
         self.rooms
@@ -197,6 +290,18 @@ where
         let mut res = Vec::new();
         // remove sender
         if self.sessions.remove(&conn_id).is_some() {
+            self.names.remove(&conn_id);
+            let mut gone = Vec::new();
+            for (user_id, sessions) in self.users.iter_mut() {
+                if sessions.remove(&conn_id) && sessions.is_empty() {
+                    gone.push(*user_id);
+                }
+            }
+            for user_id in gone {
+                self.users.remove(&user_id);
+                self.hub.unsubscribe_user(user_id).await?;
+            }
+
             let mut rooms = self.rooms.lock().await;
             // remove session from all rooms
             for (room, sessions) in rooms.iter_mut() {
@@ -252,7 +357,12 @@ where
     }
 
     /// Join room, send disconnect message to old room send join message to new room.
+    ///
+    /// the session has let the user in by the rules of the room, so the loop does no database io
     async fn join_room(&mut self, session_id: SessionID, room: String) -> BasicResult<()> {
+        if self.closing {
+            return Ok(());
+        }
         self.hub.subscribe_room(&room).await?;
 
         self.rooms
@@ -295,6 +405,9 @@ where
     }
 
     async fn change_name(&mut self, session_id: SessionID, name: String) {
+        if let Some(x) = self.names.get_mut(&session_id) {
+            *x = name.clone();
+        }
         self.hub
             .change_rooms(ChangeRoomReq {
                 id: session_id.clone(),
@@ -306,13 +419,229 @@ where
             .unwrap();
     }
 
+    /// close every session and remove what this node has in the hub
+    ///
+    /// dropping the senders ends the sessions, they close their clients with a going away reason,
+    /// the rooms on the other nodes are told here as the sessions find nothing to quit any more
+    async fn shutdown(&mut self) -> BasicResult<()> {
+        self.closing = true;
+        self.sessions.clear();
+        let names = std::mem::take(&mut self.names);
+        let rooms = std::mem::take(&mut *self.rooms.lock().await);
+        for (room, sessions) in rooms.iter() {
+            for id in sessions {
+                let content = ServerMessage::Quit {
+                    session_id: id.clone(),
+                    name: names.get(id).cloned().unwrap_or_default(),
+                    room: room.clone(),
+                }
+                .to_hub();
+                self.hub
+                    .publish(MessageForHub {
+                        room: room.clone(),
+                        id: id.clone(),
+                        content,
+                    })
+                    .await?;
+            }
+        }
+        self.hub.clean(&rooms).await?;
+        for room in rooms.keys() {
+            self.hub.unsubscribe_room(room).await?;
+        }
+        for user_id in std::mem::take(&mut self.users).into_keys() {
+            self.hub.unsubscribe_user(user_id).await?;
+        }
+        Ok(())
+    }
+
+    /// keep this node alive in the hub, remove the sessions of the dead nodes and tell their rooms
+    async fn heartbeat(&self) -> BasicResult<()> {
+        if self.closing {
+            return Ok(());
+        }
+        if self.hub.heartbeat().await? {
+            self.register_again().await?;
+        }
+        for ReapedSession { room, id, name } in self.hub.reap().await? {
+            log::info!("session {} of a dead node is removed from room {}", id, room);
+            let content = ServerMessage::Quit {
+                session_id: id.clone(),
+                name,
+                room: room.clone(),
+            }
+            .to_hub();
+            self.hub
+                .publish(MessageForHub { room, id, content })
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// add the sessions of this node back to the hub after they have been reaped as those of a dead
+    /// node, e.g. when redis has been out of reach longer than the ttl, and tell their rooms again
+    async fn register_again(&self) -> BasicResult<()> {
+        let rooms = self.rooms.lock().await;
+        for (room, sessions) in rooms.iter() {
+            for id in sessions {
+                let name = self.names.get(id).cloned().unwrap_or_default();
+                self.hub
+                    .change_rooms(ChangeRoomReq {
+                        id: id.clone(),
+                        name: Some(name.clone()),
+                        room: room.clone(),
+                        r#type: RoomChangeType::Add,
+                    })
+                    .await?;
+                let content = ServerMessage::Joined {
+                    session_id: id.clone(),
+                    name,
+                    room: room.clone(),
+                }
+                .to_hub();
+                self.hub
+                    .publish(MessageForHub {
+                        room: room.clone(),
+                        id: id.clone(),
+                        content,
+                    })
+                    .await?;
+            }
+        }
+        if rooms.values().any(|x| !x.is_empty()) {
+            log::warn!("the sessions of this node have been reaped, they are registered again");
+        }
+        Ok(())
+    }
+
+    /// the sessions and the rooms on this node
+    async fn update_metrics(&self) {
+        let rooms = self.rooms.lock().await;
+        metrics::WS_SESSIONS.set(self.sessions.len() as i64);
+        metrics::WS_ROOMS.set(rooms.values().filter(|x| !x.is_empty()).count() as i64);
+    }
+
+    /// process a command, returns true when the server is closed
+    async fn process(&mut self, cmd: Command) -> bool {
+        match cmd {
+            Command::Connect {
+                conn_tx,
+                res_tx,
+                user_id,
+                id,
+                name,
+            } => {
+                let _ = res_tx.send(self.connect(conn_tx, user_id, id, name).await.unwrap());
+            }
+
+            Command::Disconnect { session_id, res_tx } => {
+                let res = self.disconnect(session_id).await;
+
+                let _ = res_tx.send(res.unwrap());
+            }
+
+            Command::GetRoomsBySessionID { session_id, res_tx } => {
+                let _ = res_tx.send(self.get_rooms_by_session_id(session_id).await.unwrap());
+            }
+
+            Command::GetRoomsByRoomID { room_id, res_tx } => {
+                let _ = res_tx.send(self.get_rooms_by_room_id(room_id).await.unwrap());
+            }
+
+            Command::Join { conn, room, res_tx } => {
+                let _ = res_tx.send(self.join_room(conn, room).await);
+            }
+
+            Command::Quit { conn, room, res_tx } => {
+                let _ = self.quit_room(conn, room).await;
+                let _ = res_tx.send(());
+            }
+
+            Command::Joined { conn, room, res_tx } => {
+                let joined = self
+                    .rooms
+                    .lock()
+                    .await
+                    .get(&room)
+                    .is_some_and(|x| x.contains(&conn));
+                let _ = res_tx.send(joined);
+            }
+
+            Command::Name { conn, name, res_tx } => {
+                let _ = self.change_name(conn, name).await;
+                let _ = res_tx.send(());
+            }
+
+            Command::Message {
+                room,
+                id,
+                msg,
+                res_tx,
+            } => {
+                // self.send_message(room, skip, msg).await;
+                // let _ = res_tx.send(());
+                self.hub
+                    .publish(MessageForHub {
+                        room,
+                        id,
+                        content: msg,
+                    })
+                    .await
+                    .unwrap();
+
+                let _ = res_tx.send(());
+            }
+            Command::Direct {
+                to,
+                skip,
+                msg,
+                res_tx,
+            } => {
+                // the message is saved already, the recipient gets it on the next connect
+                if let Err(err) = self
+                    .hub
+                    .publish_direct(DirectForHub {
+                        to,
+                        skip,
+                        content: msg,
+                    })
+                    .await
+                {
+                    log::error!("publish direct message to {} err: {}", to, err);
+                }
+                let _ = res_tx.send(());
+            }
+            Command::Shutdown { res_tx } => {
+                if let Err(err) = self.shutdown().await {
+                    log::error!("shutdown ws server err: {}", err);
+                }
+                let _ = res_tx.send(());
+            }
+            Command::Close { res_tx } => {
+                // nothing is left when it has been shut down before
+                if let Err(err) = self.shutdown().await {
+                    log::error!("shutdown ws server err: {}", err);
+                }
+                let _ = res_tx.send(());
+                return true;
+            }
+            Command::Ping { res_tx } => {
+                let _ = res_tx.send(());
+            }
+        }
+        false
+    }
+
     pub async fn run(
         mut self,
-        mut hub_rx: UnboundedReceiver<MessageForHub>,
+        mut hub_rx: UnboundedReceiver<HubMessage>,
         mut cmd_rx: UnboundedReceiver<Command>,
     ) -> io::Result<()> {
         let hub_rx = &mut hub_rx;
         let cmd_rx = &mut cmd_rx;
+        let mut interval = tokio::time::interval(Duration::from_secs(
+            config::cfg().presence.heartbeat_interval,
+        ));
         'outer: loop {
             let cmd_rx = cmd_rx.recv();
             pin!(cmd_rx);
@@ -320,76 +649,41 @@ where
             let hub_rx = hub_rx.recv();
             pin!(hub_rx);
 
-            match select(cmd_rx, hub_rx).await {
-                Either::Left((Some(cmd), _)) => match cmd {
-                    Command::Connect {
-                        conn_tx,
-                        res_tx,
-                        id,
-                        name,
-                    } => {
-                        let _ = res_tx.send(self.connect(conn_tx, id, name).await.unwrap());
-                    }
-
-                    Command::Disconnect { session_id, res_tx } => {
-                        let res = self.disconnect(session_id).await;
+            let tick = interval.tick();
+            pin!(tick);
 
-                        let _ = res_tx.send(res.unwrap());
+            let received = match select(select(cmd_rx, hub_rx), tick).await {
+                Either::Left((received, _)) => received,
+                Either::Right(_) => {
+                    let span = tracing::info_span!("ws heartbeat");
+                    if let Err(err) = self.heartbeat().instrument(span).await {
+                        log::error!("ws heartbeat err: {}", err);
                     }
-
-                    Command::GetRoomsBySessionID { session_id, res_tx } => {
-                        let _ =
-                            res_tx.send(self.get_rooms_by_session_id(session_id).await.unwrap());
-                    }
-
-                    Command::GetRoomsByRoomID { room_id, res_tx } => {
-                        let _ = res_tx.send(self.get_rooms_by_room_id(room_id).await.unwrap());
-                    }
-
-                    Command::Join { conn, room, res_tx } => {
-                        let _ = self.join_room(conn, room).await;
-                        let _ = res_tx.send(());
-                    }
-
-                    Command::Quit { conn, room, res_tx } => {
-                        let _ = self.quit_room(conn, room).await;
-                        let _ = res_tx.send(());
-                    }
-
-                    Command::Name { conn, name, res_tx } => {
-                        let _ = self.change_name(conn, name).await;
-                        let _ = res_tx.send(());
-                    }
-
-                    Command::Message {
-                        room,
-                        id,
-                        msg,
-                        res_tx,
-                    } => {
-                        // self.send_message(room, skip, msg).await;
-                        // let _ = res_tx.send(());
-                        self.hub
-                            .publish(MessageForHub {
-                                room,
-                                id,
-                                content: msg,
-                            })
-                            .await
-                            .unwrap();
-
-                        let _ = res_tx.send(());
-                    }
-                    Command::Close { res_tx } => {
-                        let rooms = &*self.rooms.lock().await;
-                        self.hub.clean(rooms).await.unwrap();
-                        let _ = res_tx.send(());
+                    continue;
+                }
+            };
+            match received {
+                Either::Left((Some(cmd), _)) => {
+                    let span = tracing::info_span!(
+                        "ws command",
+                        otel.name = format!("ws {}", cmd.name()),
+                        ws.command = cmd.name(),
+                    );
+                    if self.process(cmd).instrument(span).await {
                         break 'outer;
                     }
-                },
+                    self.update_metrics().await;
+                }
                 Either::Right((Some(msg), _)) => match msg {
-                    MessageForHub { room, id, content } => {
-                        self.send_message(room, Some(id), content).await
+                    HubMessage::Room(MessageForHub { room, id, content }) => {
+                        let span = tracing::info_span!("ws hub message", ws.room = room.as_str());
+                        self.send_message(room, Some(id), content)
+                            .instrument(span)
+                            .await
+                    }
+                    HubMessage::Direct(DirectForHub { to, skip, content }) => {
+                        let _span = tracing::info_span!("ws hub direct", ws.user = to).entered();
+                        self.send_direct(to, skip, content)
                     }
                 },
                 _ => {
@@ -415,6 +709,7 @@ impl ChatServerHandle {
     pub async fn connect(
         &self,
         conn_tx: mpsc::UnboundedSender<String>,
+        user_id: i64,
         id: String,
         name: String,
     ) -> SessionID {
@@ -425,6 +720,7 @@ impl ChatServerHandle {
             .send(Command::Connect {
                 conn_tx,
                 res_tx,
+                user_id,
                 id,
                 name,
             })
@@ -466,8 +762,8 @@ impl ChatServerHandle {
         res_rx.await.unwrap()
     }
 
-    /// Join `room`, creating it if it does not exist.
-    pub async fn join_room(&self, conn: SessionID, room: impl Into<String>) {
+    /// Join `room`, the user must have been let in by [`crate::service::room::join`] before.
+    pub async fn join_room(&self, conn: SessionID, room: impl Into<String>) -> BasicResult<()> {
         let (res_tx, res_rx) = oneshot::channel();
 
         // unwrap: chat server should not have been dropped
@@ -480,7 +776,7 @@ impl ChatServerHandle {
             .unwrap();
 
         // unwrap: chat server does not drop our response channel
-        res_rx.await.unwrap();
+        res_rx.await.unwrap()
     }
 
     pub async fn quit_room(&self, conn: SessionID, room: impl Into<String>) {
@@ -499,6 +795,23 @@ impl ChatServerHandle {
         res_rx.await.unwrap();
     }
 
+    /// whether the session is in `room` on this node
+    pub async fn has_joined(&self, conn: SessionID, room: impl Into<String>) -> bool {
+        let (res_tx, res_rx) = oneshot::channel();
+
+        // unwrap: chat server should not have been dropped
+        self.cmd_tx
+            .send(Command::Joined {
+                conn,
+                room: room.into(),
+                res_tx,
+            })
+            .unwrap();
+
+        // unwrap: chat server does not drop our response channel
+        res_rx.await.unwrap()
+    }
+
     pub async fn change_name(&self, conn: SessionID, name: impl Into<String>) {
         let (res_tx, res_rx) = oneshot::channel();
 
@@ -533,6 +846,24 @@ impl ChatServerHandle {
         res_rx.await.unwrap();
     }
 
+    /// push a message to every session of the user `to` on any node, except `skip`
+    pub async fn send_direct(&self, to: i64, skip: Option<SessionID>, msg: impl Into<String>) {
+        let (res_tx, res_rx) = oneshot::channel();
+
+        // unwrap: chat server should not have been dropped
+        self.cmd_tx
+            .send(Command::Direct {
+                to,
+                skip,
+                msg: msg.into(),
+                res_tx,
+            })
+            .unwrap();
+
+        // unwrap: chat server does not drop our response channel
+        res_rx.await.unwrap();
+    }
+
     /// Unregister message sender and broadcast disconnection message to current room.
     pub async fn disconnect(&self, conn: SessionID) -> Vec<RoomID> {
         let (res_tx, res_rx) = oneshot::channel();
@@ -547,12 +878,42 @@ impl ChatServerHandle {
         res_rx.await.unwrap()
     }
 
+    /// whether the command loop is still running and answering
+    pub async fn ping(&self) -> bool {
+        let (res_tx, res_rx) = oneshot::channel();
+        if self.cmd_tx.send(Command::Ping { res_tx }).is_err() {
+            return false;
+        }
+        res_rx.await.is_ok()
+    }
+
+    /// close the sessions and clean the hub, the server keeps answering until it is closed
+    pub async fn shutdown(&self) {
+        let (res_tx, res_rx) = oneshot::channel();
+        // the loop may have stopped already, the shutdown goes on without it
+        if self.cmd_tx.send(Command::Shutdown { res_tx }).is_err() {
+            log::error!("ws server is not running, sessions are not closed");
+            return;
+        }
+        if res_rx.await.is_err() {
+            log::error!("ws server stopped before the sessions are closed");
+            return;
+        }
+        log::info!("ws sessions closed");
+    }
+
     // close server
     pub async fn close(&self) {
         let (res_tx, res_rx) = oneshot::channel();
-        // unwrap: chat server should not have been dropped
-        self.cmd_tx.send(Command::Close { res_tx }).unwrap();
-        res_rx.await.unwrap();
+        // the loop may have stopped already, there is nothing left to close
+        if self.cmd_tx.send(Command::Close { res_tx }).is_err() {
+            log::error!("ws server is not running, it can not be closed");
+            return;
+        }
+        if res_rx.await.is_err() {
+            log::error!("ws server stopped before it is closed");
+            return;
+        }
         log::info!("ws server stoped");
     }
 }
@@ -1,16 +1,21 @@
+use std::collections::HashMap;
 use std::time::{Duration, Instant};
 
-use actix_ws::Message;
+use actix_web::ResponseError;
+use actix_ws::{CloseCode, CloseReason, Message};
 use futures_util::{
     future::{select, Either},
     StreamExt as _,
 };
 use tokio::{pin, sync::mpsc};
 
-use serde::Serialize;
-
+use super::protocol::{ClientMessage, ErrorCode, Protocol, RemovedReason, ServerMessage};
+use crate::config;
+use crate::service::chat as chat_service;
+use crate::service::room as room_service;
 use super::server::{ChatServerHandle, SessionID, DEFAULT_ROOM};
 use util_datetime::FormatDateTime;
+use util_error::ErrorKind;
 
 /// How often heartbeat pings are sent
 const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
@@ -21,6 +26,7 @@ const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
 pub enum NotifyType<'a> {
     UpdateSession {
         session: &'a mut actix_ws::Session,
+        protocol: Protocol,
         name: &'a str,
         room: &'a str,
     },
@@ -28,18 +34,21 @@ pub enum NotifyType<'a> {
     List {
         chat_server: &'a ChatServerHandle,
         session: &'a mut actix_ws::Session,
+        protocol: Protocol,
         session_id: &'a str,
     },
 
     JoinRoom {
         chat_server: &'a ChatServerHandle,
         session: &'a mut actix_ws::Session,
+        protocol: Protocol,
         session_id: &'a str,
         name: &'a str,
         room: &'a str,
     },
     QuitRoom {
         session: &'a mut actix_ws::Session,
+        protocol: Protocol,
         chat_server: &'a ChatServerHandle,
         session_id: &'a str,
         name: &'a str,
@@ -53,6 +62,7 @@ pub enum NotifyType<'a> {
     },
     Message {
         chat_server: &'a ChatServerHandle,
+        user_id: i64,
         session_id: &'a str,
         name: &'a str,
         room: &'a str,
@@ -60,55 +70,154 @@ pub enum NotifyType<'a> {
     },
 }
 
-const UPDATE_SESSION_PRE: &str = "update_session:";
-const LIST_PRE: &str = "list:";
-const JOIN_ROOM_PRE: &str = "join_room:";
-const QUIT_ROOM_PRE: &str = "quit_room:";
-const UPDATE_NAME_PRE: &str = "update_name:";
-const MESSAGE_PRE: &str = "message:";
-
-#[derive(Serialize)]
-struct UpdateSession<'a> {
-    pub room: &'a str,
-    pub name: &'a str,
+/// push a message to the client in its protocol
+async fn send(session: &mut actix_ws::Session, protocol: Protocol, msg: ServerMessage) {
+    for text in protocol.render(msg) {
+        // the client may be gone, the session loop notices it on its next read
+        let _ = session.text(text).await;
+    }
 }
 
-#[derive(Serialize)]
-struct RoomChange<'a> {
-    pub session_id: &'a str,
-    pub name: &'a str,
-    pub room: &'a str,
+/// a push of the room, it comes through the hub as json and is rendered for this client
+///
+/// when a moderator has removed the user from a room, the session quits it
+async fn forward(
+    chat_server: &ChatServerHandle,
+    session: &mut actix_ws::Session,
+    protocol: Protocol,
+    session_id: &str,
+    name: &str,
+    room: &mut String,
+    msg: String,
+) {
+    let msg = match serde_json::from_str::<ServerMessage>(&msg) {
+        Ok(v) => v,
+        // a node which has not been upgraded yet publishes the legacy text, only a legacy client
+        // can read it
+        Err(_) => {
+            match protocol {
+                Protocol::Legacy => {
+                    let _ = session.text(msg).await;
+                }
+                Protocol::Json => log::debug!("legacy push is dropped for a json client: {}", msg),
+            }
+            return;
+        }
+    };
+    let removed = match &msg {
+        ServerMessage::Removed { room, .. } => Some(room.clone()),
+        _ => None,
+    };
+    send(session, protocol, msg).await;
+    if let Some(r) = removed {
+        quit(chat_server, session, protocol, session_id, name, room, r).await;
+    }
 }
 
-#[derive(Serialize)]
-struct UpdateName<'a> {
-    pub session_id: &'a str,
-    pub name: &'a str,
-    pub old_name: &'a str,
+/// leave the room `r`, the session is back in the default room
+async fn quit(
+    chat_server: &ChatServerHandle,
+    session: &mut actix_ws::Session,
+    protocol: Protocol,
+    session_id: &str,
+    name: &str,
+    room: &mut String,
+    r: String,
+) {
+    notify(NotifyType::QuitRoom {
+        session,
+        protocol,
+        chat_server,
+        session_id,
+        name,
+        room: &r,
+    })
+    .await;
+
+    chat_server.quit_room(session_id.to_string(), r).await;
+    *room = DEFAULT_ROOM.to_string();
+
+    notify(NotifyType::UpdateSession {
+        session,
+        protocol,
+        name,
+        room,
+    })
+    .await;
 }
 
-#[derive(Serialize)]
-pub struct MessageContent<'a> {
-    pub id: u128,
-    pub room: &'a str,
-    pub from_id: &'a str,
-    pub from_name: &'a str,
-    pub content: &'a str,
-    pub time: String,
+/// the rooms of the user, with their unread counts
+async fn unread(user_id: i64) -> Result<ServerMessage, (ErrorCode, String)> {
+    match chat_service::unread_rooms(user_id).await {
+        Ok(rooms) => Ok(ServerMessage::Unread { rooms }),
+        Err(err) => {
+            log::error!("get unread rooms of user {} err: {:?}", user_id, err);
+            Err((
+                ErrorCode::Internal,
+                "unread counts are not available".to_string(),
+            ))
+        }
+    }
 }
 
-impl<'a> MessageContent<'a> {
-    pub fn format(room: &'a str, from_id: &'a str, from_name: &'a str, content: &'a str) -> String {
-        let obj = Self {
-            id: uuid::Uuid::new_v4().as_u128(),
-            room,
-            from_id,
-            from_name,
-            content,
-            time: chrono::Utc::now().to_default(),
-        };
-        let str = serde_json::to_string(&obj).unwrap();
-        format!("{MESSAGE_PRE}{}", str)
+/// whether the typing event of the room is due, the ones within `interval` of the last sent one
+/// are dropped
+fn typing_due(
+    last: &HashMap<String, Instant>,
+    room: &str,
+    now: Instant,
+    interval: Duration,
+) -> bool {
+    !last
+        .get(room)
+        .is_some_and(|at| now.duration_since(*at) < interval)
+}
+
+/// a refusal of the service is told to the client with `code`, any other error is logged
+fn refused(code: ErrorCode, err: ErrorKind) -> (ErrorCode, String) {
+    // the errors of the server, e.g. of the database, are the ones answered with a 5xx over http
+    if err.status_code().is_server_error() {
+        log::error!("ws request err: {:?}", err);
+        return (ErrorCode::Internal, "internal error".to_string());
+    }
+    (code, err.to_string())
+}
+
+/// a refusal of the rules of the room
+fn not_allowed(err: ErrorKind) -> (ErrorCode, String) {
+    refused(ErrorCode::NotAllowed, err)
+}
+
+/// the latest messages of the room a session has joined
+async fn send_history_on_join(session: &mut actix_ws::Session, protocol: Protocol, room: &str) {
+    let limit = config::cfg().chat.history_on_join;
+    if limit == 0 {
+        return;
+    }
+    match chat_service::history(room, None, Some(limit)).await {
+        Ok(x) => {
+            let msg = ServerMessage::History {
+                room: room.to_string(),
+                messages: x.messages,
+                next: x.next,
+            };
+            send(session, protocol, msg).await;
+        }
+        Err(err) => log::error!("get history of room {} err: {:?}", room, err),
+    }
+}
+
+/// the conversations of the user, with their unread counts
+async fn conversations(user_id: i64) -> Result<ServerMessage, (ErrorCode, String)> {
+    match chat_service::conversations(user_id).await {
+        Ok(conversations) => Ok(ServerMessage::Conversations { conversations }),
+        Err(err) => {
+            log::error!("get conversations of user {} err: {:?}", user_id, err);
+            Err((
+                ErrorCode::Internal,
+                "conversations are not available".to_string(),
+            ))
+        }
     }
 }
 
@@ -116,104 +225,64 @@ async fn notify(ty: NotifyType<'_>) {
     match ty {
         NotifyType::UpdateSession {
             session,
+            protocol,
             name,
             room,
         } => {
-            session
-                .text(format!(
-                    "{UPDATE_SESSION_PRE}{}",
-                    serde_json::to_string(&UpdateSession { room, name }).unwrap()
-                ))
-                .await
-                .unwrap();
+            let msg = ServerMessage::Session {
+                room: room.to_string(),
+                name: name.to_string(),
+            };
+            send(session, protocol, msg).await;
         }
 
         NotifyType::List {
             chat_server,
             session,
+            protocol,
             session_id,
         } => {
             let rooms = chat_server.get_rooms_by_session_id(session_id).await;
-            session
-                .text(format!(
-                    "{LIST_PRE}{}",
-                    serde_json::to_string(&rooms).unwrap()
-                ))
-                .await
-                .unwrap();
+            send(session, protocol, ServerMessage::Rooms { rooms: rooms.0 }).await;
         }
 
         NotifyType::JoinRoom {
             chat_server,
             name,
             session,
+            protocol,
             session_id,
             room,
         } => {
+            let msg = ServerMessage::Joined {
+                session_id: session_id.to_string(),
+                name: name.to_string(),
+                room: room.to_string(),
+            };
             chat_server
-                .send_message(
-                    room.to_string(),
-                    session_id.to_string(),
-                    &format!(
-                        "{JOIN_ROOM_PRE}{}",
-                        serde_json::to_string(&RoomChange {
-                            session_id,
-                            room,
-                            name
-                        })
-                        .unwrap()
-                    ),
-                )
+                .send_message(room.to_string(), session_id.to_string(), msg.to_hub())
                 .await;
 
-            session
-                .text(format!(
-                    "{JOIN_ROOM_PRE}{}",
-                    serde_json::to_string(&RoomChange {
-                        session_id,
-                        room,
-                        name
-                    })
-                    .unwrap()
-                ))
-                .await
-                .unwrap();
+            send(session, protocol, msg).await;
         }
         NotifyType::QuitRoom {
             chat_server,
             session,
+            protocol,
             session_id,
             name,
             room,
         } => {
+            let msg = ServerMessage::Quit {
+                session_id: session_id.to_string(),
+                name: name.to_string(),
+                room: room.to_string(),
+            };
             chat_server
-                .send_message(
-                    room.to_string(),
-                    session_id.to_string(),
-                    &format!(
-                        "{QUIT_ROOM_PRE}{}",
-                        serde_json::to_string(&RoomChange {
-                            session_id,
-                            room,
-                            name
-                        })
-                        .unwrap()
-                    ),
-                )
+                .send_message(room.to_string(), session_id.to_string(), msg.to_hub())
                 .await;
 
-            session
-                .text(format!(
-                    "{QUIT_ROOM_PRE}{}",
-                    serde_json::to_string(&RoomChange {
-                        session_id,
-                        room,
-                        name
-                    })
-                    .unwrap()
-                ))
-                .await
-                .unwrap();
+            send(session, protocol, msg).await;
         }
         NotifyType::UpdateName {
             chat_server,
@@ -221,35 +290,54 @@ async fn notify(ty: NotifyType<'_>) {
             name,
             old_name,
         } => {
+            let msg = ServerMessage::NameChanged {
+                session_id: session_id.to_string(),
+                name: name.to_string(),
+                old_name: old_name.to_string(),
+            };
             chat_server
                 .send_message(
                     DEFAULT_ROOM.to_string(),
                     session_id.to_string(),
-                    &format!(
-                        "{UPDATE_NAME_PRE}{}",
-                        serde_json::to_string(&UpdateName {
-                            session_id,
-                            name,
-                            old_name
-                        })
-                        .unwrap()
-                    ),
+                    msg.to_hub(),
                 )
                 .await;
         }
         NotifyType::Message {
             chat_server,
+            user_id,
             session_id,
             name,
             room,
             msg,
         } => {
+            // the history never fails the message, it is only sent without a saved id, so is the
+            // message of a guest
+            let unsaved = || ServerMessage::Message {
+                id: uuid::Uuid::new_v4().simple().to_string(),
+                room: room.to_string(),
+                from_id: session_id.to_string(),
+                from_name: name.to_string(),
+                content: msg.to_string(),
+                time: chrono::Utc::now().to_default(),
+            };
+            let msg = match chat_service::save(room, user_id, session_id, name, msg).await {
+                Ok(Some(x)) => ServerMessage::Message {
+                    id: x.id.to_string(),
+                    room: x.room,
+                    from_id: x.from_id,
+                    from_name: x.from_name,
+                    content: x.content,
+                    time: x.created_at,
+                },
+                Ok(None) => unsaved(),
+                Err(err) => {
+                    log::error!("save message of {} err: {:?}", session_id, err);
+                    unsaved()
+                }
+            };
             chat_server
-                .send_message(
-                    room.to_string(),
-                    session_id.to_string(),
-                    MessageContent::format(room, session_id, name, msg),
-                )
+                .send_message(room.to_string(), session_id.to_string(), msg.to_hub())
                 .await
         }
     }
@@ -258,14 +346,18 @@ async fn notify(ty: NotifyType<'_>) {
 /// Echo text & binary messages received from the client, respond to ping messages, and monitor
 /// connection health to detect network issues and free up resources.
 pub async fn chat_ws(
+    user_id: i64,
     session_id: String,
     session_name: String,
     chat_server: ChatServerHandle,
+    protocol: Protocol,
     mut session: actix_ws::Session,
     mut msg_stream: actix_ws::MessageStream,
 ) {
     let mut room = DEFAULT_ROOM.to_string();
     let mut name = session_name.clone();
+    // room to when the last typing event of the session was sent
+    let mut typing = HashMap::new();
     let mut last_heartbeat = Instant::now();
     let mut interval = tokio::time::interval(HEARTBEAT_INTERVAL);
 
@@ -273,11 +365,12 @@ pub async fn chat_ws(
 
     // unwrap: chat server is not dropped before the HTTP server
     let conn_id = chat_server
-        .connect(conn_tx, session_id.clone(), session_name)
+        .connect(conn_tx, user_id, session_id.clone(), session_name)
         .await;
 
     notify(NotifyType::UpdateSession {
         session: &mut session,
+        protocol,
         name: &name,
         room: &room,
     })
@@ -287,6 +380,7 @@ pub async fn chat_ws(
         chat_server: &chat_server,
         session_id: &session_id,
         session: &mut session,
+        protocol,
     })
     .await;
 
@@ -294,11 +388,22 @@ pub async fn chat_ws(
         chat_server: &chat_server,
         session_id: &session_id,
         session: &mut session,
+        protocol,
         name: &name,
         room: &DEFAULT_ROOM,
     })
     .await;
 
+    send_history_on_join(&mut session, protocol, DEFAULT_ROOM).await;
+
+    // the direct messages received while the user was offline are in the unread counts
+    if let Ok(msg) = conversations(user_id).await {
+        send(&mut session, protocol, msg).await;
+    }
+    if let Ok(msg) = unread(user_id).await {
+        send(&mut session, protocol, msg).await;
+    }
+
     let close_reason = loop {
         // most of the futures we process need to be stack-pinned to work with select()
 
@@ -330,10 +435,13 @@ pub async fn chat_ws(
                         process_text_msg(
                             &chat_server,
                             &mut session,
+                            protocol,
                             &text,
+                            user_id,
                             session_id.clone(),
                             &mut name,
                             &mut room,
+                            &mut typing,
                         )
                         .await;
                     }
@@ -361,14 +469,24 @@ pub async fn chat_ws(
 
             // chat messages received from other room participants
             Either::Left((Either::Right((Some(chat_msg), _)), _)) => {
-                session.text(chat_msg).await.unwrap();
+                forward(
+                    &chat_server,
+                    &mut session,
+                    protocol,
+                    &session_id,
+                    &name,
+                    &mut room,
+                    chat_msg,
+                )
+                .await;
             }
 
-            // all connection's message senders were dropped
+            // the chat server dropped the sender, it is shutting down
             Either::Left((Either::Right((None, _)), _)) => {
-                unreachable!(
-                    "all connection message senders were dropped; chat server may have panicked"
-                )
+                break Some(CloseReason {
+                    code: CloseCode::Away,
+                    description: Some("server is shutting down".to_string()),
+                });
             }
 
             // heartbeat internal tick
@@ -393,6 +511,7 @@ pub async fn chat_ws(
     for room in rooms {
         notify(NotifyType::QuitRoom {
             session: &mut session,
+            protocol,
             chat_server: &chat_server,
             session_id: &session_id,
             name: &name,
@@ -407,136 +526,393 @@ pub async fn chat_ws(
 
 async fn process_text_msg(
     chat_server: &ChatServerHandle,
-    mut session: &mut actix_ws::Session,
+    session: &mut actix_ws::Session,
+    protocol: Protocol,
     text: &str,
+    user_id: i64,
     session_id: SessionID,
     name: &mut String,
     room: &mut String,
+    typing: &mut HashMap<String, Instant>,
 ) {
-    // strip leading and trailing whitespace (spaces, newlines, etc.)
-    let msg = text.trim();
-
-    // we check for /<cmd> type of messages
-    if msg.starts_with('/') {
-        let mut cmd_args = msg.splitn(2, ' ');
-
-        // unwrap: we have guaranteed non-zero string length already
-        match cmd_args.next().unwrap() {
-            "/list" => {
-                notify(NotifyType::List {
-                    chat_server: &chat_server,
-                    session_id: &session_id,
-                    session,
-                })
-                .await;
+    let (id, msg) = match protocol.parse(text) {
+        Ok(v) => v,
+        Err(err) => {
+            send(session, protocol, err).await;
+            return;
+        }
+    };
+
+    let res = process_client_msg(
+        chat_server,
+        session,
+        protocol,
+        msg,
+        user_id,
+        session_id,
+        name,
+        room,
+        typing,
+    )
+    .await;
+    match (res, id) {
+        (Ok(()), Some(id)) => send(session, protocol, ServerMessage::Ack { id }).await,
+        (Ok(()), None) => {}
+        (Err((code, message)), id) => {
+            send(session, protocol, ServerMessage::Error { id, code, message }).await
+        }
+    }
+}
+
+/// run the request of the client, the error is sent back with the request id
+async fn process_client_msg(
+    chat_server: &ChatServerHandle,
+    session: &mut actix_ws::Session,
+    protocol: Protocol,
+    msg: ClientMessage,
+    user_id: i64,
+    session_id: SessionID,
+    name: &mut String,
+    room: &mut String,
+    typing: &mut HashMap<String, Instant>,
+) -> Result<(), (ErrorCode, String)> {
+    match msg {
+        ClientMessage::List => {
+            notify(NotifyType::List {
+                chat_server: &chat_server,
+                session_id: &session_id,
+                session,
+                protocol,
+            })
+            .await;
+        }
+
+        ClientMessage::Join { room: r } => {
+            if r.trim().is_empty() {
+                return Err((ErrorCode::InvalidArgument, "room name is required".into()));
+            }
+            // the rules of the room are checked here, the chat server loop does no database io
+            room_service::join(&r, user_id)
+                .await
+                .map_err(not_allowed)?;
+            chat_server
+                .join_room(session_id.clone(), &r)
+                .await
+                .map_err(|err| {
+                    log::error!("join room {} err: {:?}", r, err);
+                    (ErrorCode::Internal, "room is not joined".to_string())
+                })?;
+            *room = r;
+
+            notify(NotifyType::UpdateSession {
+                session,
+                protocol,
+                name: &name,
+                room: &room,
+            })
+            .await;
+
+            notify(NotifyType::JoinRoom {
+                chat_server: &chat_server,
+                session_id: &session_id,
+                session,
+                protocol,
+                name: &name,
+                room: &room,
+            })
+            .await;
+
+            send_history_on_join(session, protocol, room).await;
+        }
+
+        ClientMessage::Quit { room: r } => {
+            if r.trim().is_empty() {
+                return Err((ErrorCode::InvalidArgument, "room name is required".into()));
+            }
+            if r == DEFAULT_ROOM {
+                return Err((
+                    ErrorCode::NotAllowed,
+                    format!("you can not quit default room: {}", r),
+                ));
             }
+            log::info!("session_id: {},room: {}", session_id, r);
 
-            "/join" => match cmd_args.next() {
-                Some(r) => {
-                    chat_server.join_room(session_id.clone(), r).await;
-                    *room = r.to_string();
-
-                    notify(NotifyType::UpdateSession {
-                        session: &mut session,
-                        name: &name,
-                        room: &r,
-                    })
-                    .await;
-
-                    notify(NotifyType::JoinRoom {
-                        chat_server: &chat_server,
-                        session_id: &session_id,
-                        session: &mut session,
-                        name: &name,
-                        room: &room,
-                    })
-                    .await;
-                }
+            quit(chat_server, session, protocol, &session_id, name, room, r).await;
+        }
 
-                None => {
-                    session.text("!!! room name is required").await.unwrap();
-                }
-            },
-
-            "/quit" => match cmd_args.next() {
-                Some(r) => {
-                    if r == DEFAULT_ROOM {
-                        session
-                            .text(&format!("!!! you can not quit default room: {}", r))
-                            .await
-                            .unwrap();
-                        return;
-                    }
-                    log::info!("session_id: {},room: {}", session_id, r);
-
-                    notify(NotifyType::QuitRoom {
-                        session: &mut session,
-                        chat_server: &chat_server,
-                        session_id: &session_id,
-                        name: &name,
-                        room: &r,
-                    })
-                    .await;
-
-                    chat_server.quit_room(session_id.clone(), r).await;
-                    *room = DEFAULT_ROOM.to_string();
-
-                    notify(NotifyType::UpdateSession {
-                        session: &mut session,
-                        name: &name,
-                        room: &room,
-                    })
-                    .await;
-                }
+        ClientMessage::Name { name: new_name } => {
+            if new_name.trim().is_empty() {
+                return Err((ErrorCode::InvalidArgument, "name is required".into()));
+            }
+            let old_name = std::mem::replace(name, new_name);
+            chat_server.change_name(session_id.clone(), name.as_str()).await;
+
+            notify(NotifyType::UpdateSession {
+                session,
+                protocol,
+                name: &name,
+                room: &room,
+            })
+            .await;
+
+            notify(NotifyType::UpdateName {
+                chat_server: &chat_server,
+                session_id: &session_id,
+                name: &name,
+                old_name: &old_name,
+            })
+            .await;
+        }
 
-                None => {
-                    session.text("!!! room name is required").await.unwrap();
-                }
-            },
-
-            "/name" => match cmd_args.next() {
-                Some(new_name) => {
-                    let old_name = name.clone();
-                    *name = new_name.to_string();
-                    chat_server
-                        .change_name(session_id.clone(), new_name)
-                        .await;
+        ClientMessage::Message { room: r, content } => {
+            let r = r.as_deref().unwrap_or(room.as_str());
+            if !chat_server.has_joined(session_id.clone(), r).await {
+                return Err((
+                    ErrorCode::NotAllowed,
+                    format!("join room {} before sending to it", r),
+                ));
+            }
+            room_service::authorize_send(r, user_id)
+                .await
+                .map_err(not_allowed)?;
+            notify(NotifyType::Message {
+                chat_server: &chat_server,
+                user_id,
+                session_id: &session_id,
+                name: &name,
+                room: r,
+                msg: &content,
+            })
+            .await;
+        }
 
-                    notify(NotifyType::UpdateSession {
-                        session: &mut session,
-                        name: &name,
-                        room: &room,
-                    })
-                    .await;
-
-                    notify(NotifyType::UpdateName {
-                        chat_server: &chat_server,
-                        session_id: &session_id,
-                        name: &name,
-                        old_name: &old_name,
-                    })
-                    .await;
-                }
-                None => {
-                    session.text("!!! name is required").await.unwrap();
-                }
-            },
+        ClientMessage::History {
+            room: r,
+            before,
+            limit,
+        } => {
+            let room = r.unwrap_or_else(|| room.clone());
+            room_service::authorize_read(&room, user_id)
+                .await
+                .map_err(not_allowed)?;
+            let res = chat_service::history(&room, before, limit)
+                .await
+                .map_err(|err| {
+                    log::error!("get history of room {} err: {:?}", room, err);
+                    (ErrorCode::Internal, "history is not available".to_string())
+                })?;
+            let msg = ServerMessage::History {
+                room,
+                messages: res.messages,
+                next: res.next,
+            };
+            send(session, protocol, msg).await;
+        }
+
+        ClientMessage::Direct { to, content } => {
+            let message = chat_service::send_direct(user_id, name, to, &content)
+                .await
+                .map_err(|err| refused(ErrorCode::InvalidArgument, err))?
+                .ok_or_else(|| {
+                    (
+                        ErrorCode::InvalidArgument,
+                        format!("user {} does not exist", to),
+                    )
+                })?;
+
+            // the recipient and the other sessions of the sender, on whichever node they are
+            let msg = ServerMessage::Direct { message };
+            chat_server.send_direct(to, None, msg.to_hub()).await;
+            chat_server
+                .send_direct(user_id, Some(session_id.clone()), msg.to_hub())
+                .await;
+            send(session, protocol, msg).await;
+        }
+
+        ClientMessage::DirectHistory {
+            with,
+            before,
+            limit,
+        } => {
+            let res = chat_service::direct_history(user_id, with, before, limit)
+                .await
+                .map_err(|err| {
+                    log::error!("get direct messages with {} err: {:?}", with, err);
+                    (ErrorCode::Internal, "history is not available".to_string())
+                })?;
+            let msg = ServerMessage::DirectHistory {
+                with,
+                messages: res.messages,
+                next: res.next,
+            };
+            send(session, protocol, msg).await;
+        }
+
+        ClientMessage::Conversations => {
+            send(session, protocol, conversations(user_id).await?).await;
+        }
+
+        ClientMessage::ReadDirect { with } => {
+            chat_service::mark_read(user_id, with)
+                .await
+                .map_err(|err| {
+                    log::error!("mark direct messages from {} read err: {:?}", with, err);
+                    (ErrorCode::Internal, "messages are not marked".to_string())
+                })?;
+        }
+
+        ClientMessage::Kick {
+            room: r,
+            user_id: u,
+        } => {
+            room_service::kick(&r, user_id, u)
+                .await
+                .map_err(not_allowed)?;
+            let msg = ServerMessage::Removed {
+                room: r,
+                reason: RemovedReason::Kicked,
+            };
+            chat_server.send_direct(u, None, msg.to_hub()).await;
+        }
 
-            _ => {
-                session
-                    .text(format!("!!! unknown command: {msg}"))
-                    .await
-                    .unwrap();
+        ClientMessage::Ban {
+            room: r,
+            user_id: u,
+        } => {
+            room_service::ban(&r, user_id, u)
+                .await
+                .map_err(not_allowed)?;
+            let msg = ServerMessage::Removed {
+                room: r,
+                reason: RemovedReason::Banned,
+            };
+            chat_server.send_direct(u, None, msg.to_hub()).await;
+        }
+
+        ClientMessage::Unban {
+            room: r,
+            user_id: u,
+        } => {
+            room_service::unban(&r, user_id, u)
+                .await
+                .map_err(not_allowed)?;
+        }
+
+        ClientMessage::Mute {
+            room: r,
+            user_id: u,
+            seconds,
+        } => {
+            let until = room_service::mute(&r, user_id, u, seconds)
+                .await
+                .map_err(not_allowed)?;
+            let msg = ServerMessage::Muted { room: r, until };
+            chat_server.send_direct(u, None, msg.to_hub()).await;
+        }
+
+        ClientMessage::Delivered {
+            room: r,
+            message_id,
+        } => {
+            let r = r.unwrap_or_else(|| room.clone());
+            room_service::authorize_read(&r, user_id)
+                .await
+                .map_err(not_allowed)?;
+            let author = chat_service::author(&r, message_id)
+                .await
+                .map_err(|err| refused(ErrorCode::InvalidArgument, err))?;
+            // only the author is told, the rest of the room has no use for it
+            if let Some(author) = author {
+                let msg = ServerMessage::Delivered {
+                    room: r,
+                    message_id,
+                    user_id,
+                    name: name.clone(),
+                };
+                chat_server.send_direct(author, None, msg.to_hub()).await;
             }
         }
-    } else {
-        notify(NotifyType::Message {
-            chat_server: &chat_server,
-            session_id: &session_id,
-            name: &name,
-            room: &room,
-            msg: &msg,
-        })
-        .await;
+
+        ClientMessage::Read {
+            room: r,
+            message_id,
+        } => {
+            let r = r.unwrap_or_else(|| room.clone());
+            room_service::authorize_read(&r, user_id)
+                .await
+                .map_err(not_allowed)?;
+            // the pointer never goes back, the room gets how far the user has read
+            let message_id = chat_service::read_room(&r, user_id, message_id)
+                .await
+                .map_err(|err| refused(ErrorCode::InvalidArgument, err))?;
+            let msg = ServerMessage::Read {
+                room: r.clone(),
+                message_id,
+                user_id,
+                name: name.clone(),
+            };
+            chat_server
+                .send_message(r, session_id.clone(), msg.to_hub())
+                .await;
+        }
+
+        ClientMessage::Typing { room: r } => {
+            let r = r.unwrap_or_else(|| room.clone());
+            let interval = Duration::from_millis(config::cfg().chat.typing_interval_ms);
+            let now = Instant::now();
+            if !typing_due(typing, &r, now, interval) {
+                return Ok(());
+            }
+            room_service::authorize_send(&r, user_id)
+                .await
+                .map_err(not_allowed)?;
+            // only the rooms the user may send to are kept, so the map is no bigger than them
+            typing.insert(r.clone(), now);
+            let msg = ServerMessage::Typing {
+                room: r.clone(),
+                user_id,
+                name: name.clone(),
+            };
+            chat_server
+                .send_message(r, session_id.clone(), msg.to_hub())
+                .await;
+        }
+
+        ClientMessage::Unread => {
+            send(session, protocol, unread(user_id).await?).await;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_typing_due() {
+        let mut last = HashMap::new();
+        let interval = Duration::from_secs(2);
+        let now = Instant::now();
+        assert!(typing_due(&last, "main", now, interval));
+        last.insert("main".to_string(), now);
+        assert!(!typing_due(
+            &last,
+            "main",
+            now + Duration::from_secs(1),
+            interval
+        ));
+        assert!(typing_due(
+            &last,
+            "rust",
+            now + Duration::from_secs(1),
+            interval
+        ));
+        assert!(typing_due(
+            &last,
+            "main",
+            now + Duration::from_secs(2),
+            interval
+        ));
     }
 }
@@ -0,0 +1,85 @@
+--[[
+Handler Object, for remove the sessions of the nodes which stopped the heartbeat
+--]]
+local Handler = {}
+
+function Handler:new()
+    local res = {}
+    setmetatable(res, {
+        __index = self
+    })
+    return res
+end
+
+function Handler:session_key(id)
+    return "ws_session_" .. id
+end
+
+function Handler:session_rooms_key(id)
+    return "ws_session_" .. id .. "_rooms"
+end
+
+function Handler:session_node_key(id)
+    return "ws_session_" .. id .. "_node"
+end
+
+function Handler:room_key(id)
+    return "ws_room_" .. id
+end
+
+function Handler:room_sessions_key(id)
+    return "ws_room_" .. id .. "_sessions"
+end
+
+function Handler:node_key(node)
+    return "ws_node_" .. node
+end
+
+function Handler:node_sessions_key(node)
+    return "ws_node_" .. node .. "_sessions"
+end
+
+function Handler:nodes_key()
+    return "ws_nodes"
+end
+
+function Handler:remove(room, id)
+    if redis.call("SREM", self:room_sessions_key(room), id) == 1 then
+        if redis.call("EXISTS", self:room_sessions_key(room)) == 0 then
+            redis.call("DEL", self:room_key(room))
+        end
+    end
+    redis.call("SREM", self:session_rooms_key(id), room)
+end
+
+-- returns room, session id and session name of every removed membership, one after another
+function Handler:reap(node, reaped)
+    for _, id in ipairs(redis.call("SMEMBERS", self:node_sessions_key(node))) do
+        -- the session may have connected to another node since
+        if redis.call("GET", self:session_node_key(id)) == node then
+            local name = redis.call("GET", self:session_key(id)) or "undefined"
+            for _, room in ipairs(redis.call("SMEMBERS", self:session_rooms_key(id))) do
+                self:remove(room, id)
+                table.insert(reaped, room)
+                table.insert(reaped, id)
+                table.insert(reaped, name)
+            end
+            redis.call("DEL", self:session_key(id), self:session_node_key(id))
+        end
+    end
+    redis.call("DEL", self:node_sessions_key(node))
+    redis.call("SREM", self:nodes_key(), node)
+end
+
+function Handler:handle()
+    local current = ARGV[1]
+    local reaped = {}
+    for _, node in ipairs(redis.call("SMEMBERS", self:nodes_key())) do
+        if node ~= current and redis.call("EXISTS", self:node_key(node)) == 0 then
+            self:reap(node, reaped)
+        end
+    end
+    return reaped
+end
+
+return Handler:new():handle()
@@ -19,6 +19,14 @@ function Handler:session_rooms_key(id)
     return "ws_session_" .. id .. "_rooms"
 end
 
+function Handler:session_node_key(id)
+    return "ws_session_" .. id .. "_node"
+end
+
+function Handler:node_sessions_key(node)
+    return "ws_node_" .. node .. "_sessions"
+end
+
 function Handler:room_key(id)
     return "ws_room_" .. id
 end
@@ -27,13 +35,16 @@ function Handler:room_sessions_key(id)
     return "ws_room_" .. id .. "_sessions"
 end
 
-function Handler:add(input)
+function Handler:add(input, node)
     local session_name = input.name or (redis.call("EXISTS", self:session_key(input.id)) == 1 and
         redis.call("GET", self:session_key(input.id))) or "undefined"
     redis.call("SET", self:session_key(input.id), session_name)
     redis.call("SADD", self:session_rooms_key(input.id), input.room)
     redis.call("SET", self:room_key(input.room), input.room)
     redis.call("SADD", self:room_sessions_key(input.room), input.id)
+    -- the node which owns the session, its sessions are removed when it stops the heartbeat
+    redis.call("SET", self:session_node_key(input.id), node)
+    redis.call("SADD", self:node_sessions_key(node), input.id)
 end
 
 function Handler:del(input)
@@ -45,6 +56,11 @@ function Handler:del(input)
     if redis.call("SREM", self:session_rooms_key(input.id), input.room) == 1 then
         if redis.call("EXISTS", self:session_rooms_key(input.id)) == 0 then
             redis.call("DEL", self:session_key(input.id))
+            local node = redis.call("GET", self:session_node_key(input.id))
+            if node then
+                redis.call("SREM", self:node_sessions_key(node), input.id)
+                redis.call("DEL", self:session_node_key(input.id))
+            end
         end
     end
 end
@@ -57,12 +73,13 @@ end
 
 function Handler:handle()
     local input = json.decode(ARGV[1]);
+    local node = ARGV[2];
     local output = {
         status = 0,
         msg = ""
     }
     if (input.type == "Add") then
-        self:add(input)
+        self:add(input, node)
     elseif (input.type == "Del") then
         self:del(input)
     elseif (input.type == "NameChange") then